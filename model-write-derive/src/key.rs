@@ -0,0 +1,46 @@
+use quote::quote;
+use syn::__private::TokenStream2;
+use syn::spanned::Spanned;
+use syn::{DeriveInput, Error, Lit, Meta, NestedMeta};
+
+/// `KeyStrategy` given by the `#[key(..)]` attribute, `Uuid` without one,
+/// together with the `slug_source` of a `slug = "field"` strategy
+pub(crate) fn strategy(input: &DeriveInput) -> Result<(TokenStream2, TokenStream2), Error> {
+    let attr = match input.attrs.iter().find(|a| a.path.is_ident("key")) {
+        Some(attr) => attr,
+        None => return Ok((quote!(crate::models::key::KeyStrategy::Uuid), quote!())),
+    };
+    let nested = match attr.parse_meta()? {
+        Meta::List(list) if list.nested.len() == 1 => list.nested,
+        meta => return Err(Error::new(meta.span(), "expected #[key(strategy)]")),
+    };
+
+    let strategy = match &nested[0] {
+        NestedMeta::Meta(Meta::Path(p)) if p.is_ident("uuid") => quote!(Uuid),
+        NestedMeta::Meta(Meta::Path(p)) if p.is_ident("ulid") => quote!(Ulid),
+        NestedMeta::Meta(Meta::Path(p)) if p.is_ident("snowflake") => quote!(Snowflake),
+        NestedMeta::Meta(Meta::Path(p)) if p.is_ident("server") => quote!(Server),
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("slug") => {
+            let field = match &nv.lit {
+                Lit::Str(s) => s.parse::<syn::Ident>()?,
+                lit => return Err(Error::new(lit.span(), "expected the name of a field")),
+            };
+            return Ok((
+                quote!(crate::models::key::KeyStrategy::Slug),
+                quote! {
+                    fn slug_source(&self) -> Option<&str> {
+                        crate::models::validate::Text::text(&self.#field)
+                    }
+                },
+            ));
+        }
+        meta => {
+            return Err(Error::new(
+                meta.span(),
+                "expected uuid, ulid, snowflake, server or slug = \"field\"",
+            ))
+        }
+    };
+
+    Ok((quote!(crate::models::key::KeyStrategy::#strategy), quote!()))
+}
@@ -7,6 +7,8 @@ use syn::__private::TokenStream2;
 use syn::{parse_macro_input, Data, DeriveInput, Ident, Type};
 
 pub(crate) mod constructor;
+pub(crate) mod key;
+pub(crate) mod validate;
 
 fn getter<T: Any>(field: &Ident) -> TokenStream2 {
     let get_ident = quote::format_ident!("get_{}", field);
@@ -139,10 +141,14 @@ fn parse_arguments(attr: TokenStream) -> Option<HashSet<String>> {
     Some(arr)
 }
 
-#[proc_macro_derive(ModelTrait)]
+#[proc_macro_derive(ModelTrait, attributes(key))]
 pub fn add_required_trait(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let sig = parse_macro_input!(input as DeriveInput);
     let doc_name = sig.ident.to_string().to_ascii_lowercase();
+    let (strategy, slug_source) = match key::strategy(&sig) {
+        Ok(strategy) => strategy,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let name = sig.ident;
 
     let expand = quote! {
@@ -157,15 +163,37 @@ pub fn add_required_trait(input: proc_macro::TokenStream) -> proc_macro::TokenSt
 
         fn collection_name<'a>() -> &'a str { #doc_name }
 
-        fn key(&self) -> String { self.key.to_string()}
+        fn key(&self) -> crate::models::id::DocKey<Self> { self.key.clone() }
+
+        fn id(&self) -> crate::models::id::DocId<Self> { self.key.id() }
+
+    }
+
+        impl crate::models::key::Keyed for #name {
+            const KEY_STRATEGY: crate::models::key::KeyStrategy = #strategy;
 
-        fn id(&self) -> String {format!("{}/{}", Self::collection_name(), self.key())}
+            #slug_source
 
-    }};
+            fn set_key(&mut self, key: String) {
+                self.key = crate::models::id::DocKey::valid(key);
+                self.id = self.key.id();
+            }
+        }
+    };
 
     proc_macro::TokenStream::from(expand)
 }
 
+#[proc_macro_derive(Validate, attributes(validate))]
+pub fn derive_validate(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let sig = parse_macro_input!(input as DeriveInput);
+
+    match validate::expand(&sig) {
+        Ok(expand) => expand.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
 #[proc_macro_derive(WriteToArango)]
 pub fn basic_arangodb_write(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let sig = parse_macro_input!(input as DeriveInput);
@@ -200,31 +228,15 @@ pub fn basic_arangodb_write(input: proc_macro::TokenStream) -> proc_macro::Token
             type E = crate::engine::EngineError;
             type Document = # name;
 
-            async fn insert(&self, doc: # name) -> Result<(String, Box<dyn crate::models::BoxedDoc>), Self::E> {
-                let aql = arangors::AqlQuery::builder()
-                .query(
-                    crate::engine::db::arangodb::aql_snippet::INSERT
-                )
-                .bind_var("@collection", #name::collection_name())
-                .bind_var("doc", serde_json::to_value(&doc).unwrap())
-                .build();
-                let resp: Vec<#name> = self.db().aql_query(aql).await?;
-                if resp.is_empty() {return crate::engine::DbError::FailedToCreate.into()}
-
-                let new_doc = resp[0].clone();
-
-                Ok((new_doc.id(), Box::new(new_doc)))
+            async fn insert(
+                &self,
+                doc: # name,
+            ) -> Result<(crate::models::id::DocId<# name>, Box<dyn crate::models::BoxedDoc>), Self::E> {
+                crate::io::write::EngineWrite::insert(self, doc).await
             }
 
             async fn update(&self, doc: #name ) -> Result<(), Self::E> {
-            use arangors::document::options::UpdateOptions;
-            let col = self.db().collection(#name::collection_name()).await?;
-            let _updated_doc = col.update_document::<#name>(
-                &doc.key(),
-                doc.clone(),
-                UpdateOptions::default()
-            ).await?;
-            Ok(())
+                crate::io::write::EngineWrite::update(self, doc).await
             }
         }
     };
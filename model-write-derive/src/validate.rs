@@ -0,0 +1,166 @@
+use quote::quote;
+use syn::__private::TokenStream2;
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Error, Field, Lit, Meta, NestedMeta};
+
+/// Implements `Validate` from the `#[validate(..)]` rules of each field
+pub(crate) fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(s) => &s.fields,
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "Validate can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut checks = Vec::new();
+    let mut read_only = Vec::new();
+    for field in fields.iter() {
+        for rule in rules_of(field)? {
+            match &rule {
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident("read_only") => {
+                    read_only.push(stored_name(field))
+                }
+                _ => checks.push(check(field, rule)?),
+            }
+        }
+    }
+
+    let name = &input.ident;
+    Ok(quote! {
+        impl crate::models::validate::Validate for #name {
+            fn validate(&self) -> Result<(), crate::models::validate::ValidationError> {
+                #[allow(unused_imports)]
+                use crate::models::validate::{rules, Pattern};
+
+                let mut errors = crate::models::validate::ValidationError::default();
+                #(#checks)*
+                errors.into_result()
+            }
+
+            fn read_only() -> &'static [&'static str] {
+                &[#(#read_only),*]
+            }
+        }
+    })
+}
+
+/// Rules given in every `validate` attribute of `field`
+fn rules_of(field: &Field) -> Result<Vec<NestedMeta>, Error> {
+    let mut rules = Vec::new();
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident("validate")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => rules.extend(list.nested),
+            meta => return Err(Error::new(meta.span(), "expected #[validate(..)]")),
+        }
+    }
+    Ok(rules)
+}
+
+/// Name `field` is stored under, taken from a serde rename
+fn stored_name(field: &Field) -> String {
+    let renamed = field
+        .attrs
+        .iter()
+        .filter(|a| a.path.is_ident("serde"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|meta| match meta {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("rename") => {
+                string(&nv.lit).ok()
+            }
+            NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("rename") => {
+                list.nested.iter().find_map(|m| match m {
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("serialize") => {
+                        string(&nv.lit).ok()
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        });
+
+    renamed.unwrap_or_else(|| field.ident.as_ref().unwrap().to_string())
+}
+
+fn check(field: &Field, rule: NestedMeta) -> Result<TokenStream2, Error> {
+    let ident = field
+        .ident
+        .as_ref()
+        .ok_or_else(|| Error::new(field.span(), "Validate needs named fields"))?;
+    let stored = stored_name(field);
+    let value = quote!(&self.#ident);
+
+    let check = match &rule {
+        NestedMeta::Meta(Meta::Path(p)) if p.is_ident("non_empty") => {
+            quote!(rules::non_empty(#value))
+        }
+        NestedMeta::Meta(Meta::Path(p)) if p.is_ident("key") => quote!(rules::key(#value)),
+        NestedMeta::Meta(Meta::Path(p)) if p.is_ident("id") => quote! {
+            rules::id(#value, <Self as crate::models::DocDetails>::collection_name())
+        },
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("id") => {
+            let collection = string(&nv.lit)?;
+            quote!(rules::id(#value, #collection))
+        }
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("max_length") => {
+            let max = match &nv.lit {
+                Lit::Int(i) => i.base10_parse::<usize>()?,
+                lit => return Err(Error::new(lit.span(), "expected a length")),
+            };
+            quote!(rules::max_length(#value, #max))
+        }
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("regex") => {
+            let pattern = string(&nv.lit)?;
+            if let Err(e) = regex::Regex::new(&pattern) {
+                return Err(Error::new(nv.lit.span(), e));
+            }
+            quote! {{
+                static PATTERN: Pattern = Pattern::new(#pattern);
+                rules::regex(#value, &PATTERN)
+            }}
+        }
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("custom") => {
+            let path: syn::Path =
+                syn::parse_str(&string(&nv.lit)?).map_err(|e| Error::new(nv.lit.span(), e))?;
+            let name = path.segments.last().unwrap().ident.to_string();
+            quote!(rules::custom(#name, #path(#value)))
+        }
+        NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("range") => {
+            let (mut min, mut max) = (quote!(None), quote!(None));
+            for bound in list.nested.iter() {
+                let (target, nv) = match bound {
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("min") => {
+                        (&mut min, nv)
+                    }
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("max") => {
+                        (&mut max, nv)
+                    }
+                    _ => return Err(Error::new(bound.span(), "expected min = .. or max = ..")),
+                };
+                let n = match &nv.lit {
+                    Lit::Int(i) => i.base10_parse::<f64>()?,
+                    Lit::Float(f) => f.base10_parse::<f64>()?,
+                    lit => return Err(Error::new(lit.span(), "expected a number")),
+                };
+                *target = quote!(Some(#n));
+            }
+            quote!(rules::range(#value, #min, #max))
+        }
+        _ => return Err(Error::new(rule.span(), "unknown validate rule")),
+    };
+
+    Ok(quote!(errors.check(#stored, #check);))
+}
+
+fn string(lit: &Lit) -> Result<String, Error> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        _ => Err(Error::new(lit.span(), "expected a string")),
+    }
+}
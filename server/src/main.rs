@@ -4,6 +4,7 @@ use actix_web::{App, HttpResponse, HttpServer};
 // Todo: preludes module for discuits_api
 use discuits_api::preludes::*;
 use discuits_api::preludes::read::EngineGet;
+use discuits_api::engine::error::request_id;
 
 
 #[actix_web::main]
@@ -15,6 +16,7 @@ async fn main() -> std::io::Result<()> {
     let session = shared_data.clone();
     HttpServer::new(move || {
         App::new()
+            .wrap_fn(request_id)
             .app_data(session.clone())
             .service(web::scope("/app").route("", get().to(get_all_albums)))
     })
@@ -37,16 +39,7 @@ async fn config_database() -> Result<Session<Db<ArangoDb>>, EngineError> {
 
 async fn get_all_albums(data: Session<Db<ArangoDb>>) -> actix_web::Result<HttpResponse> {
     let db = data.db().read().await;
-    let a = db.get_all::<Album>().await.map_err(|err| {
-        if let Some(db_error) = err.downcast_ref::<DbError>() {
-            match db_error {
-                DbError::ItemNotFound => actix_web::error::ErrorNotFound("Not found"),
-                _ => actix_web::error::ErrorInternalServerError("Whoops"),
-            }
-        } else {
-            actix_web::error::ErrorInternalServerError("Whoops")
-        }
-    })?;
+    let a = db.get_all::<Album>().await?;
 
     Ok(HttpResponse::Ok().json(a))
 }
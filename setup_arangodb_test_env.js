@@ -17,7 +17,7 @@ try {
         db._createDatabase(db_name);
     user.grantDatabase(user_name, db_name, 'rw');
     db._useDatabase(db_name);
-    var documents = {'album': 0, 'artist': 0, 'artist_to': 1, 'inventory': 0, 'variant': 0};
+    var documents = {'album': 0, 'artist': 0, 'artist_to': 1, 'inventory': 0, 'variant': 1, 'stockmovement': 0, 'pricechange': 0, 'label': 0, 'sub_label': 1, 'released_on': 1, 'genre': 0, 'sub_genre': 1, 'tagged': 1, 'redirect': 0};
     Object.keys(documents).forEach(key => {
         try {
             switch (documents[key]) {
@@ -35,6 +35,14 @@ try {
             console.error(e);
         }
     });
+    db.album.ensureIndex({type: 'persistent', name: 'unique_barcode', fields: ['barcode'], unique: true, sparse: true});
+    ['album', 'artist', 'label'].forEach(key => {
+        db._collection(key).ensureIndex({type: 'persistent', name: 'external_ids', fields: ['external_ids[*]']});
+    });
+    ['album', 'artist'].forEach(key => {
+        db._collection(key).ensureIndex({type: 'persistent', name: 'search_key', fields: ['search_key']});
+    });
+    db.redirect.ensureIndex({type: 'persistent', name: 'redirect_from', fields: ['from']});
 
 
 } catch (error) {
@@ -0,0 +1,132 @@
+//! Command line tools for the discuits database.
+//!
+//! ```text
+//! discuits dump <file> [options]      write a full dump of the database to <file>
+//! discuits restore <file> [options]   restore a dump into the database
+//!
+//! options:
+//!     --host <url>         database host, the ArangoDB default when left out
+//!     --db <name>          database name
+//!     --user <name>        user to authenticate as, no authentication when left out
+//! ```
+//!
+//! The password of the user is read from `DISCUITS_PASSWORD`, so it doesn't show
+//! in the shell history or the process list.
+//! A `<file>` of `-` writes the dump to stdout, or reads it from stdin.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::process::exit;
+
+use discuits_api::engine::db::{ArangoDb, AuthType};
+use discuits_api::engine::EngineError;
+use discuits_api::service::backup::{dump, restore, Manifest};
+
+const USAGE: &str =
+    "usage: discuits <dump|restore> <file> [--host <url>] [--db <name>] [--user <name>]";
+
+/// Variable the password of `--user` is read from
+const PASSWORD_VAR: &str = "DISCUITS_PASSWORD";
+
+#[derive(Debug, Default)]
+struct Args {
+    command: String,
+    file: String,
+    host: Option<String>,
+    db: String,
+    user: Option<String>,
+    pass: String,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut positional = Vec::new();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--host" => parsed.host = Some(value()?),
+                "--db" => parsed.db = value()?,
+                "--user" => parsed.user = Some(value()?),
+                "--pass" => {
+                    return Err(format!(
+                        "--pass is not accepted, set {} instead",
+                        PASSWORD_VAR
+                    ))
+                }
+                a if a.starts_with("--") => return Err(format!("unknown option {}", a)),
+                _ => positional.push(arg),
+            }
+        }
+        match positional.as_slice() {
+            [command, file] if command == "dump" || command == "restore" => {
+                parsed.command = command.clone();
+                parsed.file = file.clone();
+                if parsed.user.is_some() {
+                    parsed.pass = std::env::var(PASSWORD_VAR).unwrap_or_default();
+                }
+                Ok(parsed)
+            }
+            _ => Err(USAGE.to_string()),
+        }
+    }
+}
+
+async fn connect(args: &Args) -> Result<ArangoDb, EngineError> {
+    let auth = match args.user.as_deref() {
+        Some(user) => AuthType::Jwt {
+            user,
+            pass: &args.pass,
+        },
+        None => AuthType::NoAuth,
+    };
+    let mut builder = ArangoDb::builder();
+    if let Some(host) = args.host.as_deref() {
+        builder.host(host);
+    }
+    builder.db_name(&args.db).auth_type(auth).connect().await
+}
+
+async fn run(args: Args) -> Result<Manifest, EngineError> {
+    let engine = connect(&args).await?;
+    if args.command == "dump" {
+        let out: Box<dyn Write> = if args.file == "-" {
+            Box::new(std::io::stdout())
+        } else {
+            Box::new(File::create(&args.file)?)
+        };
+        dump(&engine, BufWriter::new(out)).await
+    } else {
+        let input: Box<dyn Read> = if args.file == "-" {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(File::open(&args.file)?)
+        };
+        restore(&engine, BufReader::new(input)).await
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    };
+    let command = args.command.clone();
+    match run(args).await {
+        Ok(manifest) => {
+            // stdout may be holding the dump itself
+            for c in manifest.collections.iter() {
+                eprintln!("{:<16} {:>10}", c.name, c.count);
+            }
+            eprintln!("{} {} documents", command, manifest.total());
+        }
+        Err(e) => {
+            eprintln!("{} failed: {}", command, e);
+            exit(1);
+        }
+    }
+}
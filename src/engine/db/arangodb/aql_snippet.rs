@@ -7,18 +7,508 @@ pub(crate) const FILTER: &str = "FOR doc IN @@collection \
                                  FILTER doc.@k == @v \
                                  RETURN doc";
 
+pub(crate) const FIND_NAME: &str = "FOR doc IN @@collection \
+                                    FILTER HAS(doc, 'search_key') ? doc.search_key == @key : doc.name == @name \
+                                    RETURN doc";
+
 pub(crate) const INSERT: &str = "INSERT @doc INTO @@collection \
                                 OPTIONS {overwrite: false} \
                                 RETURN NEW";
 
-pub(crate) const UPSERT_EDGE: &str = "UPSERT( {_from: @doc._from, _to: @doc._to} ) \
+pub(crate) const INSERT_EDGE: &str = "FOR doc IN [@doc] \
+                                     FILTER ASSERT(DOCUMENT(doc._from) != null, CONCAT('_from not found: ', doc._from)) \
+                                     FILTER ASSERT(DOCUMENT(doc._to) != null, CONCAT('_to not found: ', doc._to)) \
+                                     INSERT doc INTO @@collection \
+                                     OPTIONS {overwrite: false} \
+                                     RETURN NEW";
+
+pub(crate) const UPSERT_EDGE: &str = "UPSERT( {_from: @doc._from, _to: @doc._to, role: @doc.role} ) \
                                 INSERT(@doc) \
-                                UPDATE({}) in @@collection \
+                                UPDATE(@doc) in @@collection \
                                 return NEW";
 
+pub(crate) const FIND_EDGE: &str = "FOR doc IN @@collection \
+                                    FILTER MATCHES(doc, @example) \
+                                    RETURN doc";
+
+pub(crate) const TRAVERSE_OUTBOUND: &str = "FOR v, e IN 1..1 OUTBOUND @start @@collection \
+                                            FILTER MATCHES(e, @example) \
+                                            RETURN v";
+
+pub(crate) const TRAVERSE_INBOUND: &str = "FOR v, e IN 1..1 INBOUND @start @@collection \
+                                           FILTER MATCHES(e, @example) \
+                                           RETURN v";
+
 pub(crate) const UPSERT: &str = "UPSERT( _key: @key ) \
                                 INSERT(@doc) \
                                 UPDATE(@doc) in @@collection \
                                 return NEW";
 
 pub(crate) const REMOVE: &str = "REMOVE @key IN @@collection RETURN OLD";
+
+pub(crate) const LINK_EXISTING: &str = "LET found = {parent: DOCUMENT(@parent) != null, child: DOCUMENT(@child) != null} \
+                                        LET edge = FIRST(FOR doc IN (found.parent AND found.child ? [MERGE(@example, {[@vertex]: @parent, [@other]: @child})] : []) \
+                                        UPSERT {_from: doc._from, _to: doc._to, role: doc.role} \
+                                        INSERT doc \
+                                        UPDATE doc IN @@collection \
+                                        RETURN NEW) \
+                                        RETURN MERGE(found, {edge: edge})";
+
+pub(crate) const LINK_CHECKED: &str = "FOR child IN @children \
+                                       FILTER ASSERT(DOCUMENT(@parent) != null, CONCAT('parent not found: ', @parent)) \
+                                       FILTER ASSERT(DOCUMENT(child) != null, CONCAT('child not found: ', child)) \
+                                       LET doc = MERGE(@example, {[@vertex]: @parent, [@other]: child}) \
+                                       UPSERT {_from: doc._from, _to: doc._to, role: doc.role} \
+                                       INSERT doc \
+                                       UPDATE doc IN @@collection \
+                                       RETURN NEW";
+
+pub(crate) const LINK_ORDERED: &str = "FOR child IN @children \
+                                       FILTER ASSERT(DOCUMENT(@parent) != null, CONCAT('parent not found: ', @parent)) \
+                                       FILTER ASSERT(DOCUMENT(child) != null, CONCAT('child not found: ', child)) \
+                                       LET doc = MERGE(@example, {[@vertex]: @parent, [@other]: child, position: POSITION(@children, child, true)}) \
+                                       UPSERT {_from: doc._from, _to: doc._to, role: doc.role} \
+                                       INSERT doc \
+                                       UPDATE doc IN @@collection \
+                                       RETURN NEW";
+
+pub(crate) const UNLINK: &str = "FOR e IN @@collection \
+                                 FILTER e[@vertex] == @parent AND e[@other] IN @children \
+                                 FILTER MATCHES(e, @example) \
+                                 REMOVE e IN @@collection \
+                                 RETURN OLD";
+
+pub(crate) const UNLINK_STALE: &str = "FOR e IN @@collection \
+                                       FILTER e[@vertex] == @parent AND e[@other] NOT IN @children \
+                                       FILTER MATCHES(e, @example) \
+                                       REMOVE e IN @@collection \
+                                       RETURN OLD";
+
+pub(crate) const RELINK: &str = "FOR e IN @@collection \
+                                 FILTER e[@vertex] == @current AND e[@other] == @child \
+                                 FILTER MATCHES(e, @example) \
+                                 FILTER ASSERT(DOCUMENT(@target) != null, CONCAT('parent not found: ', @target)) \
+                                 UPDATE e WITH {[@vertex]: @target} IN @@collection \
+                                 RETURN NEW";
+
+pub(crate) const CREATE_INVENTORY_VARIANT: &str = "FOR album IN [DOCUMENT(@album)] \
+                                                   FILTER ASSERT(album != null, CONCAT('album not found: ', @album)) \
+                                                   INSERT @inventory INTO @@inventory \
+                                                   LET inventory = NEW \
+                                                   INSERT MERGE(@variant, {_from: album._id, _to: inventory._id}) INTO @@variant \
+                                                   LET variant = NEW \
+                                                   INSERT MERGE(@movement, {inventory: inventory._id, delta: inventory.count, created: DATE_NOW(), updated: DATE_NOW()}) INTO @@ledger \
+                                                   RETURN {variant: variant, inventory: inventory}";
+
+pub(crate) const VARIANTS_OF: &str = "FOR inventory, variant IN 1..1 OUTBOUND @album @@variant \
+                                      RETURN {variant: variant, inventory: inventory}";
+
+pub(crate) const GRADED_VARIANTS_OF: &str = "FOR inventory, variant IN 1..1 OUTBOUND @album @@variant \
+                                             FILTER @media == null OR variant.media_grade IN @media \
+                                             FILTER @sleeve == null OR variant.sleeve_grade IN @sleeve \
+                                             SORT POSITION(@scale, variant.media_grade, true) DESC, \
+                                             POSITION(@scale, variant.sleeve_grade, true) DESC \
+                                             RETURN {variant: variant, inventory: inventory}";
+
+pub(crate) const ADJUST_STOCK: &str = "FOR doc IN [DOCUMENT(@id)] \
+                                       FILTER doc != null AND doc.count + @delta >= 0 AND doc.count + @delta <= @max \
+                                       UPDATE doc WITH {count: doc.count + @delta, updated: DATE_NOW()} \
+                                       IN @@collection OPTIONS {exclusive: true} \
+                                       LET inventory = NEW \
+                                       INSERT MERGE(@movement, {inventory: doc._id, delta: @delta, created: DATE_NOW(), updated: DATE_NOW()}) INTO @@ledger \
+                                       RETURN inventory";
+
+pub(crate) const SET_STOCK: &str = "FOR doc IN [DOCUMENT(@id)] \
+                                    FILTER doc != null \
+                                    UPDATE doc WITH {count: @count, updated: DATE_NOW()} \
+                                    IN @@collection OPTIONS {exclusive: true} \
+                                    LET inventory = NEW \
+                                    INSERT MERGE(@movement, {inventory: doc._id, delta: @count - doc.count, created: DATE_NOW(), updated: DATE_NOW()}) INTO @@ledger \
+                                    RETURN inventory";
+
+pub(crate) const GET_DOCUMENT: &str = "RETURN DOCUMENT(@id)";
+
+pub(crate) const LEDGER_CHECK: &str = "FOR doc IN @@collection \
+                                       FILTER @id == null OR doc._id == @id \
+                                       LET ledger = SUM(FOR m IN @@ledger FILTER m.inventory == doc._id RETURN m.delta) \
+                                       RETURN {inventory: doc._id, count: doc.count, ledger: ledger}";
+
+pub(crate) const RECOMPUTE_STOCK: &str = "FOR doc IN @@collection \
+                                          FILTER doc._id == @id \
+                                          LET ledger = SUM(FOR m IN @@ledger FILTER m.inventory == doc._id RETURN m.delta) \
+                                          FILTER ASSERT(ledger >= 0, CONCAT('ledger total is negative for ', doc._id)) \
+                                          UPDATE doc WITH {count: ledger, updated: DATE_NOW()} \
+                                          IN @@collection OPTIONS {exclusive: true} \
+                                          RETURN NEW";
+
+pub(crate) const MOVEMENTS: &str = "FOR m IN @@ledger \
+                                    FILTER m.inventory == @id AND m.created >= @from AND m.created < @to \
+                                    SORT m.created ASC \
+                                    RETURN m";
+
+pub(crate) const SET_PRICES: &str = "FOR v IN @@variant \
+                                     FILTER v._id == @id \
+                                     UPDATE v WITH @prices IN @@variant OPTIONS {mergeObjects: false} \
+                                     LET variant = NEW \
+                                     INSERT MERGE(@change, {variant: v._id}) INTO @@history \
+                                     RETURN variant";
+
+pub(crate) const PRICE_HISTORY: &str = "FOR c IN @@history \
+                                        FILTER c.variant == @id \
+                                        SORT c.created ASC \
+                                        RETURN c";
+
+pub(crate) const ALL_STOCK: &str = "FOR v IN @@variant \
+                                    LET inventory = DOCUMENT(v._to) \
+                                    FILTER inventory != null \
+                                    RETURN {variant: v, inventory: inventory}";
+
+pub(crate) const SET_TRACKLIST: &str = "FOR doc IN @@collection \
+                                        FILTER doc._id == @id \
+                                        UPDATE doc WITH {tracklist: @tracklist, updated: DATE_NOW()} \
+                                        IN @@collection \
+                                        RETURN NEW";
+
+pub(crate) const SET_TRACKLIST_AT: &str = "UPDATE {_key: PARSE_IDENTIFIER(@id).key, _rev: @rev} \
+                                           WITH {tracklist: @tracklist, updated: DATE_NOW()} \
+                                           IN @@collection \
+                                           OPTIONS {ignoreRevs: false} \
+                                           RETURN NEW";
+
+pub(crate) const ALBUMS_WITH_TRACK: &str = "FOR doc IN @@collection \
+                                            LET titles = APPEND(doc.tracklist[*].title, \
+                                            doc.tracklist[*].sub_tracks[**].title) \
+                                            FILTER LENGTH(titles[* FILTER CONTAINS(LOWER(CURRENT), @title)]) > 0 \
+                                            RETURN doc";
+
+pub(crate) const CREATE_NODE: &str = "FOR parent IN [@parent == null ? null : DOCUMENT(@parent)] \
+                                      FILTER ASSERT(@parent == null OR parent != null, \
+                                      CONCAT('parent not found: ', @parent)) \
+                                      INSERT @doc INTO @@collection \
+                                      LET node = NEW \
+                                      LET linked = (FOR p IN (parent == null ? [] : [parent]) \
+                                      INSERT {_from: p._id, _to: node._id} INTO @@tree) \
+                                      RETURN node";
+
+pub(crate) const SET_PARENT: &str = "FOR node IN [DOCUMENT(@id)] \
+                                     FILTER ASSERT(node != null, CONCAT('not found: ', @id)) \
+                                     FILTER ASSERT(DOCUMENT(@parent) != null, CONCAT('parent not found: ', @parent)) \
+                                     FILTER ASSERT(@parent NOT IN (FOR v IN 0..@depth OUTBOUND node._id @@tree \
+                                     RETURN v._id), 'can not be moved below itself') \
+                                     UPDATE node WITH {parent: @parent, updated: DATE_NOW()} IN @@collection \
+                                     LET updated = NEW \
+                                     UPSERT {_to: updated._id} \
+                                     INSERT {_from: @parent, _to: updated._id} \
+                                     UPDATE {_from: @parent} IN @@tree \
+                                     RETURN updated";
+
+pub(crate) const CLEAR_PARENT: &str = "FOR node IN [DOCUMENT(@id)] \
+                                       FILTER ASSERT(node != null, CONCAT('not found: ', @id)) \
+                                       UPDATE node WITH {parent: null, updated: DATE_NOW()} IN @@collection \
+                                       LET updated = NEW \
+                                       LET removed = (FOR e IN @@tree FILTER e._to == updated._id \
+                                       REMOVE e IN @@tree) \
+                                       RETURN updated";
+
+pub(crate) const DESCENDANTS: &str = "FOR v IN 1..@depth OUTBOUND @id @@tree \
+                                      OPTIONS {order: 'bfs', uniqueVertices: 'global'} \
+                                      RETURN v";
+
+pub(crate) const ANCESTORS: &str = "FOR v IN 1..@depth INBOUND @id @@tree \
+                                    OPTIONS {order: 'bfs', uniqueVertices: 'global'} \
+                                    RETURN v";
+
+pub(crate) const MERGE_CHECK: &str = "FOR keep IN [DOCUMENT(@keep)] \
+                                      FOR remove IN [DOCUMENT(@remove)] \
+                                      FILTER ASSERT(keep != null AND remove != null, 'merged documents not found') \
+                                      FILTER ASSERT(keep._id != remove._id AND keep._id NOT IN \
+                                      (FOR v IN 1..@depth OUTBOUND remove._id @@tree RETURN v._id), \
+                                      'can not be merged into itself or a descendant') \
+                                      RETURN keep._id";
+
+pub(crate) const DROP_DUPLICATE_LINKS: &str = "FOR e IN @@collection \
+                                               FILTER e[@vertex] == @remove \
+                                               FILTER LENGTH(FOR x IN @@collection \
+                                               FILTER x[@vertex] == @keep AND x[@other] == e[@other] \
+                                               AND x.role == e.role LIMIT 1 RETURN 1) > 0 \
+                                               REMOVE e IN @@collection \
+                                               RETURN OLD._id";
+
+pub(crate) const MOVE_LINKS: &str = "FOR e IN @@collection \
+                                     FILTER e[@vertex] == @remove \
+                                     UPDATE e WITH {[@vertex]: @keep} IN @@collection \
+                                     RETURN NEW._id";
+
+pub(crate) const REMOVE_LINKS: &str = "FOR e IN @@collection \
+                                       FILTER e[@vertex] == @remove \
+                                       REMOVE e IN @@collection \
+                                       RETURN OLD._id";
+
+pub(crate) const REPARENT: &str = "FOR doc IN @@collection \
+                                   FILTER doc.parent == @remove \
+                                   UPDATE doc WITH {parent: @keep, updated: DATE_NOW()} IN @@collection \
+                                   RETURN NEW._id";
+
+pub(crate) const REMOVE_DOCUMENT: &str = "REMOVE PARSE_IDENTIFIER(@id).key IN @@collection RETURN OLD._id";
+
+pub(crate) const LINK_RELEASE: &str = "FOR album IN [DOCUMENT(@album)] \
+                                       FILTER ASSERT(album != null, CONCAT('album not found: ', @album)) \
+                                       FILTER ASSERT(DOCUMENT(@label) != null, CONCAT('label not found: ', @label)) \
+                                       UPSERT {_from: album._id, _to: @label} \
+                                       INSERT {_from: album._id, _to: @label, cat_no: @cat_no} \
+                                       UPDATE {cat_no: @cat_no} IN @@collection \
+                                       RETURN NEW";
+
+pub(crate) const RELEASES_ON: &str = "FOR label IN 0..@depth OUTBOUND @label @@sub_label \
+                                      OPTIONS {order: 'bfs', uniqueVertices: 'global'} \
+                                      FOR album, e IN 1..1 INBOUND label @@released_on \
+                                      SORT e.cat_no \
+                                      RETURN {album: album, label: label, cat_no: e.cat_no}";
+
+pub(crate) const FIND_RELEASE: &str = "FOR label IN [DOCUMENT(@label)] \
+                                       FILTER label != null \
+                                       FOR album, e IN 1..1 INBOUND label @@released_on \
+                                       FILTER UPPER(SUBSTITUTE(e.cat_no, ' ', '')) == @cat_no \
+                                       RETURN {album: album, label: label, cat_no: e.cat_no}";
+
+pub(crate) const CAT_NOS: &str = "FOR album IN @@album \
+                                  FILTER CONTAINS(album.cat_no, ' - ') \
+                                  RETURN {id: album._id, cat_no: album.cat_no}";
+
+pub(crate) const MIGRATE_CAT_NOS: &str = "FOR item IN @items \
+                                          UPSERT {_from: item.album, _to: item.label} \
+                                          INSERT {_from: item.album, _to: item.label, cat_no: item.cat_no} \
+                                          UPDATE {cat_no: item.cat_no} IN @@released_on \
+                                          RETURN item.album";
+
+pub(crate) const UNLINKED_CAT_NOS: &str = "FOR album IN @@album \
+                                           FILTER album.cat_no != null AND album.cat_no != '' \
+                                           FILTER LENGTH(FOR e IN @@released_on \
+                                           FILTER e._from == album._id LIMIT 1 RETURN 1) == 0 \
+                                           RETURN album._id";
+
+pub(crate) const TAG: &str = "FOR doc IN [DOCUMENT(@doc)] \
+                              FILTER ASSERT(doc != null, CONCAT('not found: ', @doc)) \
+                              FILTER ASSERT(DOCUMENT(@genre) != null, CONCAT('genre not found: ', @genre)) \
+                              UPSERT {_from: doc._id, _to: @genre} \
+                              INSERT {_from: doc._id, _to: @genre} \
+                              UPDATE {} IN @@collection \
+                              RETURN NEW";
+
+pub(crate) const UNTAG: &str = "FOR e IN @@collection \
+                                FILTER e._from == @doc AND e._to == @genre \
+                                REMOVE e IN @@collection \
+                                RETURN OLD";
+
+pub(crate) const TAGGED_IN: &str = "FOR genre IN 0..@depth OUTBOUND @genre @@sub_genre \
+                                    OPTIONS {order: 'bfs', uniqueVertices: 'global'} \
+                                    FOR doc IN 1..1 INBOUND genre @@tagged \
+                                    FILTER IS_SAME_COLLECTION(@collection, doc) \
+                                    RETURN DISTINCT doc";
+
+pub(crate) const GENRE_COUNTS: &str = "FOR genre IN @@collection \
+                                       LET tagged = UNIQUE(FOR g IN 0..@depth OUTBOUND genre @@sub_genre \
+                                       OPTIONS {order: 'bfs', uniqueVertices: 'global'} \
+                                       FOR doc IN 1..1 INBOUND g @@tagged RETURN doc._id) \
+                                       SORT genre.name \
+                                       RETURN { \
+                                       genre: genre, \
+                                       albums: LENGTH(tagged[* FILTER PARSE_IDENTIFIER(CURRENT).collection == @album]), \
+                                       artists: LENGTH(tagged[* FILTER PARSE_IDENTIFIER(CURRENT).collection == @artist])}";
+
+pub(crate) const RENAME: &str = "FOR doc IN [DOCUMENT(@id)] \
+                                 FILTER ASSERT(doc != null, CONCAT('not found: ', @id)) \
+                                 FILTER ASSERT(LENGTH(FOR other IN @@collection FILTER other.name == @name \
+                                 AND other._id != doc._id LIMIT 1 RETURN 1) == 0, \
+                                 CONCAT('name already in use: ', @name)) \
+                                 UPDATE doc WITH {name: @name, updated: DATE_NOW()} IN @@collection \
+                                 RETURN NEW";
+
+pub(crate) const FIND_BY_BARCODE: &str = "FOR album IN @@collection \
+                                          FILTER album.barcode == @barcode \
+                                          LIMIT 1 \
+                                          LET variants = (FOR inventory, variant IN 1..1 OUTBOUND album @@variant \
+                                          RETURN {variant: variant, inventory: inventory}) \
+                                          RETURN {album: album, variants: variants}";
+
+pub(crate) const BARCODES: &str = "FOR doc IN @@collection \
+                                   FILTER doc.barcode != null \
+                                   RETURN {id: doc._id, barcode: doc.barcode}";
+
+pub(crate) const NAMES: &str = "FOR doc IN @@collection \
+                                RETURN {id: doc._id, name: doc.name, search_key: doc.search_key}";
+
+pub(crate) const SET_SEARCH_KEYS: &str = "FOR item IN @items \
+                                          UPDATE PARSE_IDENTIFIER(item.id).key WITH {search_key: item.search_key} \
+                                          IN @@collection \
+                                          COLLECT WITH COUNT INTO updated \
+                                          RETURN updated";
+
+pub(crate) const SET_BARCODES: &str = "FOR item IN @items \
+                                       UPDATE PARSE_IDENTIFIER(item.id).key WITH {barcode: item.barcode} \
+                                       IN @@collection OPTIONS {keepNull: false} \
+                                       COLLECT WITH COUNT INTO updated \
+                                       RETURN updated";
+
+pub(crate) const UPSERT_EXTERNAL: &str = "LET existing = FIRST(FOR doc IN @@collection \
+                                          FILTER @keys ANY IN doc.external_ids OR doc.foreign_key IN @keys \
+                                          LIMIT 1 RETURN doc) \
+                                          LET ids = UNION_DISTINCT(existing.external_ids || [], @doc.external_ids) \
+                                          LET fields = MERGE(UNSET(@doc, '_id', '_key', 'created', 'updated'), {external_ids: ids}) \
+                                          LET unchanged = existing != null AND !HAS(existing, 'foreign_key') \
+                                          AND LENGTH(existing.external_ids) == LENGTH(ids) \
+                                          AND MATCHES(existing, UNSET(fields, 'external_ids')) \
+                                          LET written = (FOR x IN (unchanged ? [] : [1]) \
+                                          UPSERT {_key: existing == null ? @doc._key : existing._key} \
+                                          INSERT @doc \
+                                          REPLACE MERGE(fields, {created: existing.created, updated: DATE_NOW()}) \
+                                          IN @@collection \
+                                          RETURN {id: NEW._id, outcome: OLD == null ? 'created' : 'updated'}) \
+                                          RETURN unchanged ? {id: existing._id, outcome: 'skipped'} : written[0]";
+
+pub(crate) const FIND_EXTERNAL: &str = "FOR doc IN @@collection \
+                                        FILTER @id IN doc.external_ids OR doc.foreign_key == @id \
+                                        LIMIT 1 \
+                                        RETURN doc";
+
+pub(crate) const ADD_EXTERNAL_IDS: &str = "FOR doc IN @@collection \
+                                           FILTER @id IN doc.external_ids \
+                                           FILTER LENGTH(MINUS(@ids, doc.external_ids)) > 0 \
+                                           UPDATE doc WITH {external_ids: UNION_DISTINCT(doc.external_ids, @ids), \
+                                           updated: DATE_NOW()} IN @@collection \
+                                           RETURN NEW._id";
+
+pub(crate) const IMPORT_CREDITS: &str = "FOR artist IN @@artist \
+                                         FILTER @artists ANY IN artist.external_ids \
+                                         UPSERT {_from: artist._id, _to: @album, role: @role} \
+                                         INSERT {_from: artist._id, _to: @album, role: @role} \
+                                         UPDATE {} IN @@collection \
+                                         RETURN NEW";
+
+pub(crate) const IMPORT_RELEASES: &str = "FOR item IN @labels \
+                                          FOR label IN @@label \
+                                          FILTER item.external_id IN label.external_ids \
+                                          UPSERT {_from: @album, _to: label._id} \
+                                          INSERT {_from: @album, _to: label._id, cat_no: item.cat_no} \
+                                          UPDATE {cat_no: item.cat_no} IN @@collection \
+                                          RETURN NEW";
+
+pub(crate) const FOREIGN_KEYS: &str = "FOR doc IN @@collection \
+                                       FILTER HAS(doc, 'foreign_key') \
+                                       RETURN {_id: doc._id, foreign_key: doc.foreign_key, \
+                                       external_ids: doc.external_ids}";
+
+pub(crate) const SET_EXTERNAL_IDS: &str = "FOR item IN @items \
+                                           UPDATE PARSE_IDENTIFIER(item._id).key WITH {foreign_key: null, \
+                                           external_ids: item.external_ids || [], \
+                                           legacy_foreign_key: item.legacy_foreign_key} \
+                                           IN @@collection OPTIONS {keepNull: false} \
+                                           COLLECT WITH COUNT INTO upgraded \
+                                           RETURN upgraded";
+
+pub(crate) const UPSERT_ROW: &str = "LET existing = FIRST(FOR doc IN @@collection \
+                                     FILTER @field == 'external_ids' ? @value IN doc.external_ids : doc[@field] == @value \
+                                     LIMIT 1 RETURN doc) \
+                                     LET ids = UNION_DISTINCT(existing.external_ids || [], @doc.external_ids || []) \
+                                     LET unchanged = existing != null AND MATCHES(existing, @patch) \
+                                     AND LENGTH(ids) == LENGTH(existing.external_ids || []) \
+                                     LET outcome = existing == null ? 'created' : unchanged ? 'skipped' : 'updated' \
+                                     LET written = (FOR x IN (@dry_run OR unchanged ? [] : [1]) \
+                                     UPSERT {_key: existing == null ? @doc._key : existing._key} \
+                                     INSERT @doc \
+                                     UPDATE MERGE(@patch, {external_ids: ids, updated: DATE_NOW()}) \
+                                     IN @@collection OPTIONS {mergeObjects: false} \
+                                     RETURN NEW._id) \
+                                     RETURN {id: existing == null ? @doc._id : existing._id, outcome: outcome}";
+
+pub(crate) const UPDATE_DOCUMENT: &str = "UPDATE PARSE_IDENTIFIER(@id).key WITH @patch IN @@collection \
+                                          OPTIONS {mergeObjects: false} \
+                                          RETURN NEW._id";
+
+pub(crate) const FIND_ALBUM_REF: &str = "FOR album IN @@collection \
+                                         FILTER album._key == @ref OR album.barcode == @ref OR @ref IN album.external_ids \
+                                         LIMIT 1 \
+                                         RETURN album";
+
+pub(crate) const EXPORT_ALBUMS: &str = "FOR album IN @@album \
+                                        SORT album.search_key \
+                                        LET artists = (FOR artist IN 1..1 INBOUND album @@artist_to \
+                                        SORT artist.search_key RETURN DISTINCT artist.name) \
+                                        RETURN {album: album, artists: artists}";
+
+pub(crate) const EXPORT_STOCK: &str = "FOR album IN @@album \
+                                       SORT album.search_key \
+                                       LET artists = (FOR artist IN 1..1 INBOUND album @@artist_to \
+                                       SORT artist.search_key RETURN DISTINCT artist.name) \
+                                       FOR inventory, variant IN 1..1 OUTBOUND album @@variant \
+                                       RETURN {album: album, artists: artists, variant: variant, inventory: inventory}";
+
+pub(crate) const DUMP_COLLECTION: &str = "FOR doc IN @@collection \
+                                          FILTER doc._key > @after \
+                                          SORT doc._key \
+                                          LIMIT @limit \
+                                          RETURN UNSET(doc, \"_rev\")";
+
+pub(crate) const RESTORE_DOCUMENTS: &str = "FOR doc IN @docs \
+                                            INSERT UNSET(doc, \"_id\", \"_rev\") INTO @@collection \
+                                            OPTIONS {overwriteMode: \"replace\"} \
+                                            RETURN NEW._key";
+
+pub(crate) const MERGE_PAIR_CHECK: &str = "FOR keep IN [DOCUMENT(@keep)] \
+                                           FOR remove IN [DOCUMENT(@remove)] \
+                                           FILTER ASSERT(keep != null AND remove != null, 'merged documents not found') \
+                                           FILTER ASSERT(keep._id != remove._id, 'can not be merged into itself') \
+                                           RETURN keep._id";
+
+pub(crate) const MERGE_ARTIST: &str = "FOR keep IN [DOCUMENT(@keep)] \
+                                       FOR remove IN [DOCUMENT(@remove)] \
+                                       LET aliases = MINUS(UNION_DISTINCT(keep.aliases || [], remove.aliases || [], \
+                                       [remove.name]), [keep.name, '']) \
+                                       UPDATE keep WITH { \
+                                       aliases: aliases, \
+                                       external_ids: UNION_DISTINCT(keep.external_ids || [], remove.external_ids || []), \
+                                       profile: keep.profile != '' ? keep.profile : remove.profile, \
+                                       updated: DATE_NOW()} IN @@collection \
+                                       RETURN NEW._id";
+
+pub(crate) const RETARGET_REDIRECTS: &str = "FOR r IN @@redirect \
+                                             FILTER r.to == @remove \
+                                             UPDATE r WITH {to: @keep} IN @@redirect \
+                                             RETURN NEW._id";
+
+pub(crate) const ADD_REDIRECT: &str = "INSERT {from: @remove, to: @keep, created: DATE_NOW()} INTO @@redirect \
+                                       RETURN NEW._id";
+
+pub(crate) const RESOLVE: &str = "LET doc = DOCUMENT(@id) \
+                                  LET to = doc == null ? FIRST(FOR r IN @@redirect FILTER r.from == @id RETURN r.to) : null \
+                                  RETURN doc != null ? doc : (to == null ? null : DOCUMENT(to))";
+
+pub(crate) const FUZZY_FIND: &str = "LET query = CONCAT('  ', @query, ' ') \
+                                     LET query_grams = UNIQUE(FOR i IN 0..(LENGTH(query) - 3) RETURN SUBSTRING(query, i, 3)) \
+                                     FOR doc IN @@collection \
+                                     LET best = FIRST(FOR name IN APPEND([doc.name], doc.aliases || []) \
+                                     LET folded = name == doc.name AND HAS(doc, 'search_key') ? doc.search_key \
+                                     : TRIM(REGEX_REPLACE(LOWER(name), '[^\\\\p{L}\\\\p{N}]+', ' ')) \
+                                     LET padded = CONCAT('  ', folded, ' ') \
+                                     LET grams = UNIQUE(FOR i IN 0..(LENGTH(padded) - 3) RETURN SUBSTRING(padded, i, 3)) \
+                                     LET trigram = LENGTH(INTERSECTION(query_grams, grams)) / LENGTH(UNION_DISTINCT(query_grams, grams)) \
+                                     LET edit = 1 - LEVENSHTEIN_DISTANCE(@query, folded) / MAX([LENGTH(@query), LENGTH(folded), 1]) \
+                                     LET score = MAX([trigram, edit]) \
+                                     SORT score DESC \
+                                     LIMIT 1 \
+                                     RETURN {name: name, score: score}) \
+                                     FILTER best != null AND best.score >= @threshold \
+                                     SORT best.score DESC \
+                                     LIMIT @limit \
+                                     RETURN {doc: doc, score: best.score, matched: best.name}";
+
+pub(crate) const OUTDATED: &str = "FOR doc IN @@collection \
+                                   FILTER doc._key > @after \
+                                   FILTER doc.schema_version == null OR doc.schema_version < @version \
+                                   SORT doc._key \
+                                   LIMIT @batch \
+                                   RETURN doc";
+
+pub(crate) const REPLACE_UNCHANGED: &str = "REPLACE @doc IN @@collection \
+                                            OPTIONS {ignoreRevs: false} \
+                                            RETURN NEW._id";
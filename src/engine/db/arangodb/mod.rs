@@ -1,11 +1,17 @@
+use arangors::index::{Index, IndexSettings};
+use arangors::transaction::{Transaction, TransactionCollections, TransactionSettings};
 use arangors::uclient::reqwest::ReqwestClient;
 use arangors::{AqlQuery, ClientError, Connection, Database};
 use serde::Serialize;
+use serde_json::Value;
 use tokio::sync::RwLock;
 
 use crate::engine::db::arangodb::aql_snippet::*;
 use crate::engine::db::{Db, DbBasics, DbBuilder, DEFAULT_HOST};
-use crate::engine::EngineError;
+use crate::engine::error::Cause;
+use crate::engine::{DbError, EngineError};
+use crate::models::edge::Direction;
+use crate::models::name::search_key;
 use crate::models::{DocDetails};
 use arangoq::{ArangoConnection};
 
@@ -27,6 +33,58 @@ pub struct ArangoDB2(ArangoConnection);
 // }
 //
 
+/// ArangoDb error number of a write breaking a unique index, including the primary `_key` index
+pub(crate) const UNIQUE_CONSTRAINT_VIOLATED: u16 = 1210;
+
+/// ArangoDb error numbers of a missing document, collection, database or graph
+const NOT_FOUND: [u16; 4] = [1202, 1203, 1228, 1924];
+
+/// ArangoDb error number of a write given a `_rev` the document no longer has
+pub(crate) const REVISION_CONFLICT: u16 = 1200;
+
+/// ArangoDb error numbers of a write conflicting with a stored document
+const CONFLICT: [u16; 2] = [REVISION_CONFLICT, UNIQUE_CONSTRAINT_VIOLATED];
+
+/// ArangoDb error number of refused credentials or a missing permission
+const FORBIDDEN: u16 = 11;
+
+/// ArangoDb error numbers of a lock or cluster operation running out of time
+const TIMEOUT: [u16; 2] = [18, 1457];
+
+impl From<ClientError> for EngineError {
+    fn from(e: ClientError) -> Self {
+        match e {
+            ClientError::Arango(ref arango) => {
+                let (num, code) = (arango.error_num(), arango.code());
+                let cause = Cause::with_error_num(num, e);
+                if NOT_FOUND.contains(&num) || code == 404 {
+                    EngineError::NotFound(cause)
+                } else if CONFLICT.contains(&num) || code == 409 {
+                    EngineError::Conflict(cause)
+                } else if num == FORBIDDEN || code == 401 || code == 403 {
+                    EngineError::Unauthorized(cause)
+                } else if TIMEOUT.contains(&num) || code == 408 || code == 504 {
+                    EngineError::Timeout(cause)
+                } else {
+                    EngineError::Other(cause)
+                }
+            }
+            ClientError::InsufficientPermission { .. } => EngineError::Unauthorized(Cause::new(e)),
+            ClientError::InvalidServer(_) => EngineError::Connection(Cause::new(e)),
+            // The http client's error is only kept as its debug text
+            ClientError::HttpClient(ref msg) => {
+                let msg = msg.to_ascii_lowercase();
+                if ["timed out", "timedout", "timeout"].iter().any(|t| msg.contains(t)) {
+                    EngineError::Timeout(Cause::new(e))
+                } else {
+                    EngineError::Connection(Cause::new(e))
+                }
+            }
+            _ => EngineError::other(e),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ArangoDb {
     pub(crate) conn: Connection,
@@ -64,10 +122,14 @@ impl ArangoDb {
 
     pub async fn validate_db(&self) -> Result<(), EngineError> {
         let info = self.db.url();
+        let (host, port) = match (info.host(), info.port()) {
+            (Some(host), Some(port)) => (host, port),
+            _ => return DbError::NoHostProvided.into(),
+        };
         let db = format!(
             "http://{}:{}/_db/{}/_api/simple/any",
-            info.host().unwrap(),
-            info.port().unwrap(),
+            host,
+            port,
             self.db.name()
         );
         self.conn.session().client.put(&db).send().await?;
@@ -91,6 +153,35 @@ impl ArangoDb {
     pub fn db(&self) -> &Database<ReqwestClient> {
         &self.db
     }
+
+    /// Begins a stream transaction with write access to the given collections,
+    /// the caller is responsible for calling `commit` or `abort`.
+    pub async fn begin_transaction(
+        &self,
+        write: Vec<String>,
+    ) -> Result<Transaction<ReqwestClient>, EngineError> {
+        let settings = TransactionSettings::builder()
+            .collections(TransactionCollections::builder().write(write).build())
+            .build();
+        let tx = self.db.begin_transaction(settings).await?;
+        Ok(tx)
+    }
+
+    /// Creates a unique index on `field` if there isn't one already,
+    /// documents without the field are not indexed.
+    pub async fn ensure_unique_index(&self, collection: &str, field: &str) -> Result<(), EngineError> {
+        let index = Index::builder()
+            .name(format!("unique_{}", field))
+            .fields(vec![field.to_string()])
+            .settings(IndexSettings::Persistent {
+                unique: true,
+                sparse: true,
+                deduplicate: false,
+            })
+            .build();
+        self.db.create_index(collection, &index).await?;
+        Ok(())
+    }
 }
 
 /// Simple AQL generation methods
@@ -121,6 +212,52 @@ impl ArangoDb {
             .build()
     }
 
+    /// Query for the documents whose `k` is `v`. Names are compared by their
+    /// search key, other values ignoring case and surrounding whitespace.
+    pub fn aql_find(k: &str, v: &str, collection: &str) -> AqlQuery<'static> {
+        if k == "name" {
+            AqlQuery::builder()
+                .query(aql_snippet::FIND_NAME)
+                .bind_var("@collection", collection.to_string())
+                .bind_var("key", search_key(v))
+                .bind_var("name", v.trim().to_lowercase())
+                .build()
+        } else {
+            AqlQuery::builder()
+                .query(FILTER)
+                .bind_var("k", k.to_string())
+                .bind_var("v", v.trim().to_ascii_lowercase())
+                .bind_var("@collection", collection.to_string())
+                .build()
+        }
+    }
+
+    pub fn aql_find_edges<'a>(collection: &'a str, example: Value) -> AqlQuery<'a> {
+        AqlQuery::builder()
+            .query(FIND_EDGE)
+            .bind_var("@collection", collection)
+            .bind_var("example", example)
+            .build()
+    }
+
+    pub fn aql_traverse<'a>(
+        collection: &'a str,
+        start: &'a str,
+        direction: Direction,
+        example: Value,
+    ) -> AqlQuery<'a> {
+        let query = match direction {
+            Direction::Outbound => TRAVERSE_OUTBOUND,
+            Direction::Inbound => TRAVERSE_INBOUND,
+        };
+        AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", collection)
+            .bind_var("start", start)
+            .bind_var("example", example)
+            .build()
+    }
+
     // TODO: change raw upsert
     pub fn aql_upsert<T: Clone + Serialize + 'static + DocDetails>(
         document: T,
@@ -134,14 +271,21 @@ impl ArangoDb {
             .build()
     }
 
+    /// Edges, documents with a `_from` and `_to`, are only inserted when both ends exist
     pub fn insert<T: Clone + Serialize + 'static>(
         document: T,
         collection: &'static str,
     ) -> AqlQuery<'static> {
+        let doc = serde_json::to_value(&document).unwrap();
+        let query = if doc.get("_from").is_some() && doc.get("_to").is_some() {
+            INSERT_EDGE
+        } else {
+            INSERT
+        };
         AqlQuery::builder()
-            .query(INSERT)
+            .query(query)
             .bind_var("@collection", collection)
-            .bind_var("doc", serde_json::to_value(&document).unwrap())
+            .bind_var("doc", doc)
             .build()
     }
 
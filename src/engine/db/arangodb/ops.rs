@@ -1,11 +1,17 @@
 use arangors::document::options::UpdateOptions;
-use arangors::Cursor;
+use arangors::{AqlQuery, ClientError, Cursor};
 
 use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
 
-use crate::engine::db::arangodb::ArangoDb;
+use crate::engine::db::arangodb::aql_snippet::{DUMP_COLLECTION, GET_DOCUMENT, RESTORE_DOCUMENTS};
+use crate::engine::db::arangodb::{ArangoDb, UNIQUE_CONSTRAINT_VIOLATED};
 use crate::engine::{DbError, EngineError};
+use crate::io::dump::{CollectionKind, EngineDump};
 use crate::io::{delete::EngineDelete, read::EngineGet, write::EngineWrite};
+use crate::models::id::DocId;
+use crate::models::key::MAX_KEY_ATTEMPTS;
 use crate::models::{BoxedDoc, ReqModelTraits};
 
 /// handles pagination
@@ -29,6 +35,23 @@ pub async fn cursor_digest<T: DeserializeOwned>(
     Ok(col)
 }
 
+/// handles pagination like `cursor_digest`, but fails if a batch can't be read
+/// rather than returning the batches read before it
+pub async fn cursor_digest_all<T: DeserializeOwned>(
+    cursor: Cursor<T>,
+    engine: &ArangoDb,
+) -> Result<Vec<T>, EngineError> {
+    let mut col: Vec<T> = cursor.result;
+    let mut next = cursor.id;
+    while let Some(i) = next {
+        let c: Cursor<T> = engine.db().aql_next_batch(&i).await?;
+        col.extend(c.result);
+        next = c.id;
+    }
+
+    Ok(col)
+}
+
 #[crate::async_trait]
 impl EngineGet for ArangoDb {
     type E = EngineError;
@@ -47,21 +70,23 @@ impl EngineGet for ArangoDb {
         Ok(collection)
     }
 
-    async fn get<T>(&self, id: &str) -> Result<T, Self::E>
+    async fn get<T>(&self, id: &DocId<T>) -> Result<T, Self::E>
     where
         T: ReqModelTraits,
     {
-        let aql = Self::aql_get_single(T::collection_name(), id);
-        let col: Vec<T> = self.db.aql_query(aql).await?;
-
-        Ok(col[0].clone())
+        let key = id.key();
+        let aql = Self::aql_get_single(T::collection_name(), &key);
+        let resp: Option<T> = self.db.aql_query(aql).await?.pop();
+        match resp {
+            Some(doc) => Ok(doc),
+            None => DbError::ItemNotFound.into(),
+        }
     }
 
     async fn find<T: ReqModelTraits>(&self, k: &str, v: &str) -> Result<T, Self::E> {
-        let val = v.trim().to_ascii_lowercase();
         let resp: Option<T> = self
             .db()
-            .aql_query(ArangoDb::aql_filter(k, &val, T::collection_name()))
+            .aql_query(ArangoDb::aql_find(k, v, T::collection_name()))
             .await?
             .pop();
         if let Some(doc) = resp {
@@ -72,6 +97,89 @@ impl EngineGet for ArangoDb {
     }
 }
 
+impl ArangoDb {
+    /// Validates `doc` and writes it with the query `write` makes of it, returning the query's result.
+    /// A document without a key is given one by its `KeyStrategy`. When a key made by the
+    /// strategy is taken by another document it is written again with the strategy's next key,
+    /// up to `MAX_KEY_ATTEMPTS` times. A key given by hand, or a copy of a stored document, fails.
+    pub(crate) async fn insert_keyed<T, F>(
+        &self,
+        mut doc: T,
+        write: F,
+    ) -> Result<Vec<T>, EngineError>
+    where
+        T: ReqModelTraits,
+        F: Fn(Value) -> AqlQuery<'static> + Send,
+    {
+        doc.validate()?;
+        if doc.key().is_empty() {
+            doc.new_key();
+        }
+
+        let mut attempt = 0;
+        loop {
+            let document = document(&doc)?;
+            let err = match self.db.aql_query(write(document.clone())).await {
+                Ok(resp) => return Ok(resp),
+                Err(ClientError::Arango(e)) if e.error_num() == UNIQUE_CONSTRAINT_VIOLATED => {
+                    ClientError::Arango(e)
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            attempt += 1;
+            let key = match doc.generate_key(attempt) {
+                Some(key) if attempt < MAX_KEY_ATTEMPTS && doc.key_made() => key,
+                _ => return Err(err.into()),
+            };
+            if !self.taken_by_other(&document).await? {
+                return Err(err.into());
+            }
+            log::debug!(
+                "key {} of {} taken, retrying",
+                doc.key(),
+                T::collection_name()
+            );
+            doc.set_key(key);
+        }
+    }
+
+    /// Whether a different document is stored under the `_id` of `document`,
+    /// rather than a unique index other than the key being broken or `document` being stored already
+    async fn taken_by_other(&self, document: &Value) -> Result<bool, EngineError> {
+        let id = match document.get("_id").and_then(Value::as_str) {
+            Some(id) => id.to_string(),
+            None => return Ok(false),
+        };
+        let aql = AqlQuery::builder()
+            .query(GET_DOCUMENT)
+            .bind_var("id", id)
+            .build();
+        let stored: Option<Value> = self.db.aql_query(aql).await?.pop();
+
+        Ok(match stored {
+            Some(Value::Object(mut stored)) => {
+                stored.remove("_rev");
+                Value::Object(stored) != *document
+            }
+            _ => false,
+        })
+    }
+}
+
+/// `doc` as it is sent to the database, leaving out a blank `_key` and `_id` for it to fill in
+fn document<T: Serialize>(doc: &T) -> Result<Value, EngineError> {
+    let mut document = serde_json::to_value(doc)?;
+    if let Value::Object(fields) = &mut document {
+        for k in ["_key", "_id"].iter() {
+            if fields.get(*k).and_then(Value::as_str) == Some("") {
+                fields.remove(*k);
+            }
+        }
+    }
+    Ok(document)
+}
+
 #[crate::async_trait]
 impl EngineWrite for ArangoDb {
     type E = EngineError;
@@ -79,23 +187,29 @@ impl EngineWrite for ArangoDb {
     async fn insert<T: ReqModelTraits + BoxedDoc + 'static>(
         &self,
         doc: T,
-    ) -> Result<(String, Box<dyn BoxedDoc>), Self::E> {
-        let resp: Vec<T> = self
-            .db
-            .aql_query(ArangoDb::insert(doc.clone(), T::collection_name()))
-            .await?;
-        if resp.is_empty() {
-            return Err(Box::new(DbError::FailedToCreate));
+    ) -> Result<(DocId<T>, Box<dyn BoxedDoc>), Self::E> {
+        let resp: Option<T> = self
+            .insert_keyed(doc, |doc| ArangoDb::insert(doc, T::collection_name()))
+            .await?
+            .pop();
+        match resp {
+            Some(new_doc) => Ok((new_doc.id(), Box::new(new_doc))),
+            None => DbError::FailedToCreate.into(),
         }
-        let new_doc = resp[0].clone();
-
-        Ok((new_doc.id(), Box::new(new_doc)))
     }
 
     async fn update<T: ReqModelTraits>(&self, doc: T) -> Result<(), Self::E> {
+        doc.validate()?;
+        let mut patch = serde_json::to_value(&doc)?;
+        // read_only fields are left as stored
+        if let Value::Object(fields) = &mut patch {
+            for field in T::read_only() {
+                fields.remove(*field);
+            }
+        }
         let col = self.db().collection(T::collection_name()).await?;
         let _updated_doc = col
-            .update_document::<T>(&doc.key(), doc.clone(), UpdateOptions::default())
+            .update_document::<Value>(&doc.key(), patch, UpdateOptions::default())
             .await?;
         Ok(())
     }
@@ -105,12 +219,11 @@ impl EngineWrite for ArangoDb {
 impl EngineDelete for ArangoDb {
     type E = EngineError;
 
-    async fn remove<T>(&self, id: &str) -> Result<T, Self::E>
+    async fn remove<T>(&self, id: &DocId<T>) -> Result<T, Self::E>
     where
         T: DeserializeOwned + Send + Sync,
     {
-        let parse = id.split('/').collect::<Vec<&str>>();
-        let aql = ArangoDb::remove(parse[1], parse[0]);
+        let aql = ArangoDb::remove(&id.key(), id.collection());
         let mut value: Vec<T> = self.db.aql_query(aql).await?;
         if value.is_empty() {
             return DbError::ItemNotFound.into();
@@ -118,3 +231,44 @@ impl EngineDelete for ArangoDb {
         Ok(value.swap_remove(0))
     }
 }
+
+#[crate::async_trait]
+impl EngineDump for ArangoDb {
+    type E = EngineError;
+
+    async fn export(
+        &self,
+        collection: &str,
+        after: &str,
+        limit: usize,
+    ) -> Result<Vec<Value>, Self::E> {
+        let aql = AqlQuery::builder()
+            .query(DUMP_COLLECTION)
+            .bind_var("@collection", collection)
+            .bind_var("after", after)
+            .bind_var("limit", limit)
+            .build();
+        let cursor: Cursor<Value> = self.db().aql_query_batch(aql).await?;
+
+        // A batch missing from a backup must fail it
+        cursor_digest_all(cursor, self).await
+    }
+
+    async fn import(
+        &self,
+        collection: &str,
+        _kind: CollectionKind,
+        docs: Vec<Value>,
+    ) -> Result<u64, Self::E> {
+        // Edges are stored like any other document, the collection was
+        // created as an edge collection by the database setup
+        let aql = AqlQuery::builder()
+            .query(RESTORE_DOCUMENTS)
+            .bind_var("@collection", collection)
+            .bind_var("docs", docs)
+            .build();
+        let keys: Vec<String> = self.db().aql_query(aql).await?;
+
+        Ok(keys.len() as u64)
+    }
+}
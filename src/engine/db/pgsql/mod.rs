@@ -1,9 +1,12 @@
 
 use async_trait::async_trait;
+use serde_json::Value;
 use tokio::sync::RwLock;
 
 use crate::engine::db::{Db, DbBasics};
+use crate::engine::EngineError;
 use crate::io::*;
+use crate::models::id::DocId;
 use crate::models::{BoxedDoc, ReqModelTraits};
 
 #[derive(Debug)]
@@ -17,12 +20,12 @@ impl PostgresSQL {
 
 #[async_trait]
 impl EngineWrite for PostgresSQL {
-    type E = ();
+    type E = EngineError;
 
     async fn insert<T: ReqModelTraits + BoxedDoc + 'static>(
         &self,
         _doc: T,
-    ) -> Result<(String, Box<dyn BoxedDoc>), Self::E> {
+    ) -> Result<(DocId<T>, Box<dyn BoxedDoc>), Self::E> {
         todo!()
     }
 
@@ -31,6 +34,29 @@ impl EngineWrite for PostgresSQL {
     }
 }
 
+#[async_trait]
+impl EngineDump for PostgresSQL {
+    type E = EngineError;
+
+    async fn export(
+        &self,
+        _collection: &str,
+        _after: &str,
+        _limit: usize,
+    ) -> Result<Vec<Value>, Self::E> {
+        todo!()
+    }
+
+    async fn import(
+        &self,
+        _collection: &str,
+        _kind: CollectionKind,
+        _docs: Vec<Value>,
+    ) -> Result<u64, Self::E> {
+        todo!()
+    }
+}
+
 #[cfg(feature = "pgsql")]
 #[crate::async_trait]
 impl<'a> DbBasics<'a> for Db<PostgresSQL> {
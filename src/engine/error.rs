@@ -0,0 +1,268 @@
+//! Error returned by every engine.
+//!
+//! Failures are told apart by what went wrong rather than by the engine or
+//! library they came from, so callers can match on `NotFound` or `Conflict`
+//! without downcasting. Each keeps the error it came from, with the ArangoDb
+//! error number when the database gave one.
+
+use std::error::Error;
+use std::fmt::Formatter;
+
+use crate::engine::DbError;
+use crate::models::validate::ValidationError;
+
+#[cfg(feature = "actix")]
+pub use problem::*;
+
+#[cfg(feature = "actix")]
+mod problem;
+
+/// Error an `EngineError` was made from
+pub type Source = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EngineError {
+    /// The document, collection or database doesn't exist
+    NotFound(Cause),
+    /// The write conflicts with what is stored i.e. a unique key already taken
+    Conflict(Cause),
+    /// The credentials were refused or don't allow the operation
+    Unauthorized(Cause),
+    /// The document broke its `Validate` rules, nothing was written
+    Validation(ValidationError),
+    /// An id, key or value that can't be used i.e. a malformed `_id`
+    Invalid(Cause),
+    /// The database couldn't be reached
+    Connection(Cause),
+    /// The database didn't answer in time
+    Timeout(Cause),
+    /// Any other failure i.e. a query the database refused or a document that couldn't be read
+    Other(Cause),
+}
+
+/// Underlying error of an `EngineError`
+#[derive(Debug)]
+pub struct Cause {
+    error_num: Option<u16>,
+    source: Source,
+}
+
+impl Cause {
+    pub fn new<E: Into<Source>>(source: E) -> Self {
+        Cause {
+            error_num: None,
+            source: source.into(),
+        }
+    }
+
+    /// Cause the database gave the error number `error_num` for
+    pub fn with_error_num<E: Into<Source>>(error_num: u16, source: E) -> Self {
+        Cause {
+            error_num: Some(error_num),
+            source: source.into(),
+        }
+    }
+
+    pub fn get_error_num(&self) -> Option<u16> {
+        self.error_num
+    }
+
+    pub fn get_source(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self.source.as_ref()
+    }
+}
+
+impl EngineError {
+    /// Wraps an error that has no closer kind
+    pub fn other<E: Into<Source>>(source: E) -> Self {
+        EngineError::Other(Cause::new(source))
+    }
+
+    /// The underlying error, `None` for `Validation`
+    pub fn cause(&self) -> Option<&Cause> {
+        match self {
+            EngineError::NotFound(c)
+            | EngineError::Conflict(c)
+            | EngineError::Unauthorized(c)
+            | EngineError::Invalid(c)
+            | EngineError::Connection(c)
+            | EngineError::Timeout(c)
+            | EngineError::Other(c) => Some(c),
+            EngineError::Validation(_) => None,
+        }
+    }
+
+    /// ArangoDb error number of the failure i.e. `1210` for a unique constraint violated
+    pub fn error_num(&self) -> Option<u16> {
+        self.cause().and_then(Cause::get_error_num)
+    }
+
+    /// The underlying error as `E`, if it is one
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        match self {
+            EngineError::Validation(e) => (e as &dyn Error).downcast_ref(),
+            _ => self.cause()?.get_source().downcast_ref(),
+        }
+    }
+
+    /// Stable name of the kind of error, given to clients i.e. `not_found`
+    pub fn code(&self) -> &'static str {
+        match self {
+            EngineError::NotFound(_) => "not_found",
+            EngineError::Conflict(_) => "conflict",
+            EngineError::Unauthorized(_) => "unauthorized",
+            EngineError::Validation(_) => "validation_failed",
+            EngineError::Invalid(_) => "invalid_input",
+            EngineError::Connection(_) => "unavailable",
+            EngineError::Timeout(_) => "timeout",
+            EngineError::Other(_) => "internal",
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, EngineError::NotFound(_))
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, EngineError::Conflict(_))
+    }
+}
+
+impl std::fmt::Display for EngineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            EngineError::NotFound(_) => "Not found",
+            EngineError::Conflict(_) => "Conflict",
+            EngineError::Unauthorized(_) => "Unauthorized",
+            EngineError::Validation(e) => return write!(f, "Invalid document: {}", e),
+            EngineError::Invalid(_) => "Invalid input",
+            EngineError::Connection(_) => "Connection failed",
+            EngineError::Timeout(_) => "Timed out",
+            EngineError::Other(_) => "Engine error",
+        };
+        match self.cause() {
+            Some(Cause {
+                error_num: Some(n),
+                source,
+            }) => write!(f, "{} ({}): {}", kind, n, source),
+            Some(Cause { source, .. }) => write!(f, "{}: {}", kind, source),
+            None => f.write_str(kind),
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EngineError::Validation(e) => Some(e),
+            _ => self
+                .cause()
+                .map(|c| c.get_source() as &(dyn Error + 'static)),
+        }
+    }
+}
+
+impl From<DbError> for EngineError {
+    fn from(e: DbError) -> Self {
+        match e {
+            DbError::ItemNotFound => EngineError::NotFound(Cause::new(e)),
+            DbError::InsufficientStock { .. } => EngineError::Conflict(Cause::new(e)),
+            DbError::InvalidIdentification
+            | DbError::ParseFail
+            | DbError::NoHostProvided
+            | DbError::BlankDatabaseName
+            | DbError::StockOverflow { .. } => EngineError::Invalid(Cause::new(e)),
+            DbError::FailedToCreate => EngineError::other(e),
+        }
+    }
+}
+
+impl From<ValidationError> for EngineError {
+    fn from(e: ValidationError) -> Self {
+        EngineError::Validation(e)
+    }
+}
+
+impl From<serde_json::Error> for EngineError {
+    fn from(e: serde_json::Error) -> Self {
+        EngineError::other(e)
+    }
+}
+
+impl From<std::io::Error> for EngineError {
+    fn from(e: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+
+        match e.kind() {
+            ErrorKind::NotFound => EngineError::NotFound(Cause::new(e)),
+            ErrorKind::PermissionDenied => EngineError::Unauthorized(Cause::new(e)),
+            ErrorKind::TimedOut => EngineError::Timeout(Cause::new(e)),
+            ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected => EngineError::Connection(Cause::new(e)),
+            _ => EngineError::other(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for EngineError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            EngineError::Timeout(Cause::new(e))
+        } else if e.is_connect() {
+            EngineError::Connection(Cause::new(e))
+        } else {
+            match e.status().map(|s| s.as_u16()) {
+                Some(401) | Some(403) => EngineError::Unauthorized(Cause::new(e)),
+                Some(404) => EngineError::NotFound(Cause::new(e)),
+                _ => EngineError::other(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::error::*;
+
+    #[test]
+    fn test_from_db_error() {
+        let err = EngineError::from(DbError::ItemNotFound);
+        assert!(err.is_not_found());
+        assert!(matches!(
+            err.downcast_ref::<DbError>(),
+            Some(DbError::ItemNotFound)
+        ));
+
+        let err = EngineError::from(DbError::InsufficientStock {
+            available: 1,
+            requested: 2,
+        });
+        assert!(err.is_conflict());
+        assert_eq!(err.error_num(), None);
+        assert!(matches!(
+            EngineError::from(DbError::InvalidIdentification),
+            EngineError::Invalid(_)
+        ));
+        assert!(matches!(
+            EngineError::from(DbError::StockOverflow {
+                available: 1,
+                delta: i64::MAX,
+            }),
+            EngineError::Invalid(_)
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        let err = EngineError::Conflict(Cause::with_error_num(1210, "unique constraint violated"));
+        assert_eq!(err.error_num(), Some(1210));
+        assert_eq!(
+            err.to_string(),
+            "Conflict (1210): unique constraint violated"
+        );
+        assert!(err.source().is_some());
+    }
+}
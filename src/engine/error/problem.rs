@@ -0,0 +1,253 @@
+//! Responses for `EngineError` in handlers, under the `actix` feature.
+//!
+//! `EngineError` implements `ResponseError` so handlers can return it with `?`,
+//! each kind answered with its own status and an RFC 7807 `application/problem+json`
+//! body naming the error `code` and the id of the request. Wrap the app with
+//! `request_id` for the id to be taken from the `x-request-id` header of the request,
+//! and sent back in the same header:
+//!
+//! ```ignore
+//! App::new()
+//!     .wrap_fn(request_id)
+//!     .route("/albums", get().to(get_all_albums))
+//! ```
+
+use std::future::Future;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use uuid::Uuid;
+
+use crate::engine::EngineError;
+use crate::models::validate::FieldError;
+
+/// Header the id of a request is read from and written to
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id taken from a client, longer ones are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// RFC 7807 problem details of a failed request
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Problem {
+    /// Always `about:blank`, the `code` tells problems apart
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Reason phrase of the status
+    pub title: String,
+    pub status: u16,
+    /// What went wrong in words fixed for each `code`, the cause is only logged.
+    /// Left out of server errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// `EngineError::code` of the error
+    pub code: String,
+    /// Id of the request, to find it in the logs
+    pub request_id: String,
+    /// Every field that broke its rules, for `validation_failed`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl Problem {
+    pub fn new(error: &EngineError) -> Self {
+        let status = error.status_code();
+        let errors = match error {
+            EngineError::Validation(e) => e.errors.clone(),
+            _ => Vec::new(),
+        };
+        Problem {
+            kind: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: detail(error).map(str::to_string),
+            code: error.code().to_string(),
+            request_id: current_request_id(),
+            errors,
+        }
+    }
+}
+
+impl ResponseError for EngineError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EngineError::NotFound(_) => StatusCode::NOT_FOUND,
+            EngineError::Conflict(_) => StatusCode::CONFLICT,
+            EngineError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            EngineError::Invalid(_) => StatusCode::BAD_REQUEST,
+            // the credentials refused are those of the server, not of the client
+            EngineError::Unauthorized(_) | EngineError::Connection(_) | EngineError::Timeout(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            EngineError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self);
+        if self.status_code().is_server_error() {
+            log::error!("request {} failed: {}", problem.request_id, self);
+        } else {
+            log::info!("request {} refused: {}", problem.request_id, self);
+        }
+
+        let body = serde_json::to_string(&problem).unwrap_or_default();
+        HttpResponse::build(self.status_code())
+            .content_type("application/problem+json")
+            .insert_header((REQUEST_ID_HEADER, problem.request_id))
+            .body(body)
+    }
+}
+
+/// Explanation of a client error given whatever its cause, as the messages of
+/// the database may name collections, indexes or documents
+fn detail(error: &EngineError) -> Option<&'static str> {
+    match error {
+        EngineError::NotFound(_) => Some("The document asked for does not exist."),
+        EngineError::Conflict(_) => Some("The change conflicts with a stored document."),
+        EngineError::Validation(_) => Some("The document breaks the rules listed in errors."),
+        EngineError::Invalid(_) => Some("An id, key or value of the request can't be used."),
+        EngineError::Unauthorized(_)
+        | EngineError::Connection(_)
+        | EngineError::Timeout(_)
+        | EngineError::Other(_) => None,
+    }
+}
+
+/// Middleware for `App::wrap_fn` giving each request an id, the one in its
+/// `x-request-id` header when it has a usable one, and setting it on the response
+pub fn request_id<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let res = REQUEST_ID.sync_scope(id.clone(), || srv.call(req));
+    REQUEST_ID.scope(id.clone(), async move {
+        let mut res = res.await?;
+        if let Ok(value) = HeaderValue::from_str(&id) {
+            res.headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+        }
+        Ok(res)
+    })
+}
+
+/// Id of the request being handled, a new one outside of `request_id`
+fn current_request_id() -> String {
+    REQUEST_ID
+        .try_with(String::clone)
+        .unwrap_or_else(|_| Uuid::new_v4().to_string())
+}
+
+/// Ids of clients are kept to printable ascii that is safe to log
+fn is_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::http::StatusCode;
+    use actix_web::test::{
+        call_and_read_body_json, call_service, init_service, read_body_json, TestRequest,
+    };
+    use actix_web::web::get;
+    use actix_web::{App, ResponseError};
+    use serde_json::Value;
+
+    use crate::engine::error::*;
+    use crate::engine::DbError;
+    use crate::models::validate::{rules, ValidationError};
+
+    async fn missing() -> Result<String, EngineError> {
+        DbError::ItemNotFound.into()
+    }
+
+    #[test]
+    fn test_status() {
+        let mut invalid = ValidationError::default();
+        invalid.check("name", rules::non_empty(""));
+        let conflict = EngineError::Conflict(Cause::with_error_num(1210, "taken"));
+
+        assert_eq!(
+            EngineError::from(DbError::ItemNotFound).status_code(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(conflict.status_code(), StatusCode::CONFLICT);
+        assert_eq!(
+            EngineError::from(invalid.clone()).status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+
+        let problem = Problem::new(&EngineError::from(invalid));
+        assert_eq!(problem.code, "validation_failed");
+        assert_eq!(problem.errors[0].field, "name");
+        assert!(!problem.request_id.is_empty());
+
+        let problem = Problem::new(&EngineError::other("connection string leaked"));
+        assert_eq!(problem.status, 500);
+        assert_eq!(problem.detail, None);
+
+        let refused = EngineError::Unauthorized(Cause::with_error_num(11, "not authorized"));
+        assert_eq!(refused.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(Problem::new(&refused).detail, None);
+
+        let problem = Problem::new(&conflict);
+        let detail = problem.detail.unwrap();
+        assert!(!detail.contains("taken"));
+    }
+
+    #[actix_web::test]
+    async fn test_problem_response() {
+        let app = init_service(
+            App::new()
+                .wrap_fn(request_id)
+                .route("/album", get().to(missing)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/album")
+            .insert_header((REQUEST_ID_HEADER, "req-1"))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers().get(REQUEST_ID_HEADER).unwrap(), "req-1");
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/problem+json"
+        );
+
+        let problem: Value = read_body_json(res).await;
+        assert_eq!(problem["type"], "about:blank");
+        assert_eq!(problem["code"], "not_found");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["request_id"], "req-1");
+
+        let req = TestRequest::get()
+            .uri("/album")
+            .insert_header((REQUEST_ID_HEADER, "bad id"))
+            .to_request();
+        let problem: Value = call_and_read_body_json(&app, req).await;
+        assert_ne!(problem["request_id"], "bad id");
+    }
+}
@@ -2,11 +2,11 @@
 use std::fmt::Formatter;
 
 pub mod db;
+pub mod error;
 pub mod session;
 
-pub type EngineError = Box<dyn std::error::Error + Sync + Send>;
+pub use error::EngineError;
 
-//TODO: Rework error handling
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum DbError {
@@ -16,11 +16,15 @@ pub enum DbError {
     ParseFail,
     ItemNotFound,
     FailedToCreate,
+    /// Removing stock would take the count below zero
+    InsufficientStock { available: u32, requested: u32 },
+    /// Changing the stock by `delta` would take the count past the largest it can hold
+    StockOverflow { available: u32, delta: i64 },
 }
 
 impl DbError {
     pub fn into<T>(self) -> Result<T, EngineError> {
-        Err(Box::new(self))
+        Err(EngineError::from(self))
     }
 }
 
@@ -47,6 +51,23 @@ impl std::fmt::Display for DbError {
             DbError::FailedToCreate => {
                 write!(f, "Failed to create new item")
             }
+            DbError::InsufficientStock {
+                available,
+                requested,
+            } => {
+                write!(
+                    f,
+                    "Insufficient stock: requested {} but only {} available.",
+                    requested, available
+                )
+            }
+            DbError::StockOverflow { available, delta } => {
+                write!(
+                    f,
+                    "Stock overflow: changing {} by {} is out of range.",
+                    available, delta
+                )
+            }
         }
     }
 }
@@ -0,0 +1,1122 @@
+//! CSV import and export of albums, artists and stock, for stock sheets kept in spreadsheets.
+//!
+//! Columns are found by their header, or by position when a sheet has none,
+//! and can be mapped to other headers or positions with `CsvOptions::column`.
+//! Every problem with a row is reported with its line number, rows with
+//! problems are not written. A dry run reports what an import would do
+//! without writing anything.
+//!
+//! Stock rows create a new variant of the album given by its `_key`, barcode or
+//! external id, or update the variant given by its `_key`. Exports add the
+//! album name, artist names and format so a sheet can be read on its own,
+//! these columns are ignored when the sheet is imported again.
+
+use std::collections::HashMap;
+use std::fmt::Formatter;
+use std::io::{BufRead, Write};
+
+use arangors::AqlQuery;
+use serde_json::{Map, Value};
+
+use crate::engine::db::arangodb::aql_snippet::{
+    EXPORT_ALBUMS, EXPORT_STOCK, FIND_ALBUM_REF, GET_ALL, GET_DOCUMENT, UPDATE_DOCUMENT, UPSERT_ROW,
+};
+use crate::engine::db::ArangoDb;
+use crate::engine::EngineError;
+use crate::import::{ImportError, ImportReport, Outcome, Upserted, ARTIST_TO};
+use crate::models::album::Album;
+use crate::models::artist::Artist;
+use crate::models::barcode::Barcode;
+use crate::models::external_id::{ExternalId, ExternalIds};
+use crate::models::format::{Medium, Size, Speed};
+use crate::models::grade::{Grade, SleeveGrade};
+use crate::models::id::DocKey;
+use crate::models::inventory::Inventory;
+use crate::models::price_change::PriceChange;
+use crate::models::stock_movement::{Reason, StockMovement};
+use crate::models::variant::Variant;
+use crate::models::{DocDetails, ReqModelTraits};
+use crate::money::Money;
+use crate::service::inventory::{create_inventory_variant, set_stock_query};
+use crate::service::pricing::set_prices_query;
+use crate::service::tree::in_transaction;
+
+/// Reference written to the ledger and price history for changes made by an import
+const REFERENCE: &str = "csv import";
+
+/// What the rows of a sheet describe
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CsvKind {
+    Album,
+    Artist,
+    /// A variant of an album with its stock
+    Stock,
+}
+
+impl CsvKind {
+    /// Columns in their default order, which is also the order of an export
+    pub fn fields(&self) -> &'static [&'static str] {
+        match self {
+            CsvKind::Album => &[
+                "key",
+                "name",
+                "artists",
+                "description",
+                "barcode",
+                "external_id",
+            ],
+            CsvKind::Artist => &["key", "name", "aliases", "profile", "external_id"],
+            CsvKind::Stock => &[
+                "album",
+                "album_name",
+                "artists",
+                "barcode",
+                "variant",
+                "format",
+                "medium",
+                "size",
+                "speed",
+                "discs",
+                "colour",
+                "media_grade",
+                "sleeve_grade",
+                "count",
+                "list_price",
+            ],
+        }
+    }
+
+    /// Fields rows can be matched to stored documents by
+    fn upsert_fields(&self) -> &'static [&'static str] {
+        match self {
+            CsvKind::Album => &["key", "barcode", "external_id"],
+            CsvKind::Artist => &["key", "external_id"],
+            CsvKind::Stock => &["variant"],
+        }
+    }
+}
+
+/// Where the value of a field is found in a sheet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    /// Column with this header, compared ignoring case
+    Header(String),
+    /// Column at this position, counted from 0
+    Index(usize),
+}
+
+/// How a sheet is read and written
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    delimiter: char,
+    header: Option<bool>,
+    columns: HashMap<&'static str, Column>,
+    dry_run: bool,
+    upsert_on: &'static str,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            header: None,
+            columns: HashMap::new(),
+            dry_run: false,
+            upsert_on: "key",
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `,` by default, spreadsheets in some locales use `;`
+    pub fn delimiter(&mut self, delimiter: char) -> &mut Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Whether the first row is a header, detected from its values when not set
+    pub fn header(&mut self, header: bool) -> &mut Self {
+        self.header = Some(header);
+        self
+    }
+
+    /// Reads or writes `field` in `column` rather than the column named after it
+    pub fn column(&mut self, field: &'static str, column: Column) -> &mut Self {
+        self.columns.insert(field, column);
+        self
+    }
+
+    /// Validates every row and reports what would change without writing anything
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Field rows are matched to stored documents by, `key` by default.
+    /// Albums can also be matched by `barcode` or `external_id`, artists by `external_id`.
+    /// Rows without a value for it are created.
+    pub fn upsert_on(&mut self, field: &'static str) -> &mut Self {
+        self.upsert_on = field;
+        self
+    }
+
+    fn header_of(&self, field: &'static str) -> String {
+        match self.columns.get(field) {
+            Some(Column::Header(header)) => header.clone(),
+            _ => field.to_string(),
+        }
+    }
+}
+
+/// A problem with a single row
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowError {
+    /// Line of the sheet the row starts on, counted from 1
+    pub line: usize,
+    /// Field the problem is with, `None` when it is with the whole row
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "line {}, {}: {}", self.line, field, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+/// Rows created, updated, skipped and failed with the problems of the failed rows
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CsvReport {
+    pub rows: ImportReport,
+    pub errors: Vec<RowError>,
+}
+
+/// Reads the records of a sheet with the line each starts on,
+/// quoted values may hold delimiters, quotes written twice and line breaks.
+pub(crate) struct CsvReader<R: BufRead> {
+    input: R,
+    delimiter: char,
+    line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(input: R, delimiter: char) -> Self {
+        CsvReader {
+            input,
+            delimiter,
+            line: 0,
+        }
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> Result<bool, ImportError> {
+        buf.clear();
+        if self.input.read_line(buf)? == 0 {
+            return Ok(false);
+        }
+        self.line += 1;
+        if self.line == 1 && buf.starts_with('\u{feff}') {
+            buf.remove(0);
+        }
+        while buf.ends_with('\n') || buf.ends_with('\r') {
+            buf.pop();
+        }
+        Ok(true)
+    }
+
+    fn next_record(&mut self) -> Result<Option<(usize, Vec<String>)>, ImportError> {
+        let mut buf = String::new();
+        // Blank lines between records are skipped
+        loop {
+            if !self.read_line(&mut buf)? {
+                return Ok(None);
+            }
+            if !buf.trim().is_empty() {
+                break;
+            }
+        }
+        let start = self.line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            let mut chars = buf.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted && chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' if quoted => quoted = false,
+                    '"' if field.trim().is_empty() => {
+                        field.clear();
+                        quoted = true;
+                    }
+                    c if c == self.delimiter && !quoted => {
+                        fields.push(std::mem::take(&mut field));
+                    }
+                    c => field.push(c),
+                }
+            }
+            if !quoted {
+                break;
+            }
+            // The quoted value goes on to the next line
+            field.push('\n');
+            if !self.read_line(&mut buf)? {
+                return Err(ImportError::Malformed(format!(
+                    "line {}: quoted value is never closed",
+                    start
+                )));
+            }
+        }
+        fields.push(field);
+        Ok(Some((start, fields)))
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<(usize, Vec<String>), ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// Writes a record, quoting the values that need it
+pub(crate) fn write_record<W: Write>(
+    out: &mut W,
+    values: &[String],
+    delimiter: char,
+) -> std::io::Result<()> {
+    let line: Vec<String> = values
+        .iter()
+        .map(|v| {
+            let quote =
+                v.contains(delimiter) || v.contains(['"', '\n', '\r']) || v.trim() != v.as_str();
+            if quote {
+                format!("\"{}\"", v.replace('"', "\"\""))
+            } else {
+                v.clone()
+            }
+        })
+        .collect();
+    writeln!(out, "{}", line.join(&delimiter.to_string()))
+}
+
+/// Lowercase with spaces and hyphens as underscores, so `Media Grade` finds `media_grade`
+fn normalise_header(header: &str) -> String {
+    header.trim().to_lowercase().replace([' ', '-'], "_")
+}
+
+/// Position of each field found in a sheet
+type Columns = HashMap<&'static str, usize>;
+
+/// Finds the column of each field, `header` is the first record when it is a header.
+fn resolve_columns(
+    kind: CsvKind,
+    options: &CsvOptions,
+    header: Option<&[String]>,
+) -> Result<Columns, ImportError> {
+    let mut columns = Columns::new();
+    for (position, field) in kind.fields().iter().enumerate() {
+        let found = match (options.columns.get(field), header) {
+            (Some(Column::Index(i)), _) => Some(*i),
+            (Some(Column::Header(name)), Some(header)) => {
+                let name = normalise_header(name);
+                let i = header.iter().position(|h| normalise_header(h) == name);
+                if i.is_none() {
+                    return Err(ImportError::Malformed(format!(
+                        "no column {} for {}",
+                        name, field
+                    )));
+                }
+                i
+            }
+            (Some(Column::Header(name)), None) => {
+                return Err(ImportError::Malformed(format!(
+                    "column {} for {} needs a header",
+                    name, field
+                )))
+            }
+            (None, Some(header)) => header.iter().position(|h| normalise_header(h) == *field),
+            (None, None) => Some(position),
+        };
+        if let Some(i) = found {
+            columns.insert(field, i);
+        }
+    }
+    Ok(columns)
+}
+
+/// Whether `record` names at least one column, by field or mapped header
+fn is_header(kind: CsvKind, options: &CsvOptions, record: &[String]) -> bool {
+    let mut names: Vec<String> = kind.fields().iter().map(|f| f.to_string()).collect();
+    names.extend(options.columns.values().filter_map(|c| match c {
+        Column::Header(name) => Some(normalise_header(name)),
+        Column::Index(_) => None,
+    }));
+    record
+        .iter()
+        .any(|cell| names.contains(&normalise_header(cell)))
+}
+
+/// A record with the problems found while reading it
+struct Row<'a> {
+    line: usize,
+    cells: Vec<String>,
+    columns: &'a Columns,
+    errors: Vec<RowError>,
+}
+
+impl<'a> Row<'a> {
+    /// Whether the sheet has the column, even when this row leaves it blank
+    fn has(&self, field: &str) -> bool {
+        self.columns.contains_key(field)
+    }
+
+    fn get(&self, field: &str) -> Option<String> {
+        let i = *self.columns.get(field)?;
+        self.cells
+            .get(i)
+            .map(|cell| cell.trim())
+            .filter(|cell| !cell.is_empty())
+            .map(str::to_string)
+    }
+
+    fn error(&mut self, field: Option<&str>, message: impl ToString) {
+        self.errors.push(RowError {
+            line: self.line,
+            field: field.map(str::to_string),
+            message: message.to_string(),
+        });
+    }
+
+    fn required(&mut self, field: &str) -> Option<String> {
+        let value = self.get(field);
+        if value.is_none() {
+            self.error(Some(field), "is required");
+        }
+        value
+    }
+
+    fn parse<T, E: std::fmt::Display>(
+        &mut self,
+        field: &str,
+        parse: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Option<T> {
+        let value = self.get(field)?;
+        match parse(&value) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.error(Some(field), format!("{:?} is not valid, {}", value, e));
+                None
+            }
+        }
+    }
+
+    /// Values separated by `;` or `|`
+    fn list(&self, field: &str) -> Vec<String> {
+        self.get(field)
+            .map(|v| {
+                v.split([';', '|'])
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn external_ids(&mut self) -> Vec<ExternalId> {
+        let mut ids = Vec::new();
+        for id in self.list("external_id") {
+            match id.parse::<ExternalId>() {
+                Ok(id) => ids.push(id),
+                Err(_) => self.error(
+                    Some("external_id"),
+                    format!("{:?} is not written as `Source - ID`", id),
+                ),
+            }
+        }
+        ids
+    }
+}
+
+/// Imports every row of a sheet.
+/// Fails only when the sheet can't be read, problems with rows are listed in the report.
+pub async fn import<R: BufRead + Send>(
+    engine: &ArangoDb,
+    kind: CsvKind,
+    input: R,
+    options: &CsvOptions,
+) -> Result<CsvReport, EngineError> {
+    if !kind.upsert_fields().contains(&options.upsert_on) && kind != CsvKind::Stock {
+        return Err(ImportError::Malformed(format!(
+            "rows can't be matched on {}",
+            options.upsert_on
+        ))
+        .into());
+    }
+    let mut records = CsvReader::new(input, options.delimiter).peekable();
+    let first = match records.peek() {
+        Some(Ok((_, record))) => Some(record.clone()),
+        Some(Err(_)) => return Err(records.next().unwrap().unwrap_err().into()),
+        None => None,
+    };
+    let header = match (options.header, &first) {
+        (Some(header), _) => header,
+        (None, Some(first)) => is_header(kind, options, first),
+        (None, None) => false,
+    };
+    let columns = if header {
+        records.next();
+        resolve_columns(kind, options, first.as_deref())?
+    } else {
+        resolve_columns(kind, options, None)?
+    };
+
+    let mut report = CsvReport::default();
+    for record in records {
+        let (line, cells) = record?;
+        let mut row = Row {
+            line,
+            cells,
+            columns: &columns,
+            errors: Vec::new(),
+        };
+        let outcome = match kind {
+            CsvKind::Album => import_album(engine, &mut row, options).await,
+            CsvKind::Artist => import_artist(engine, &mut row, options).await,
+            CsvKind::Stock => import_stock(engine, &mut row, options).await,
+        };
+        match outcome {
+            Ok(Some(outcome)) if row.errors.is_empty() => report.rows.record(outcome),
+            Ok(_) => {}
+            Err(e) => row.error(None, e),
+        }
+        if !row.errors.is_empty() {
+            report.rows.failed += 1;
+            report.errors.append(&mut row.errors);
+        }
+    }
+    Ok(report)
+}
+
+/// Upserts a row matched on `options.upsert_on`, only the fields the sheet has are updated
+async fn upsert_row<T: ReqModelTraits + ExternalIds>(
+    engine: &ArangoDb,
+    row: &Row<'_>,
+    doc: &T,
+    fields: &[(&str, &str)],
+    options: &CsvOptions,
+) -> Result<Outcome, EngineError> {
+    doc.validate()?;
+    let value = serde_json::to_value(doc)?;
+    let patch: Map<String, Value> = fields
+        .iter()
+        .filter(|(field, _)| row.has(field))
+        .filter_map(|(_, key)| Some((key.to_string(), value.get(*key)?.clone())))
+        .collect();
+    let (field, matched) = match options.upsert_on {
+        "barcode" => ("barcode", value.get("barcode").cloned()),
+        "external_id" => (
+            "external_ids",
+            doc.external_ids()
+                .first()
+                .map(|id| Value::from(id.to_string())),
+        ),
+        _ => ("_key", None),
+    };
+    // Rows without the field are matched on their own `_key`, new or given
+    let (field, matched) = match matched {
+        Some(matched) => (field, matched),
+        None => ("_key", Value::from(doc.key())),
+    };
+
+    let aql = AqlQuery::builder()
+        .query(UPSERT_ROW)
+        .bind_var("@collection", T::collection_name())
+        .bind_var("field", field)
+        .bind_var("value", matched)
+        .bind_var("doc", value)
+        .bind_var("patch", Value::Object(patch))
+        .bind_var("dry_run", options.dry_run)
+        .build();
+    let resp: Option<Upserted> = engine.db().aql_query(aql).await?.pop();
+    Ok(resp.map(|r| r.outcome).unwrap_or(Outcome::Skipped))
+}
+
+async fn import_album(
+    engine: &ArangoDb,
+    row: &mut Row<'_>,
+    options: &CsvOptions,
+) -> Result<Option<Outcome>, EngineError> {
+    let name = row.required("name");
+    let key = row.parse("key", str::parse::<DocKey<_>>);
+    let barcode = row.parse("barcode", Barcode::new);
+    let ids = row.external_ids();
+    let name = match name {
+        Some(name) if row.errors.is_empty() => name,
+        _ => return Ok(None),
+    };
+
+    let mut album = Album::new();
+    if let Some(key) = key {
+        album.change_id(key);
+    }
+    album.name(name);
+    if let Some(description) = row.get("description") {
+        album.description(description);
+    }
+    if let Some(barcode) = barcode {
+        album.barcode(barcode);
+    }
+    for id in ids {
+        album.external_id(id);
+    }
+    // The search key is patched with the name so a renamed album is found by its new name
+    let fields = [
+        ("name", "name"),
+        ("name", "search_key"),
+        ("description", "description"),
+        ("barcode", "barcode"),
+    ];
+    upsert_row(engine, row, &album, &fields, options)
+        .await
+        .map(Some)
+}
+
+async fn import_artist(
+    engine: &ArangoDb,
+    row: &mut Row<'_>,
+    options: &CsvOptions,
+) -> Result<Option<Outcome>, EngineError> {
+    let name = row.required("name");
+    let key = row.parse("key", str::parse::<DocKey<_>>);
+    let ids = row.external_ids();
+    let name = match name {
+        Some(name) if row.errors.is_empty() => name,
+        _ => return Ok(None),
+    };
+
+    let mut artist = Artist::new();
+    if let Some(key) = key {
+        artist.change_id(key);
+    }
+    artist.name(name);
+    for alias in row.list("aliases") {
+        artist.alias(alias);
+    }
+    if let Some(profile) = row.get("profile") {
+        artist.profile(profile);
+    }
+    for id in ids {
+        artist.external_id(id);
+    }
+    let fields = [
+        ("name", "name"),
+        ("name", "search_key"),
+        ("aliases", "aliases"),
+        ("profile", "profile"),
+    ];
+    upsert_row(engine, row, &artist, &fields, options)
+        .await
+        .map(Some)
+}
+
+fn parse_size(s: &str) -> Result<Size, ImportError> {
+    let s = s
+        .trim()
+        .trim_end_matches(['"', '\''])
+        .trim_end_matches("inch")
+        .trim();
+    match s {
+        "7" => Ok(Size::Seven),
+        "10" => Ok(Size::Ten),
+        "12" => Ok(Size::Twelve),
+        _ => Err(ImportError::Malformed(
+            "expected 7\", 10\" or 12\"".to_string(),
+        )),
+    }
+}
+
+fn parse_speed(s: &str) -> Result<Speed, ImportError> {
+    let s = s.to_ascii_lowercase();
+    match s.trim_end_matches("rpm").trim() {
+        "33" | "33⅓" | "33 ⅓" | "33 1/3" | "33.3" => Ok(Speed::Rpm33),
+        "45" => Ok(Speed::Rpm45),
+        "78" => Ok(Speed::Rpm78),
+        _ => Err(ImportError::Malformed(
+            "expected 33, 45 or 78 RPM".to_string(),
+        )),
+    }
+}
+
+fn parse_medium(s: &str) -> Result<Medium, ImportError> {
+    [
+        Medium::Vinyl,
+        Medium::Shellac,
+        Medium::CD,
+        Medium::Cassette,
+        Medium::ReelToReel,
+        Medium::MiniDisc,
+    ]
+    .iter()
+    .copied()
+    .find(|m| m.as_str().eq_ignore_ascii_case(s.trim()))
+    .ok_or_else(|| ImportError::Malformed("unknown medium".to_string()))
+}
+
+/// Format, grades, count and price given by a stock row
+struct StockRow {
+    medium: Option<Medium>,
+    size: Option<Size>,
+    speed: Option<Speed>,
+    discs: Option<u16>,
+    colour: Option<String>,
+    media_grade: Option<Grade>,
+    sleeve_grade: Option<SleeveGrade>,
+    count: Option<u32>,
+    list_price: Option<Money>,
+}
+
+impl StockRow {
+    fn read(row: &mut Row<'_>) -> Self {
+        StockRow {
+            medium: row.parse("medium", parse_medium),
+            size: row.parse("size", parse_size),
+            speed: row.parse("speed", parse_speed),
+            discs: row.parse("discs", |s| s.parse::<u16>()),
+            colour: row.get("colour"),
+            media_grade: row.parse("media_grade", |s| s.parse::<Grade>()),
+            sleeve_grade: row.parse("sleeve_grade", |s| s.parse::<SleeveGrade>()),
+            count: row.parse("count", |s| s.parse::<u32>()),
+            list_price: row.parse("list_price", |s| s.parse::<Money>()),
+        }
+    }
+
+    fn apply(&self, variant: &mut Variant) {
+        let mut format = variant.get_format().clone();
+        if let Some(medium) = self.medium {
+            format.medium(medium);
+        }
+        if let Some(size) = self.size {
+            format.size(size);
+        }
+        if let Some(speed) = self.speed {
+            format.speed(speed);
+        }
+        if let Some(discs) = self.discs {
+            format.discs(discs);
+        }
+        if let Some(colour) = &self.colour {
+            format.colour(colour.clone());
+        }
+        variant.format(format);
+        if let Some(grade) = self.media_grade {
+            variant.media_grade(grade);
+        }
+        if let Some(grade) = self.sleeve_grade {
+            variant.sleeve_grade(grade);
+        }
+    }
+}
+
+async fn get_document<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    id: &str,
+) -> Result<Option<T>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(GET_DOCUMENT)
+        .bind_var("id", id)
+        .build();
+    let resp: Option<Option<T>> = engine.db().aql_query(aql).await?.pop();
+    Ok(resp.flatten())
+}
+
+async fn import_stock(
+    engine: &ArangoDb,
+    row: &mut Row<'_>,
+    options: &CsvOptions,
+) -> Result<Option<Outcome>, EngineError> {
+    let stock = StockRow::read(row);
+    let movement = || {
+        let mut movement = StockMovement::new(Reason::Adjustment);
+        movement.reference(REFERENCE);
+        movement
+    };
+
+    if let Some(key) = row.get("variant") {
+        let id = format!("{}/{}", Variant::collection_name(), key);
+        let stored = match get_document::<Variant>(engine, &id).await? {
+            Some(stored) => stored,
+            None => {
+                row.error(Some("variant"), format!("{} not found", key));
+                return Ok(None);
+            }
+        };
+        if !row.errors.is_empty() {
+            return Ok(None);
+        }
+        let mut variant = stored.clone();
+        stock.apply(&mut variant);
+        let mut patch = serde_json::to_value(&variant)?;
+        if let Value::Object(fields) = &mut patch {
+            for key in [
+                "_id",
+                "_key",
+                "_from",
+                "_to",
+                "purchase_price",
+                "list_price",
+                "sale_price",
+            ] {
+                fields.remove(key);
+            }
+        }
+        let changed = serde_json::to_value(&stored)? != serde_json::to_value(&variant)?;
+
+        let mut prices = stored.get_prices();
+        let reprice = stock.list_price.is_some() && stock.list_price != prices.list_price;
+        prices.list_price = stock.list_price.or(prices.list_price);
+
+        let inventory: Option<Inventory> = get_document(engine, stored.get_dest()).await?;
+        let recount = match (stock.count, &inventory) {
+            (Some(count), Some(inventory)) => count != inventory.get_count(),
+            _ => false,
+        };
+
+        let mut queries = Vec::new();
+        if changed {
+            queries.push(
+                AqlQuery::builder()
+                    .query(UPDATE_DOCUMENT)
+                    .bind_var("@collection", Variant::collection_name())
+                    .bind_var("id", id.as_str())
+                    .bind_var("patch", patch)
+                    .build(),
+            );
+        }
+        if reprice {
+            let mut change = PriceChange::new(prices);
+            change.actor(REFERENCE);
+            queries.push(set_prices_query(&id, &change)?);
+        }
+        if let (true, Some(count)) = (recount, stock.count) {
+            queries.push(set_stock_query(stored.get_dest(), count, &movement())?);
+        }
+        // The variant, its prices and its count change together or not at all
+        if !options.dry_run && !queries.is_empty() {
+            let write = vec![
+                Variant::collection_name().to_string(),
+                PriceChange::collection_name().to_string(),
+                Inventory::collection_name().to_string(),
+                StockMovement::collection_name().to_string(),
+            ];
+            in_transaction::<Variant>(engine, write, queries, &id).await?;
+        }
+        return Ok(Some(if changed || reprice || recount {
+            Outcome::Updated
+        } else {
+            Outcome::Skipped
+        }));
+    }
+
+    let reference = match row.required("album") {
+        Some(reference) => reference,
+        None => return Ok(None),
+    };
+    // Barcodes are matched in their normalised form
+    let reference = Barcode::new(&reference)
+        .map(String::from)
+        .unwrap_or(reference);
+    let aql = AqlQuery::builder()
+        .query(FIND_ALBUM_REF)
+        .bind_var("@collection", Album::collection_name())
+        .bind_var("ref", reference.as_str())
+        .build();
+    let album: Option<Album> = engine.db().aql_query(aql).await?.pop();
+    let album = match album {
+        Some(album) => album,
+        None => {
+            row.error(Some("album"), format!("{} not found", reference));
+            return Ok(None);
+        }
+    };
+    if !row.errors.is_empty() {
+        return Ok(None);
+    }
+
+    let mut variant = Variant::new();
+    stock.apply(&mut variant);
+    if let Some(price) = stock.list_price {
+        let mut prices = variant.get_prices();
+        prices.list_price = Some(price);
+        variant.prices(prices);
+    }
+    if !options.dry_run {
+        create_inventory_variant(
+            engine,
+            &album,
+            variant,
+            stock.count.unwrap_or(0),
+            movement(),
+        )
+        .await?;
+    }
+    Ok(Some(Outcome::Created))
+}
+
+#[derive(Deserialize)]
+struct AlbumRow {
+    album: Album,
+    artists: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct StockExport {
+    album: Album,
+    artists: Vec<String>,
+    variant: Variant,
+    inventory: Inventory,
+}
+
+fn join_ids(ids: &[ExternalId]) -> String {
+    ids.iter()
+        .map(ExternalId::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Writes every stored album, artist or variant as a sheet with a header,
+/// returns the number of rows written.
+pub async fn export<W: Write>(
+    engine: &ArangoDb,
+    kind: CsvKind,
+    out: &mut W,
+    options: &CsvOptions,
+) -> Result<u64, EngineError> {
+    let header: Vec<String> = kind.fields().iter().map(|f| options.header_of(f)).collect();
+    write_record(out, &header, options.delimiter)?;
+
+    let rows: Vec<Vec<String>> = match kind {
+        CsvKind::Album => {
+            let aql = AqlQuery::builder()
+                .query(EXPORT_ALBUMS)
+                .bind_var("@album", Album::collection_name())
+                .bind_var("@artist_to", ARTIST_TO)
+                .build();
+            let albums: Vec<AlbumRow> = engine.db().aql_query(aql).await?;
+            albums
+                .into_iter()
+                .map(|AlbumRow { album, artists }| {
+                    vec![
+                        album.key().into(),
+                        album.get_name().to_string(),
+                        artists.join("; "),
+                        album.get_description().to_string(),
+                        album.get_barcode().map(String::from).unwrap_or_default(),
+                        join_ids(album.get_external_ids()),
+                    ]
+                })
+                .collect()
+        }
+        CsvKind::Artist => {
+            let aql = AqlQuery::builder()
+                .query(GET_ALL)
+                .bind_var("@collection", Artist::collection_name())
+                .build();
+            let mut artists: Vec<Artist> = engine.db().aql_query(aql).await?;
+            artists.sort_by_cached_key(|a| a.get_search_key());
+            artists
+                .into_iter()
+                .map(|artist| {
+                    vec![
+                        artist.key().into(),
+                        artist.get_name().to_string(),
+                        artist.get_aliases().join("; "),
+                        artist.get_profile().to_string(),
+                        join_ids(artist.get_external_ids()),
+                    ]
+                })
+                .collect()
+        }
+        CsvKind::Stock => {
+            let aql = AqlQuery::builder()
+                .query(EXPORT_STOCK)
+                .bind_var("@album", Album::collection_name())
+                .bind_var("@artist_to", ARTIST_TO)
+                .bind_var("@variant", Variant::collection_name())
+                .build();
+            let stock: Vec<StockExport> = engine.db().aql_query(aql).await?;
+            stock.into_iter().map(stock_row).collect()
+        }
+    };
+
+    for row in &rows {
+        write_record(out, row, options.delimiter)?;
+    }
+    Ok(rows.len() as u64)
+}
+
+fn stock_row(stock: StockExport) -> Vec<String> {
+    let StockExport {
+        album,
+        artists,
+        variant,
+        inventory,
+    } = stock;
+    let format = variant.get_format();
+    vec![
+        album.key().into(),
+        album.get_name().to_string(),
+        artists.join("; "),
+        album.get_barcode().map(String::from).unwrap_or_default(),
+        variant.key().into(),
+        format.to_string(),
+        format.get_medium().as_str().to_string(),
+        format
+            .get_size()
+            .map(|s| s.as_str().to_string())
+            .unwrap_or_default(),
+        format
+            .get_speed()
+            .map(|s| s.as_str().to_string())
+            .unwrap_or_default(),
+        format.get_discs().to_string(),
+        format.get_colour().unwrap_or_default().to_string(),
+        variant.get_media_grade().abbreviation().to_string(),
+        String::from(variant.get_sleeve_grade()),
+        inventory.get_count().to_string(),
+        variant
+            .get_prices()
+            .list_price
+            .map(|p| p.to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::db::test::common;
+    use crate::import::csv::*;
+    use crate::io::read::EngineGet;
+
+    fn records(sheet: &str, delimiter: char) -> Vec<(usize, Vec<String>)> {
+        CsvReader::new(sheet.as_bytes(), delimiter)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_read() {
+        let sheet = "\u{feff}name,description\r\n\r\n\"Owl House\",\"says \"\"hoot\"\"\"\n\"two\nlines\",x,\n";
+        let rows = records(sheet, ',');
+        assert_eq!(
+            rows[0],
+            (1, vec!["name".to_string(), "description".to_string()])
+        );
+        assert_eq!(rows[1].0, 3);
+        assert_eq!(rows[1].1, vec!["Owl House", "says \"hoot\""]);
+        assert_eq!(
+            rows[2],
+            (
+                4,
+                vec!["two\nlines".to_string(), "x".to_string(), String::new()]
+            )
+        );
+
+        assert_eq!(records("a;b", ';')[0].1, vec!["a", "b"]);
+        assert!(CsvReader::new("\"open".as_bytes(), ',')
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_write() {
+        let mut out = Vec::new();
+        let values = vec![
+            "plain".to_string(),
+            "a,b".to_string(),
+            "say \"hi\"".to_string(),
+        ];
+        write_record(&mut out, &values, ',').unwrap();
+        let line = String::from_utf8(out).unwrap();
+        assert_eq!(line, "plain,\"a,b\",\"say \"\"hi\"\"\"\n");
+        assert_eq!(records(&line, ',')[0].1, values);
+    }
+
+    #[test]
+    fn test_columns() {
+        let mut options = CsvOptions::new();
+        options.column("name", Column::Header("Title".to_string()));
+        let header: Vec<String> = vec!["Title".into(), "Media Grade".into()];
+        assert!(is_header(CsvKind::Album, &options, &header));
+        assert!(!is_header(
+            CsvKind::Album,
+            &options,
+            &["Owl House".to_string()]
+        ));
+
+        let columns = resolve_columns(CsvKind::Stock, &options, Some(&header)).unwrap();
+        assert_eq!(columns.get("media_grade"), Some(&1));
+        assert_eq!(columns.get("count"), None);
+
+        let columns = resolve_columns(CsvKind::Album, &options, Some(&header)).unwrap();
+        assert_eq!(columns.get("name"), Some(&0));
+        assert!(resolve_columns(CsvKind::Album, &options, None).is_err());
+
+        let columns = resolve_columns(CsvKind::Artist, &CsvOptions::new(), None).unwrap();
+        assert_eq!(columns.get("profile"), Some(&3));
+    }
+
+    #[test]
+    fn test_row_errors() {
+        let columns = resolve_columns(CsvKind::Stock, &CsvOptions::new(), None).unwrap();
+        let mut cells = vec![String::new(); CsvKind::Stock.fields().len()];
+        cells[7] = "12\"".to_string();
+        cells[8] = "33 1/3".to_string();
+        cells[11] = "VG+".to_string();
+        cells[13] = "-1".to_string();
+        cells[14] = "12.99".to_string();
+        let mut row = Row {
+            line: 7,
+            cells,
+            columns: &columns,
+            errors: Vec::new(),
+        };
+        let stock = StockRow::read(&mut row);
+        assert_eq!(stock.size, Some(Size::Twelve));
+        assert_eq!(stock.speed, Some(Speed::Rpm33));
+        assert_eq!(stock.media_grade, Some(Grade::VeryGoodPlus));
+        let fields: Vec<_> = row.errors.iter().map(|e| e.field.as_deref()).collect();
+        assert_eq!(fields, vec![Some("count"), Some("list_price")]);
+        assert!(row.errors[0].to_string().starts_with("line 7, count:"));
+        assert_eq!(row.required("album"), None);
+    }
+
+    #[tokio::test]
+    async fn test_rename() -> Result<(), EngineError> {
+        let db = common().await?;
+        let options = CsvOptions::new();
+
+        let key = uuid::Uuid::new_v4().to_string();
+        let sheet = format!("key,name\n{},Owl House {}\n", key, key);
+        import(&db, CsvKind::Album, sheet.as_bytes(), &options).await?;
+        let sheet = format!("key,name\n{},The Boiling Isles {}\n", key, key);
+        let report = import(&db, CsvKind::Album, sheet.as_bytes(), &options).await?;
+        assert_eq!(report.rows.updated, 1);
+
+        let album: Album = db.find("name", &format!("boiling isles {}", key)).await?;
+        assert_eq!(album.key().as_str(), key);
+        Ok(())
+    }
+}
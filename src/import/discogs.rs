@@ -0,0 +1,632 @@
+//! Import of the Discogs monthly data dumps and release JSON.
+//!
+//! The XML dumps hold one of `<artists>`, `<labels>` or `<releases>` and are read
+//! a record at a time so the multi gigabyte release dump never has to fit in memory.
+//! Release JSON, as returned by the Discogs API, is read as a stream of objects
+//! so a single release or a file with one release per line can be imported.
+//!
+//! Import artists and labels before releases, releases are only linked to the
+//! artists and labels that are already stored.
+
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::engine::db::ArangoDb;
+use crate::engine::EngineError;
+use crate::import::xml::{Node, RecordReader};
+use crate::import::{
+    failed, find_by_external_id, open, store_release, upsert_by_external_ids, ImportError,
+    ImportReport, Outcome, Release,
+};
+use crate::models::album::Album;
+use crate::models::artist::Artist;
+use crate::models::barcode::Barcode;
+use crate::models::external_id::ExternalId;
+use crate::models::format::{Descriptor, Format, Medium, Size, Speed};
+use crate::models::label::Label;
+use crate::models::track::{parse_duration, Track};
+use crate::models::DocDetails;
+use crate::service::label as label_service;
+
+/// Artist as given in the artists dump
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct DiscogsArtist {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub profile: String,
+    /// Other spellings of the name, stored as aliases
+    #[serde(default)]
+    pub namevariations: Vec<String>,
+}
+
+/// Label as given in the labels dump
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct DiscogsLabel {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub profile: String,
+    /// Discogs id of the label this is a sub label of
+    #[serde(default)]
+    pub parent_id: Option<u64>,
+}
+
+/// Release as given in the releases dump or as release JSON
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct DiscogsRelease {
+    pub id: u64,
+    pub title: String,
+    #[serde(default)]
+    pub artists: Vec<ArtistRef>,
+    #[serde(default)]
+    pub labels: Vec<LabelRef>,
+    #[serde(default)]
+    pub formats: Vec<DiscogsFormat>,
+    #[serde(default)]
+    pub tracklist: Vec<DiscogsTrack>,
+    #[serde(default)]
+    pub identifiers: Vec<Identifier>,
+    #[serde(default)]
+    pub notes: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct ArtistRef {
+    pub id: u64,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct LabelRef {
+    pub id: u64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub catno: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct DiscogsFormat {
+    pub name: String,
+    /// Number of discs, a string in both the dumps and JSON
+    #[serde(default)]
+    pub qty: String,
+    #[serde(default)]
+    pub descriptions: Vec<String>,
+    /// Free text, usually the colour of the vinyl
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct DiscogsTrack {
+    #[serde(default)]
+    pub position: String,
+    pub title: String,
+    /// Written as `m:ss`, blank when unknown
+    #[serde(default)]
+    pub duration: String,
+    /// `track`, `heading` or `index`
+    #[serde(default, rename = "type_")]
+    pub kind: String,
+    #[serde(default)]
+    pub sub_tracks: Vec<DiscogsTrack>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct Identifier {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: String,
+}
+
+fn id_of(node: &Node, field: &str) -> Result<u64, ImportError> {
+    node.text_of(field)
+        .or_else(|| node.attr(field))
+        .and_then(|id| id.trim().parse().ok())
+        .ok_or_else(|| ImportError::Malformed(format!("<{}> without a valid {}", node.name, field)))
+}
+
+fn text(node: &Node, field: &str) -> String {
+    node.text_of(field).unwrap_or_default().to_string()
+}
+
+impl DiscogsArtist {
+    fn from_node(node: &Node) -> Result<Self, ImportError> {
+        Ok(DiscogsArtist {
+            id: id_of(node, "id")?,
+            name: text(node, "name"),
+            profile: text(node, "profile"),
+            namevariations: node
+                .child("namevariations")
+                .map(|n| {
+                    n.children("name")
+                        .map(|n| n.text.trim().to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn to_artist(&self) -> Artist {
+        let mut artist = Artist::new();
+        artist
+            .name(&self.name)
+            .profile(self.profile.clone())
+            .external_id(ExternalId::discogs(self.id));
+        for name in &self.namevariations {
+            artist.alias(name);
+        }
+        artist
+    }
+}
+
+impl DiscogsLabel {
+    fn from_node(node: &Node) -> Result<Self, ImportError> {
+        let parent_id = match node.child("parentLabel") {
+            Some(parent) => Some(id_of(parent, "id")?),
+            None => None,
+        };
+        Ok(DiscogsLabel {
+            id: id_of(node, "id")?,
+            name: text(node, "name"),
+            profile: text(node, "profile"),
+            parent_id,
+        })
+    }
+
+    /// The label without its parent, which has to be looked up by its external id
+    pub fn to_label(&self) -> Label {
+        let mut label = Label::new();
+        label
+            .name(&self.name)
+            .profile(self.profile.clone())
+            .external_id(ExternalId::discogs(self.id));
+        label
+    }
+}
+
+impl DiscogsTrack {
+    fn from_node(node: &Node) -> Self {
+        DiscogsTrack {
+            position: text(node, "position"),
+            title: text(node, "title"),
+            duration: text(node, "duration"),
+            kind: String::new(),
+            sub_tracks: node
+                .child("sub_tracks")
+                .map(|n| n.children("track").map(DiscogsTrack::from_node).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn to_track(&self) -> Track {
+        if self.kind == "heading" {
+            return Track::heading(self.title.clone());
+        }
+        let mut track = Track::new(self.position.clone(), self.title.clone());
+        if let Ok(seconds) = parse_duration(&self.duration) {
+            track.duration(seconds);
+        }
+        for sub in &self.sub_tracks {
+            track.sub_track(sub.to_track());
+        }
+        track
+    }
+}
+
+impl DiscogsFormat {
+    /// Fails for formats without a `Medium` i.e. `File` or `DVD`
+    pub fn to_format(&self) -> Result<Format, ImportError> {
+        let medium = match self.name.as_str() {
+            "Vinyl" => Medium::Vinyl,
+            "Shellac" => Medium::Shellac,
+            "CD" | "CDr" => Medium::CD,
+            "Cassette" => Medium::Cassette,
+            "Reel-To-Reel" => Medium::ReelToReel,
+            "Minidisc" | "MiniDisc" => Medium::MiniDisc,
+            name => return Err(ImportError::Malformed(format!("unknown format {:?}", name))),
+        };
+        let mut format = Format::new(medium);
+        if let Ok(discs) = self.qty.trim().parse() {
+            format.discs(discs);
+        }
+        for description in &self.descriptions {
+            match description.as_str() {
+                "7\"" => format.size(Size::Seven),
+                "10\"" => format.size(Size::Ten),
+                "12\"" => format.size(Size::Twelve),
+                "33 ⅓ RPM" => format.speed(Speed::Rpm33),
+                "45 RPM" => format.speed(Speed::Rpm45),
+                "78 RPM" => format.speed(Speed::Rpm78),
+                "Limited Edition" => format.limited(None, None),
+                _ => format.descriptor(Descriptor::from(description.clone())),
+            };
+        }
+        if !self.text.trim().is_empty() {
+            format.colour(self.text.trim().to_string());
+        }
+        Ok(format)
+    }
+}
+
+impl DiscogsRelease {
+    fn from_node(node: &Node) -> Result<Self, ImportError> {
+        let artists = match node.child("artists") {
+            Some(artists) => artists
+                .children("artist")
+                .map(|a| {
+                    Ok(ArtistRef {
+                        id: id_of(a, "id")?,
+                        name: text(a, "name"),
+                    })
+                })
+                .collect::<Result<_, ImportError>>()?,
+            None => Vec::new(),
+        };
+        let labels = match node.child("labels") {
+            Some(labels) => labels
+                .children("label")
+                .map(|l| {
+                    Ok(LabelRef {
+                        id: id_of(l, "id")?,
+                        name: l.attr("name").unwrap_or_default().to_string(),
+                        catno: l.attr("catno").unwrap_or_default().to_string(),
+                    })
+                })
+                .collect::<Result<_, ImportError>>()?,
+            None => Vec::new(),
+        };
+        let formats = node
+            .child("formats")
+            .map(|f| {
+                f.children("format")
+                    .map(|f| DiscogsFormat {
+                        name: f.attr("name").unwrap_or_default().to_string(),
+                        qty: f.attr("qty").unwrap_or_default().to_string(),
+                        descriptions: f
+                            .child("descriptions")
+                            .map(|d| d.children("description").map(|d| d.text.clone()).collect())
+                            .unwrap_or_default(),
+                        text: f.attr("text").unwrap_or_default().to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let identifiers = node
+            .child("identifiers")
+            .map(|i| {
+                i.children("identifier")
+                    .map(|i| Identifier {
+                        kind: i.attr("type").unwrap_or_default().to_string(),
+                        value: i.attr("value").unwrap_or_default().to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(DiscogsRelease {
+            id: id_of(node, "id")?,
+            title: text(node, "title"),
+            artists,
+            labels,
+            formats,
+            tracklist: node
+                .child("tracklist")
+                .map(|t| t.children("track").map(DiscogsTrack::from_node).collect())
+                .unwrap_or_default(),
+            identifiers,
+            notes: text(node, "notes"),
+        })
+    }
+
+    /// First identifier of type `Barcode` that is a valid barcode
+    pub fn barcode(&self) -> Option<Barcode> {
+        self.identifiers
+            .iter()
+            .filter(|i| i.kind == "Barcode")
+            .find_map(|i| Barcode::new(&i.value).ok())
+    }
+
+    pub fn to_album(&self) -> Album {
+        let mut album = Album::new();
+        album
+            .name(&self.title)
+            .description(self.notes.clone())
+            .external_id(ExternalId::discogs(self.id))
+            .tracklist(self.tracklist.iter().map(DiscogsTrack::to_track).collect());
+        if let Some(barcode) = self.barcode() {
+            album.barcode(barcode);
+        }
+        album
+    }
+
+    fn to_release(&self) -> Result<Release, ImportError> {
+        Ok(Release {
+            album: self.to_album(),
+            format: self.to_format()?,
+            artists: self
+                .artists
+                .iter()
+                .map(|a| ExternalId::discogs(a.id))
+                .collect(),
+            labels: self
+                .labels
+                .iter()
+                .map(|l| (ExternalId::discogs(l.id), l.catno.clone()))
+                .collect(),
+        })
+    }
+
+    /// Format of the first listed format, the one stock is created for.
+    /// Fails when there is none or it is unknown.
+    pub fn to_format(&self) -> Result<Format, ImportError> {
+        match self.formats.first() {
+            Some(format) => format.to_format(),
+            None => Err(ImportError::Malformed("no format".to_string())),
+        }
+    }
+}
+
+/// Imports the `<artist>` records of an artists dump.
+pub async fn import_artists<R: BufRead + Send>(
+    engine: &ArangoDb,
+    input: R,
+) -> Result<ImportReport, EngineError> {
+    let mut report = ImportReport::default();
+    for node in RecordReader::new(input, "artist") {
+        let artist = match DiscogsArtist::from_node(&node?) {
+            Ok(artist) => artist,
+            Err(e) => {
+                failed(&mut report, "artist", e);
+                continue;
+            }
+        };
+        match upsert_by_external_ids(engine, &artist.to_artist()).await {
+            Ok((_, outcome)) => report.record(outcome),
+            Err(e) => failed(&mut report, &ExternalId::discogs(artist.id).to_string(), e),
+        }
+    }
+    Ok(report)
+}
+
+/// Imports the `<label>` records of a labels dump, then places each label
+/// under its parent. Parents are looked up once the whole dump is stored as a
+/// sub label may come before its parent.
+pub async fn import_labels<R: BufRead + Send>(
+    engine: &ArangoDb,
+    input: R,
+) -> Result<ImportReport, EngineError> {
+    let mut report = ImportReport::default();
+    let mut parents: Vec<(String, ExternalId)> = Vec::new();
+    for node in RecordReader::new(input, "label") {
+        let label = match DiscogsLabel::from_node(&node?) {
+            Ok(label) => label,
+            Err(e) => {
+                failed(&mut report, "label", e);
+                continue;
+            }
+        };
+        let mut doc = label.to_label();
+        let parent_id = match label.parent_id {
+            Some(parent) => find_by_external_id::<Label>(engine, &ExternalId::discogs(parent))
+                .await?
+                .map(|parent| parent.id()),
+            None => None,
+        };
+        if let Some(parent_id) = &parent_id {
+            doc.parent(parent_id.clone());
+        }
+
+        match upsert_by_external_ids(engine, &doc).await {
+            Ok((id, outcome)) => {
+                report.record(outcome);
+                // Skipped labels are already under their parent
+                let placed = parent_id.is_some() && outcome == Outcome::Skipped;
+                if let (Some(parent), false) = (label.parent_id, placed) {
+                    parents.push((id, ExternalId::discogs(parent)));
+                }
+            }
+            Err(e) => failed(&mut report, &ExternalId::discogs(label.id).to_string(), e),
+        }
+    }
+
+    for (label_id, parent_fk) in parents {
+        match find_by_external_id::<Label>(engine, &parent_fk).await? {
+            Some(parent) => {
+                if let Err(e) =
+                    label_service::set_parent(engine, &label_id, Some(&parent.id())).await
+                {
+                    log::warn!(
+                        "Could not place Discogs label {} under {}: {}",
+                        label_id,
+                        parent_fk,
+                        e
+                    );
+                }
+            }
+            None => log::warn!("Parent {} of label {} is not stored", parent_fk, label_id),
+        }
+    }
+    Ok(report)
+}
+
+/// Imports the `<release>` records of a releases dump.
+pub async fn import_releases<R: BufRead + Send>(
+    engine: &ArangoDb,
+    input: R,
+) -> Result<ImportReport, EngineError> {
+    let mut report = ImportReport::default();
+    for node in RecordReader::new(input, "release") {
+        match DiscogsRelease::from_node(&node?) {
+            Ok(release) => import_release(engine, &release, &mut report).await,
+            Err(e) => failed(&mut report, "release", e),
+        }
+    }
+    Ok(report)
+}
+
+/// Imports release JSON, either a single release or one release per line.
+/// Releases are read as any JSON first, so one missing a field doesn't end the stream.
+pub async fn import_release_json<R: BufRead + Send>(
+    engine: &ArangoDb,
+    input: R,
+) -> Result<ImportReport, EngineError> {
+    let mut report = ImportReport::default();
+    let stream = serde_json::Deserializer::from_reader(input).into_iter::<serde_json::Value>();
+    for value in stream {
+        // The stream can't be resumed after a syntax error
+        let value = value.map_err(ImportError::from)?;
+        match serde_json::from_value::<DiscogsRelease>(value) {
+            Ok(release) => import_release(engine, &release, &mut report).await,
+            Err(e) => failed(&mut report, "release", e),
+        }
+    }
+    Ok(report)
+}
+
+/// Imports a dump file, choosing what it holds from its name as Discogs names
+/// them i.e. `discogs_20240101_releases.xml.gz`. `.json` files are release JSON.
+pub async fn import_dump(
+    engine: &ArangoDb,
+    path: impl AsRef<Path>,
+) -> Result<ImportReport, EngineError> {
+    let path = path.as_ref();
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let input = open(path)?;
+
+    if name.ends_with(".json") || name.ends_with(".json.gz") {
+        import_release_json(engine, input).await
+    } else if name.contains("artists") {
+        import_artists(engine, input).await
+    } else if name.contains("labels") {
+        import_labels(engine, input).await
+    } else if name.contains("releases") {
+        import_releases(engine, input).await
+    } else {
+        Err(ImportError::Malformed(format!("can't tell what {} holds", name)).into())
+    }
+}
+
+async fn import_release(engine: &ArangoDb, release: &DiscogsRelease, report: &mut ImportReport) {
+    let stored = match release.to_release() {
+        Ok(release) => store_release(engine, release, "discogs import").await,
+        Err(e) => Err(e.into()),
+    };
+    match stored {
+        Ok(outcome) => report.record(outcome),
+        Err(e) => failed(report, &ExternalId::discogs(release.id).to_string(), e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::db::test::common;
+    use crate::import::discogs::*;
+    use crate::models::track::TrackKind;
+
+    #[test]
+    fn test_read_xml() {
+        let artists = r#"<artists><artist><id>1</id><name>The Persuader</name>
+            <profile>Swedish techno</profile>
+            <namevariations><name>Persuader</name><name>The Presuader</name></namevariations>
+            </artist></artists>"#;
+        let artist = RecordReader::new(artists.as_bytes(), "artist")
+            .map(|n| DiscogsArtist::from_node(&n.unwrap()).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(artist.namevariations, vec!["Persuader", "The Presuader"]);
+        let artist = artist.to_artist();
+        assert_eq!(artist.get_name(), "The Persuader");
+        assert_eq!(artist.get_external_ids(), &[ExternalId::discogs(1)]);
+
+        let labels = r#"<labels><label><id>5</id><name>Svek</name>
+            <parentLabel id="9">Parent</parentLabel></label></labels>"#;
+        let label = RecordReader::new(labels.as_bytes(), "label")
+            .map(|n| DiscogsLabel::from_node(&n.unwrap()).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(label.parent_id, Some(9));
+
+        let releases = r#"<releases><release id="1" status="Accepted">
+            <artists><artist><id>1</id><name>The Persuader</name></artist></artists>
+            <title>Stockholm</title>
+            <labels><label name="Svek" catno="SK032" id="5"/></labels>
+            <formats><format name="Vinyl" qty="2" text="Red">
+                <descriptions><description>12"</description><description>33 ⅓ RPM</description></descriptions>
+            </format></formats>
+            <identifiers><identifier type="Barcode" value="0 75678-16512 2"/></identifiers>
+            <tracklist><track><position>A</position><title>Östermalm</title><duration>4:45</duration></track></tracklist>
+            </release></releases>"#;
+        let release = RecordReader::new(releases.as_bytes(), "release")
+            .map(|n| DiscogsRelease::from_node(&n.unwrap()).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(release.labels[0].catno, "SK032");
+        let album = release.to_album();
+        assert_eq!(album.get_name(), "Stockholm");
+        assert_eq!(album.get_barcode().unwrap().as_str(), "0075678165122");
+        assert_eq!(album.running_time(), 285);
+        let format = release.to_format().unwrap();
+        assert_eq!(format.get_discs(), 2);
+        assert_eq!(format.get_size(), Some(Size::Twelve));
+        assert_eq!(format.get_speed(), Some(Speed::Rpm33));
+        assert_eq!(format.get_colour(), Some("Red"));
+    }
+
+    #[test]
+    fn test_read_json() {
+        let json = r#"{"id": 2, "title": "Knockin' Boots", "artists": [{"id": 7, "name": "Mr. James Barth"}],
+            "labels": [{"id": 5, "name": "Svek", "catno": "SK 032"}],
+            "formats": [{"name": "CD", "qty": "1", "descriptions": ["Album", "Limited Edition"]}],
+            "tracklist": [{"position": "", "title": "Side A", "type_": "heading", "duration": ""},
+                {"position": "1", "title": "Suite", "type_": "index", "duration": "",
+                 "sub_tracks": [{"position": "1a", "title": "One", "type_": "track", "duration": "1:00"}]}]}
+            {"id": 3, "title": "Second"}"#;
+        let releases: Vec<DiscogsRelease> = serde_json::Deserializer::from_str(json)
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(releases.len(), 2);
+
+        let album = releases[0].to_album();
+        let tracks = album.get_tracklist();
+        assert_eq!(tracks[0].get_kind(), TrackKind::Heading);
+        assert_eq!(tracks[1].get_kind(), TrackKind::Index);
+        assert_eq!(album.running_time(), 60);
+        let format = releases[0].to_format().unwrap();
+        assert_eq!(format.get_medium(), Medium::CD);
+        assert!(format.has(&Descriptor::Album));
+        assert!(format.is_limited());
+        assert!(releases[1].to_format().is_err());
+
+        // Formats without a medium aren't taken for vinyl
+        let file = DiscogsFormat {
+            name: "File".to_string(),
+            ..DiscogsFormat::default()
+        };
+        assert!(file.to_format().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_release_json() -> Result<(), EngineError> {
+        let db = common().await?;
+
+        // The release without a title is failed, the ones either side imported
+        let json = r#"{"id": 90001, "title": "First", "formats": [{"name": "CD", "qty": "1"}]}
+            {"id": 90002}
+            {"id": 90003, "title": "Third", "formats": [{"name": "CD", "qty": "1"}]}"#;
+        let report = import_release_json(&db, json.as_bytes()).await?;
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.total(), 3);
+
+        let third: Option<Album> = find_by_external_id(&db, &ExternalId::discogs(90003)).await?;
+        assert_eq!(third.unwrap().get_name(), "Third");
+        Ok(())
+    }
+}
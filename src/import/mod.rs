@@ -0,0 +1,280 @@
+//! Importers reading catalogue data from external sources.
+//!
+//! Records are matched to stored documents by any of their external ids, so
+//! importing the same data twice, or the same release from two catalogues that
+//! know of each other, updates documents rather than duplicating them.
+
+use std::fmt::Formatter;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use arangors::AqlQuery;
+use flate2::read::GzDecoder;
+
+use serde_json::json;
+
+use crate::engine::db::arangodb::aql_snippet::{
+    ADD_EXTERNAL_IDS, FIND_EXTERNAL, IMPORT_CREDITS, IMPORT_RELEASES, UPSERT_EXTERNAL,
+};
+use crate::engine::db::ArangoDb;
+use crate::engine::error::Cause;
+use crate::engine::{DbError, EngineError};
+use crate::models::album::Album;
+use crate::models::artist::Artist;
+use crate::models::external_id::{ExternalId, ExternalIds};
+use crate::models::format::Format;
+use crate::models::id::DocId;
+use crate::models::label::Label;
+use crate::models::stock_movement::{Reason, StockMovement};
+use crate::models::variant::Variant;
+use crate::models::{DocDetails, ReqModelTraits};
+use crate::service::inventory::create_inventory_variant;
+use crate::service::label::{normalise_cat_no, RELEASED_ON};
+
+pub mod csv;
+pub mod discogs;
+pub mod musicbrainz;
+mod xml;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ImportError {
+    Io(std::io::Error),
+    Xml(quick_xml::Error),
+    Json(serde_json::Error),
+    /// A record is missing data it can't be imported without
+    Malformed(String),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "Could not read import: {}", e),
+            ImportError::Xml(e) => write!(f, "Invalid XML: {}", e),
+            ImportError::Json(e) => write!(f, "Invalid JSON: {}", e),
+            ImportError::Malformed(reason) => write!(f, "Malformed record: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Records that can't be read are invalid input, failing to read the import at all is not
+impl From<ImportError> for EngineError {
+    fn from(e: ImportError) -> Self {
+        match e {
+            ImportError::Io(_) => EngineError::other(e),
+            _ => EngineError::Invalid(Cause::new(e)),
+        }
+    }
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<quick_xml::Error> for ImportError {
+    fn from(e: quick_xml::Error) -> Self {
+        ImportError::Xml(e)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(e: serde_json::Error) -> Self {
+        ImportError::Json(e)
+    }
+}
+
+/// Edge collection crediting an artist on an album
+pub const ARTIST_TO: &str = "artist_to";
+
+/// What happened to a single imported record
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Created,
+    Updated,
+    /// Already stored with the same data
+    Skipped,
+}
+
+/// Number of records created, updated and skipped by an import.
+/// Records that could not be imported are counted as `failed` and logged.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ImportReport {
+    pub created: u64,
+    pub updated: u64,
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+impl ImportReport {
+    pub fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Created => self.created += 1,
+            Outcome::Updated => self.updated += 1,
+            Outcome::Skipped => self.skipped += 1,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.created + self.updated + self.skipped + self.failed
+    }
+}
+
+impl std::ops::AddAssign for ImportReport {
+    fn add_assign(&mut self, other: Self) {
+        self.created += other.created;
+        self.updated += other.updated;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+    }
+}
+
+/// Opens a file for streaming, decompressing it on the fly when it ends in `.gz`
+/// as the Discogs dumps are distributed.
+pub fn open(path: impl AsRef<Path>) -> Result<Box<dyn BufRead + Send>, ImportError> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Upserted {
+    pub id: String,
+    pub outcome: Outcome,
+}
+
+/// Inserts `doc`, or replaces the stored document sharing any of its external ids
+/// keeping its `_id`, creation time and the external ids `doc` doesn't have.
+/// Only ids whose `Source::identifies` match documents, so albums of one release
+/// group are kept apart. Returns the `_id` of the stored document and whether anything changed.
+pub async fn upsert_by_external_ids<T: ReqModelTraits + ExternalIds>(
+    engine: &ArangoDb,
+    doc: &T,
+) -> Result<(String, Outcome), EngineError> {
+    let keys: Vec<&ExternalId> = doc
+        .external_ids()
+        .iter()
+        .filter(|id| id.source().identifies())
+        .collect();
+    if keys.is_empty() {
+        return DbError::InvalidIdentification.into();
+    }
+    doc.validate()?;
+    let aql = AqlQuery::builder()
+        .query(UPSERT_EXTERNAL)
+        .bind_var("@collection", T::collection_name())
+        .bind_var("keys", serde_json::to_value(&keys)?)
+        .bind_var("doc", serde_json::to_value(doc)?)
+        .build();
+
+    let resp: Option<Upserted> = engine.db().aql_query(aql).await?.pop();
+    match resp {
+        Some(stored) => Ok((stored.id, stored.outcome)),
+        None => DbError::FailedToCreate.into(),
+    }
+}
+
+/// The stored document known to another catalogue as `id`, if any.
+pub async fn find_by_external_id<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    id: &ExternalId,
+) -> Result<Option<T>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(FIND_EXTERNAL)
+        .bind_var("@collection", T::collection_name())
+        .bind_var("id", id.to_string())
+        .build();
+    Ok(engine.db().aql_query(aql).await?.pop())
+}
+
+/// Adds `ids` to the stored document known as `id`, returns `false` when no
+/// document is known as `id` or it already has them all.
+pub async fn add_external_ids<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    id: &ExternalId,
+    ids: &[ExternalId],
+) -> Result<bool, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(ADD_EXTERNAL_IDS)
+        .bind_var("@collection", T::collection_name())
+        .bind_var("id", id.to_string())
+        .bind_var("ids", serde_json::to_value(ids)?)
+        .build();
+    let updated: Vec<String> = engine.db().aql_query(aql).await?;
+    Ok(!updated.is_empty())
+}
+
+/// A release read from any catalogue, ready to be stored
+pub(crate) struct Release {
+    pub album: Album,
+    /// Format stock of a new release is created for
+    pub format: Format,
+    /// External ids of the credited artists
+    pub artists: Vec<ExternalId>,
+    /// External ids of the labels with the catalogue number on each
+    pub labels: Vec<(ExternalId, String)>,
+}
+
+/// Upserts a release and links it to its artists and labels that are already
+/// stored. New releases get a variant of `format` holding no stock, ready to be stocked.
+pub(crate) async fn store_release(
+    engine: &ArangoDb,
+    mut release: Release,
+    reference: &'static str,
+) -> Result<Outcome, EngineError> {
+    let (id, outcome) = upsert_by_external_ids(engine, &release.album).await?;
+
+    let artists: Vec<String> = release.artists.iter().map(ExternalId::to_string).collect();
+    let aql = AqlQuery::builder()
+        .query(IMPORT_CREDITS)
+        .bind_var("@artist", Artist::collection_name())
+        .bind_var("@collection", ARTIST_TO)
+        .bind_var("artists", serde_json::to_value(&artists)?)
+        .bind_var("album", id.as_str())
+        .bind_var("role", "artist")
+        .build();
+    let _: Vec<serde_json::Value> = engine.db().aql_query(aql).await?;
+
+    let labels: Vec<serde_json::Value> = release
+        .labels
+        .iter()
+        .map(|(label, cat_no)| {
+            json!({"external_id": label.to_string(), "cat_no": normalise_cat_no(cat_no)})
+        })
+        .collect();
+    let aql = AqlQuery::builder()
+        .query(IMPORT_RELEASES)
+        .bind_var("@label", Label::collection_name())
+        .bind_var("@collection", RELEASED_ON)
+        .bind_var("labels", serde_json::Value::from(labels))
+        .bind_var("album", id.as_str())
+        .build();
+    let _: Vec<serde_json::Value> = engine.db().aql_query(aql).await?;
+
+    if outcome == Outcome::Created {
+        release
+            .album
+            .change_id(DocId::<Album>::parse(id)?.key());
+        let mut variant = Variant::new();
+        variant.format(release.format);
+        let mut movement = StockMovement::new(Reason::Adjustment);
+        movement.reference(reference);
+        create_inventory_variant(engine, &release.album, variant, 0, movement).await?;
+    }
+    Ok(outcome)
+}
+
+/// Counts a record that could not be imported and logs why
+pub(crate) fn failed(report: &mut ImportReport, record: &str, e: impl std::fmt::Display) {
+    log::warn!("Could not import {}: {}", record, e);
+    report.failed += 1;
+}
@@ -0,0 +1,513 @@
+//! Import of MusicBrainz JSON, as returned by the web service or found in the
+//! JSON data dumps with one entity per line.
+//!
+//! Entities are stored with their MBID and, where MusicBrainz links them to
+//! Discogs, their Discogs id too, so an album already imported from Discogs is
+//! updated rather than stored twice. Releases are imported as albums, release
+//! groups add their MBID to the albums of their releases.
+//!
+//! Import artists and labels before releases, releases are only linked to the
+//! artists and labels that are already stored.
+
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::engine::db::ArangoDb;
+use crate::engine::EngineError;
+use crate::import::{
+    add_external_ids, failed, open, store_release, upsert_by_external_ids, ImportError,
+    ImportReport, Outcome, Release,
+};
+use crate::models::album::Album;
+use crate::models::artist::Artist;
+use crate::models::barcode::Barcode;
+use crate::models::external_id::ExternalId;
+use crate::models::format::{Format, Medium, Size};
+use crate::models::label::Label;
+use crate::models::track::Track;
+
+/// Entity held by a MusicBrainz JSON file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Entity {
+    Artist,
+    Label,
+    Release,
+    ReleaseGroup,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbArtist {
+    pub id: String,
+    pub name: String,
+    #[serde(default, rename = "sort-name")]
+    pub sort_name: String,
+    #[serde(default)]
+    pub disambiguation: String,
+    #[serde(default)]
+    pub aliases: Vec<MbAlias>,
+    #[serde(default)]
+    pub relations: Vec<MbRelation>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbAlias {
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbLabel {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub disambiguation: String,
+    #[serde(default)]
+    pub relations: Vec<MbRelation>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbReleaseGroup {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    /// Only present when requested with `inc=releases`
+    #[serde(default)]
+    pub releases: Vec<MbRef>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbRelease {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub barcode: Option<String>,
+    #[serde(default)]
+    pub disambiguation: String,
+    #[serde(default)]
+    pub annotation: Option<String>,
+    #[serde(default, rename = "artist-credit")]
+    pub artist_credit: Vec<MbCredit>,
+    #[serde(default, rename = "label-info")]
+    pub label_info: Vec<MbLabelInfo>,
+    #[serde(default)]
+    pub media: Vec<MbMedium>,
+    #[serde(default, rename = "release-group")]
+    pub release_group: Option<MbRef>,
+    #[serde(default)]
+    pub relations: Vec<MbRelation>,
+}
+
+/// Any entity given only by its MBID
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbRef {
+    pub id: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbCredit {
+    #[serde(default)]
+    pub name: String,
+    pub artist: MbRef,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbLabelInfo {
+    #[serde(default, rename = "catalog-number")]
+    pub catalog_number: Option<String>,
+    #[serde(default)]
+    pub label: Option<MbRef>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbMedium {
+    /// i.e. `12" Vinyl` or `CD`, missing when unknown
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub tracks: Vec<MbTrack>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbTrack {
+    /// Position as printed on the medium i.e. `A1`
+    #[serde(default)]
+    pub number: String,
+    pub title: String,
+    /// Running time in milliseconds
+    #[serde(default)]
+    pub length: Option<u64>,
+}
+
+/// Link to another entity or a url, only Discogs urls are used
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbRelation {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub url: Option<MbUrl>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct MbUrl {
+    pub resource: String,
+}
+
+fn mbid(id: &str) -> Result<ExternalId, ImportError> {
+    ExternalId::musicbrainz(id).map_err(|_| ImportError::Malformed(format!("invalid MBID {}", id)))
+}
+
+/// Discogs ids of the `discogs` url relations to `entity` pages,
+/// i.e. `https://www.discogs.com/release/249504-Rick-Astley`
+fn discogs_ids(relations: &[MbRelation], entity: &str) -> Vec<ExternalId> {
+    let segment = format!("/{}/", entity);
+    relations
+        .iter()
+        .filter(|r| r.kind == "discogs")
+        .filter_map(|r| r.url.as_ref())
+        .filter_map(|url| {
+            let (_, rest) = url.resource.split_once(&segment)?;
+            let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse().ok().map(ExternalId::discogs)
+        })
+        .collect()
+}
+
+impl MbArtist {
+    pub fn to_artist(&self) -> Result<Artist, ImportError> {
+        let mut artist = Artist::new();
+        artist
+            .name(&self.name)
+            .alias(&self.sort_name)
+            .profile(self.disambiguation.clone())
+            .external_id(mbid(&self.id)?);
+        for alias in &self.aliases {
+            artist.alias(&alias.name);
+        }
+        for id in discogs_ids(&self.relations, "artist") {
+            artist.external_id(id);
+        }
+        Ok(artist)
+    }
+}
+
+impl MbLabel {
+    pub fn to_label(&self) -> Result<Label, ImportError> {
+        let mut label = Label::new();
+        label
+            .name(&self.name)
+            .profile(self.disambiguation.clone())
+            .external_id(mbid(&self.id)?);
+        for id in discogs_ids(&self.relations, "label") {
+            label.external_id(id);
+        }
+        Ok(label)
+    }
+}
+
+impl MbMedium {
+    /// Fails for formats without a `Medium` i.e. `Digital Media` or `DVD`, and unknown ones
+    pub fn to_format(&self) -> Result<Format, ImportError> {
+        let name = self.format.as_deref().unwrap_or_default();
+        let medium = match name {
+            "Vinyl" | "7\" Vinyl" | "10\" Vinyl" | "12\" Vinyl" => Medium::Vinyl,
+            "CD" | "Enhanced CD" | "HDCD" | "CD-R" => Medium::CD,
+            "Cassette" => Medium::Cassette,
+            "Shellac" => Medium::Shellac,
+            "Reel-to-reel" => Medium::ReelToReel,
+            "MiniDisc" => Medium::MiniDisc,
+            _ => return Err(ImportError::Malformed(format!("unknown format {:?}", name))),
+        };
+        let mut format = Format::new(medium);
+        match name {
+            "7\" Vinyl" => format.size(Size::Seven),
+            "10\" Vinyl" => format.size(Size::Ten),
+            "12\" Vinyl" => format.size(Size::Twelve),
+            _ => &mut format,
+        };
+        Ok(format)
+    }
+}
+
+impl MbRelease {
+    /// Positions are prefixed with the medium number when there are several, as `2-05`
+    fn tracklist(&self) -> Vec<Track> {
+        let several = self.media.len() > 1;
+        self.media
+            .iter()
+            .enumerate()
+            .flat_map(|(i, medium)| {
+                medium.tracks.iter().map(move |t| {
+                    let position = if several {
+                        format!("{}-{}", i + 1, t.number)
+                    } else {
+                        t.number.clone()
+                    };
+                    let mut track = Track::new(position, t.title.clone());
+                    if let Some(ms) = t.length {
+                        track.duration(((ms + 500) / 1000) as u32);
+                    }
+                    track
+                })
+            })
+            .collect()
+    }
+
+    pub fn to_album(&self) -> Result<Album, ImportError> {
+        let mut album = Album::new();
+        let description = match &self.annotation {
+            Some(annotation) if !annotation.trim().is_empty() => annotation.clone(),
+            _ => self.disambiguation.clone(),
+        };
+        album
+            .name(&self.title)
+            .description(description)
+            .external_id(mbid(&self.id)?)
+            .tracklist(self.tracklist());
+        if let Some(group) = &self.release_group {
+            album.external_id(ExternalId::musicbrainz_group(&group.id).map_err(|_| {
+                ImportError::Malformed(format!("invalid release group MBID {}", group.id))
+            })?);
+        }
+        for id in discogs_ids(&self.relations, "release") {
+            album.external_id(id);
+        }
+        if let Some(barcode) = self.barcode.as_deref().and_then(|b| Barcode::new(b).ok()) {
+            album.barcode(barcode);
+        }
+        Ok(album)
+    }
+
+    /// Format of the first medium with the number of media as discs.
+    /// Fails when there is none or its format is unknown.
+    pub fn to_format(&self) -> Result<Format, ImportError> {
+        let mut format = match self.media.first() {
+            Some(medium) => medium.to_format()?,
+            None => return Err(ImportError::Malformed("no media".to_string())),
+        };
+        if self.media.len() > 1 {
+            format.discs(self.media.len() as u16);
+        }
+        Ok(format)
+    }
+
+    fn to_release(&self) -> Result<Release, ImportError> {
+        Ok(Release {
+            album: self.to_album()?,
+            format: self.to_format()?,
+            artists: self
+                .artist_credit
+                .iter()
+                .map(|credit| mbid(&credit.artist.id))
+                .collect::<Result<_, _>>()?,
+            labels: self
+                .label_info
+                .iter()
+                .filter_map(|info| {
+                    let label = info.label.as_ref()?;
+                    let cat_no = info.catalog_number.clone().unwrap_or_default();
+                    Some(mbid(&label.id).map(|id| (id, cat_no)))
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Imports every entity of a JSON file of one kind of entity, either a single
+/// entity or one per line.
+pub async fn import<R: BufRead + Send>(
+    engine: &ArangoDb,
+    entity: Entity,
+    input: R,
+) -> Result<ImportReport, EngineError> {
+    let mut report = ImportReport::default();
+    let stream = serde_json::Deserializer::from_reader(input).into_iter::<serde_json::Value>();
+    for value in stream {
+        let value = value.map_err(ImportError::from)?;
+        let record = value
+            .get("id")
+            .and_then(|id| id.as_str())
+            .map(|id| format!("musicbrainz {}", id))
+            .unwrap_or_else(|| "musicbrainz record".to_string());
+        match import_entity(engine, entity, value).await {
+            Ok(outcome) => report.record(outcome),
+            Err(e) => failed(&mut report, &record, e),
+        }
+    }
+    Ok(report)
+}
+
+/// Imports a JSON file, choosing the entity it holds from its name as in the
+/// data dumps i.e. `release-group` or `artist.json`.
+pub async fn import_dump(
+    engine: &ArangoDb,
+    path: impl AsRef<Path>,
+) -> Result<ImportReport, EngineError> {
+    let path = path.as_ref();
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let entity = if name.contains("release-group") || name.contains("release_group") {
+        Entity::ReleaseGroup
+    } else if name.contains("release") {
+        Entity::Release
+    } else if name.contains("artist") {
+        Entity::Artist
+    } else if name.contains("label") {
+        Entity::Label
+    } else {
+        return Err(ImportError::Malformed(format!("can't tell what {} holds", name)).into());
+    };
+    import(engine, entity, open(path)?).await
+}
+
+async fn import_entity(
+    engine: &ArangoDb,
+    entity: Entity,
+    value: serde_json::Value,
+) -> Result<Outcome, EngineError> {
+    match entity {
+        Entity::Artist => {
+            let artist: MbArtist = serde_json::from_value(value)?;
+            Ok(upsert_by_external_ids(engine, &artist.to_artist()?)
+                .await?
+                .1)
+        }
+        Entity::Label => {
+            let label: MbLabel = serde_json::from_value(value)?;
+            Ok(upsert_by_external_ids(engine, &label.to_label()?).await?.1)
+        }
+        Entity::Release => {
+            let release: MbRelease = serde_json::from_value(value)?;
+            store_release(engine, release.to_release()?, "musicbrainz import").await
+        }
+        Entity::ReleaseGroup => {
+            let group: MbReleaseGroup = serde_json::from_value(value)?;
+            let group_id = ExternalId::musicbrainz_group(&group.id)?;
+            let mut outcome = Outcome::Skipped;
+            for release in &group.releases {
+                if add_external_ids::<Album>(
+                    engine,
+                    &mbid(&release.id)?,
+                    std::slice::from_ref(&group_id),
+                )
+                .await?
+                {
+                    outcome = Outcome::Updated;
+                }
+            }
+            Ok(outcome)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use crate::engine::db::test::common;
+    use crate::import::find_by_external_id;
+    use crate::import::musicbrainz::*;
+    use crate::models::external_id::{ExternalIds, Source};
+    use crate::models::DocDetails;
+
+    const RELEASE: &str = r#"{
+        "id": "b10bbbfc-cf9e-42e0-be17-e2c3e1d2600d",
+        "title": "Stockholm",
+        "barcode": "075678165122",
+        "artist-credit": [{"name": "The Persuader", "artist": {"id": "f27ec8db-af05-4f36-916e-3d57f91ecf5e"}}],
+        "label-info": [{"catalog-number": "SK032", "label": {"id": "46f0f4cd-8aab-4b33-b698-f459faf64190"}},
+            {"catalog-number": null, "label": null}],
+        "media": [{"format": "12\" Vinyl", "tracks": [{"number": "A", "title": "Östermalm", "length": 284600}]},
+            {"format": "12\" Vinyl", "tracks": [{"number": "B", "title": "Gamla Stan", "length": null}]}],
+        "release-group": {"id": "3bd76d40-7f0e-36b7-9348-91a33afee20e"},
+        "relations": [{"type": "discogs", "url": {"resource": "https://www.discogs.com/release/1-The-Persuader-Stockholm"}},
+            {"type": "amazon asin", "url": {"resource": "https://www.amazon.com/gp/product/B000"}}]
+    }"#;
+
+    #[test]
+    fn test_release() {
+        let release: MbRelease = serde_json::from_str(RELEASE).unwrap();
+        let album = release.to_album().unwrap();
+        let ids = album.get_external_ids();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[1].source(), Source::MusicBrainzGroup);
+        assert_eq!(ids[2], ExternalId::discogs(1));
+        assert_eq!(album.get_barcode().unwrap().as_str(), "0075678165122");
+        assert_eq!(album.get_tracklist()[1].get_position(), "2-B");
+        assert_eq!(album.running_time(), 285);
+
+        let format = release.to_format().unwrap();
+        assert_eq!(format.get_size(), Some(Size::Twelve));
+        assert_eq!(format.get_discs(), 2);
+        let digital = MbMedium {
+            format: Some("Digital Media".to_string()),
+            ..MbMedium::default()
+        };
+        assert!(digital.to_format().is_err());
+
+        let release = release.to_release().unwrap();
+        assert_eq!(release.artists.len(), 1);
+        assert_eq!(
+            release.labels,
+            vec![(
+                ExternalId::musicbrainz("46f0f4cd-8aab-4b33-b698-f459faf64190").unwrap(),
+                "SK032".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_artist() {
+        let json = r#"{"id": "f27ec8db-af05-4f36-916e-3d57f91ecf5e", "name": "The Persuader",
+            "sort-name": "Persuader, The", "aliases": [{"name": "Jesper Dahlbäck"}],
+            "relations": [{"type": "discogs", "url": {"resource": "https://www.discogs.com/artist/1"}}]}"#;
+        let artist: MbArtist = serde_json::from_str(json).unwrap();
+        let artist = artist.to_artist().unwrap();
+        // The sort name has the same search key as the name
+        assert_eq!(artist.get_aliases(), &["Jesper Dahlbäck"]);
+        assert!(artist.get_external_ids().contains(&ExternalId::discogs(1)));
+
+        let invalid = MbArtist {
+            id: "1".to_string(),
+            ..MbArtist::default()
+        };
+        assert!(invalid.to_artist().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_release_group() -> Result<(), EngineError> {
+        let db = common().await?;
+
+        // The vinyl and the CD of one release group
+        let group = Uuid::new_v4().to_string();
+        let releases: Vec<String> = (0..2).map(|_| Uuid::new_v4().to_string()).collect();
+        let input: String = releases
+            .iter()
+            .zip(["Stockholm", "Stockholm (CD)"])
+            .map(|(id, title)| {
+                serde_json::json!({"id": id, "title": title, "release-group": {"id": group},
+                    "media": [{"format": "CD"}]})
+                .to_string()
+                    + "\n"
+            })
+            .collect();
+
+        let report = import(&db, Entity::Release, input.as_bytes()).await?;
+        assert_eq!(report.created, 2);
+
+        let mut albums = Vec::new();
+        for id in &releases {
+            let album: Album = find_by_external_id(&db, &ExternalId::musicbrainz(id)?)
+                .await?
+                .expect("album of each release");
+            assert_eq!(
+                album.external_id_of(Source::MusicBrainzGroup),
+                Some(&ExternalId::musicbrainz_group(&group)?)
+            );
+            albums.push(album);
+        }
+        assert_ne!(albums[0].id(), albums[1].id());
+        assert_eq!(albums[0].get_name(), "Stockholm");
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+//! Streaming reader splitting a large XML document into small record trees.
+
+use std::io::BufRead;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::import::ImportError;
+
+/// An element with its attributes, text and child elements
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Node {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub text: String,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|n| n.name == name)
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Node> + 'a {
+        self.children.iter().filter(move |n| n.name == name)
+    }
+
+    /// Trimmed text of the named child, `None` when missing or blank
+    pub fn text_of(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .map(|n| n.text.trim())
+            .filter(|t| !t.is_empty())
+    }
+}
+
+/// Iterates over every `record` element of a document as a `Node`,
+/// only one record is held in memory at a time.
+pub(crate) struct RecordReader<R: BufRead> {
+    reader: Reader<R>,
+    record: &'static str,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(input: R, record: &'static str) -> Self {
+        let mut reader = Reader::from_reader(input);
+        reader.trim_text(true);
+        RecordReader {
+            reader,
+            record,
+            buf: Vec::new(),
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<Node>, ImportError> {
+        // Elements opened but not yet closed within the current record
+        let mut open: Vec<Node> = Vec::new();
+        loop {
+            self.buf.clear();
+            let event = self.reader.read_event(&mut self.buf)?;
+            let done = match event {
+                Event::Start(ref e) => {
+                    if !open.is_empty() || e.name() == self.record.as_bytes() {
+                        let node = start(&self.reader, e)?;
+                        open.push(node);
+                    }
+                    None
+                }
+                Event::Empty(ref e) => {
+                    let node = start(&self.reader, e)?;
+                    match open.last_mut() {
+                        Some(parent) => {
+                            parent.children.push(node);
+                            None
+                        }
+                        None if e.name() == self.record.as_bytes() => Some(node),
+                        None => None,
+                    }
+                }
+                Event::Text(ref e) => {
+                    if let Some(node) = open.last_mut() {
+                        node.text.push_str(&e.unescape_and_decode(&self.reader)?);
+                    }
+                    None
+                }
+                Event::CData(ref e) => {
+                    if let Some(node) = open.last_mut() {
+                        node.text.push_str(&String::from_utf8_lossy(e));
+                    }
+                    None
+                }
+                Event::End(_) => match open.pop() {
+                    Some(node) => match open.last_mut() {
+                        Some(parent) => {
+                            parent.children.push(node);
+                            None
+                        }
+                        None => Some(node),
+                    },
+                    None => None,
+                },
+                Event::Eof => {
+                    if open.is_empty() {
+                        return Ok(None);
+                    }
+                    return Err(ImportError::Malformed(format!(
+                        "document ended inside <{}>",
+                        self.record
+                    )));
+                }
+                _ => None,
+            };
+            if done.is_some() {
+                return Ok(done);
+            }
+        }
+    }
+}
+
+fn start<R: BufRead>(reader: &Reader<R>, e: &BytesStart) -> Result<Node, ImportError> {
+    let mut node = Node {
+        name: String::from_utf8_lossy(e.name()).into_owned(),
+        ..Node::default()
+    };
+    for attr in e.attributes() {
+        let attr = attr?;
+        node.attrs.push((
+            String::from_utf8_lossy(attr.key).into_owned(),
+            attr.unescape_and_decode_value(reader)?,
+        ));
+    }
+    Ok(node)
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = Result<Node, ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::import::xml::*;
+
+    #[test]
+    fn test_records() {
+        let doc = r#"<?xml version="1.0"?>
+            <labels>
+                <label><id>1</id><name>Planet E &amp; Co</name>
+                    <sublabels><label id="2">Sub</label></sublabels></label>
+                <label id="3"/>
+            </labels>"#;
+
+        let records: Vec<Node> = RecordReader::new(doc.as_bytes(), "label")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].text_of("name"), Some("Planet E & Co"));
+        let sub = records[0]
+            .child("sublabels")
+            .unwrap()
+            .child("label")
+            .unwrap();
+        assert_eq!(sub.attr("id"), Some("2"));
+        assert_eq!(sub.text, "Sub");
+        assert_eq!(records[1].attr("id"), Some("3"));
+    }
+}
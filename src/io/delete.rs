@@ -1,10 +1,12 @@
 use serde::de::DeserializeOwned;
 
+use crate::models::id::DocId;
+
 #[crate::async_trait]
 pub trait EngineDelete {
     type E;
 
-    async fn remove<T>(&self, id: &str) -> Result<T, Self::E>
+    async fn remove<T>(&self, id: &DocId<T>) -> Result<T, Self::E>
     where
         T: DeserializeOwned + Send + Sync;
 }
@@ -0,0 +1,35 @@
+use serde_json::Value;
+
+/// Whether a collection holds documents or edges linking them
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionKind {
+    Document,
+    Edge,
+}
+
+/// Trait for reading and writing whole collections as raw documents,
+/// used to move data between engines.
+#[crate::async_trait]
+pub trait EngineDump {
+    type E;
+
+    /// Method to read up to `limit` documents of a collection with a `_key` after `after`,
+    /// ordered by `_key`, without attributes only meaningful to the engine i.e. revisions.
+    /// A blank `after` reads from the first document.
+    async fn export(
+        &self,
+        collection: &str,
+        after: &str,
+        limit: usize,
+    ) -> Result<Vec<Value>, Self::E>;
+
+    /// Method to store documents keeping their `_key`, a stored document
+    /// with the same `_key` is replaced. Returns the number stored.
+    async fn import(
+        &self,
+        collection: &str,
+        kind: CollectionKind,
+        docs: Vec<Value>,
+    ) -> Result<u64, Self::E>;
+}
@@ -1,8 +1,10 @@
 //! Modules for defining `IO` traits for storage engines to use.
 pub mod delete;
+pub mod dump;
 pub mod read;
 pub mod write;
 
 pub use delete::*;
+pub use dump::*;
 pub use read::*;
 pub use write::*;
@@ -1,3 +1,4 @@
+use crate::models::id::DocId;
 use crate::models::{ReqModelTraits};
 
 /// Trait for implementing `GET` like methods.
@@ -11,7 +12,7 @@ pub trait Get<T> {
     async fn get_all(engine: &T) -> Result<Vec<Self::Document>, Self::E>;
 
     /// Method to get a single Element
-    async fn get(id: &str, engine: &T) -> Result<Self::Document, Self::E>;
+    async fn get(id: &DocId<Self::Document>, engine: &T) -> Result<Self::Document, Self::E>;
 
     async fn find<'a>(k: &str, v: &str, engine: &T) -> Result<Self::Document, Self::E>;
 }
@@ -26,7 +27,7 @@ pub trait EngineGet {
     async fn get_all<T: ReqModelTraits>(&self) -> Result<Vec<T>, Self::E>;
 
     /// Method to get a single Element
-    async fn get<T: ReqModelTraits>(&self, id: &str) -> Result<T, Self::E>;
+    async fn get<T: ReqModelTraits>(&self, id: &DocId<T>) -> Result<T, Self::E>;
 
     /// Method to find a single Element
     /// with a key(`field`), value pair
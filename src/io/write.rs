@@ -1,3 +1,4 @@
+use crate::models::id::DocId;
 use crate::models::{BoxedDoc, ReqModelTraits};
 
 #[crate::async_trait]
@@ -8,14 +9,14 @@ where
     type E;
     type Document;
 
-    async fn insert(&self, doc: T) -> Result<(String, Box<dyn BoxedDoc>), Self::E>;
+    async fn insert(&self, doc: T) -> Result<(DocId<T>, Box<dyn BoxedDoc>), Self::E>;
 
     async fn update(&self, doc: T) -> Result<(), Self::E>;
 
     async fn insert_collection(
         &self,
         jobs: Vec<T>,
-    ) -> Result<Vec<String>, Self::E> {
+    ) -> Result<Vec<DocId<T>>, Self::E> {
         let mut resp = Vec::new();
         for job in jobs {
             let r = self.insert(job).await?;
@@ -29,19 +30,20 @@ where
 pub trait EngineWrite {
     type E;
 
-    /// Method to inserting a new document
+    /// Method to inserting a new document,
+    /// fails with a `ValidationError` without writing if `doc` is invalid
     async fn insert<T: ReqModelTraits + BoxedDoc + 'static>(
         &self,
         doc: T,
-    ) -> Result<(String, Box<dyn BoxedDoc>), Self::E>;
+    ) -> Result<(DocId<T>, Box<dyn BoxedDoc>), Self::E>;
 
-    /// Method to updating a single document
+    /// Method to updating a single document, validated as it is on insert
     async fn update<T: ReqModelTraits>(&self, doc: T) -> Result<(), Self::E>;
 
     async fn insert_collection<T: ReqModelTraits + BoxedDoc + 'static>(
         &self,
         jobs: Vec<T>,
-    ) -> Result<Vec<String>, Self::E> {
+    ) -> Result<Vec<DocId<T>>, Self::E> {
         let mut resp = Vec::new();
         for job in jobs {
             let r = self.insert(job).await?;
@@ -60,7 +62,7 @@ macro_rules! insert_many {
             let r = $db.insert($e).await;
             match r {
                 Ok(doc) => v.push(Ok(doc.1)),
-                Err(e) => v.push(Err(Box::new($crate::engine::DbError::FailedToCreate))),
+                Err(e) => v.push($crate::engine::DbError::FailedToCreate.into()),
             }
         )*
         v
@@ -11,11 +11,17 @@ pub use async_trait::async_trait;
 pub use model_write_derive as macros;
 
 pub mod engine;
+/// Modules for importing catalogue data from external sources
+pub mod import;
 /// Modules for defining read and writes traits for storage engines.
 pub mod io;
 /// Modules for Models
 pub mod models;
+/// Modules for operations spanning several models
+pub mod service;
 
 pub mod time;
 
+pub mod money;
+
 pub mod preludes;
@@ -1,68 +1,141 @@
 use std::borrow::Cow;
 
 use crate::macros::*;
-
-mod ver;
+use crate::models::barcode::Barcode;
+use crate::models::external_id::{self, ExternalId, ExternalIds};
+use crate::models::id::{DocId, DocKey};
+use crate::models::key::Keyed;
+use crate::models::name::Name;
+use crate::models::track::{self, Track};
 
 #[include_database_fields(timestamp)]
 /// Album data type
-#[derive(Debug, ModelTrait, WriteToArango, Default, Clone, Deserialize, Serialize)]
+#[derive(Debug, ModelTrait, WriteToArango, Validate, Default, Clone, Deserialize, Serialize)]
+#[key(ulid)]
+#[serde(remote = "Self")]
 pub struct Album {
     /// ArangonDb _id
     #[serde(rename(deserialize = "_id", serialize = "_id"))]
-    id: Cow<'static, str>,
+    id: DocId<Album>,
     /// ArangonDb _key
     #[serde(rename(deserialize = "_key", serialize = "_key"))]
-    key: Cow<'static, str>,
-    /// field for storing an barcode of a album
-    barcode: Cow<'static, str>,
-    /// field for storing an catalog number of a album
+    key: DocKey<Album>,
+    /// Ids of the album in the catalogues it was imported from,
+    /// stored as a single `foreign_key` by version 1
+    #[serde(default)]
+    external_ids: Vec<ExternalId>,
+    /// `foreign_key` of version 1 that isn't an external id, kept to be fixed by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    legacy_foreign_key: Option<Cow<'static, str>>,
+    /// Normalised barcode of a album, unique when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(regex = "^([0-9]{8}|[0-9]{13})$")]
+    barcode: Option<Cow<'static, str>>,
+    /// field for storing an catalog number of a album,
+    /// superseded by `released_on` links to a `Label` see `service::label`
+    #[validate(max_length = 100)]
     cat_no: Cow<'static, str>,
-    /// Albums name
-    name: Cow<'static, str>,
+    /// Albums name as it is shown, written with its `search_key`
+    #[serde(flatten)]
+    #[validate(non_empty, max_length = 500)]
+    name: Name,
     /// Album details
     description: Cow<'static, str>,
+    /// Tracks in release order
+    #[serde(default)]
+    tracklist: Vec<Track>,
 }
 
+crate::versioned!(Album, [external_id::upgrade_foreign_key]);
+
 impl Album {
-    /// Creates a new blank Album with a unique identifier for `_key`
+    /// Creates a new blank Album with a `_key` made by its `KeyStrategy`
     pub fn new() -> Self {
-        use uuid::Uuid;
-
-        let uid = Uuid::new_v4().to_string()[0..8].to_string();
-        let id = format!("{}/{}", "album", uid);
-        Album {
-            id: Cow::from(id),
-            key: Cow::from(uid),
-            ..Album::default()
-        }
+        let mut album = Album::default();
+        album.new_key();
+        album
     }
 
-    pub fn change_id<T>(&mut self, new_id: T) -> &mut Self
-    where
-        T: Into<Cow<'static, str>>,
-    {
-        self.key = new_id.into();
-        self.id = format!("album/{}", self.key).into();
+    /// Sets the `_key`, and the `_id` to match
+    pub fn change_id(&mut self, key: DocKey<Album>) -> &mut Self {
+        self.id = key.id();
+        self.key = key;
         self
     }
 
     pub fn gen_id(&mut self) -> &mut Self {
-        use uuid::Uuid;
-        let uid = Uuid::new_v4().to_string()[0..8].to_string();
-        self.change_id(uid);
+        self.new_key();
+        self
+    }
+
+    pub fn name<T: AsRef<str>>(&mut self, name: T) -> &mut Self {
+        self.name = Name::new(name);
         self
     }
 
-    pub fn name(&mut self, name: &'static str) -> &mut Self {
-        self.name = Cow::from(name.trim().to_ascii_lowercase());
+    pub fn description<T: Into<Cow<'static, str>>>(&mut self, desc: T) -> &mut Self {
+        self.description = desc.into();
+        self
+    }
+
+    pub fn external_id(&mut self, id: ExternalId) -> &mut Self {
+        external_id::push(&mut self.external_ids, id);
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Key the name is searched by, see `models::name::search_key`
+    pub fn get_search_key(&self) -> String {
+        self.name.search_key()
+    }
+
+    pub fn get_description(&self) -> &str {
+        self.description.as_ref()
+    }
+
+    pub fn get_external_ids(&self) -> &[ExternalId] {
+        &self.external_ids
+    }
+
+    pub fn get_legacy_foreign_key(&self) -> Option<&str> {
+        self.legacy_foreign_key.as_deref()
+    }
+
+    pub fn barcode(&mut self, barcode: Barcode) -> &mut Self {
+        self.barcode = Some(String::from(barcode).into());
         self
     }
 
-    pub fn description(&mut self, desc: &'static str) -> &mut Self {
-        self.description = Cow::from(desc);
+    /// Barcode of the album, `None` if unset or stored by an earlier
+    /// version without validation and not a valid barcode.
+    pub fn get_barcode(&self) -> Option<Barcode> {
+        self.barcode.as_deref().and_then(|b| Barcode::new(b).ok())
+    }
+
+    /// Sets the tracklist of a new album, use `service::tracklist::set_tracklist`
+    /// for stored albums.
+    pub fn tracklist(&mut self, tracks: Vec<Track>) -> &mut Self {
+        self.tracklist = tracks;
         self
     }
+
+    pub fn get_tracklist(&self) -> &[Track] {
+        &self.tracklist
+    }
+
+    /// Total running time of the tracklist in seconds
+    pub fn running_time(&self) -> u32 {
+        track::running_time(&self.tracklist)
+    }
+}
+
+impl ExternalIds for Album {
+    fn external_ids(&self) -> &[ExternalId] {
+        &self.external_ids
+    }
 }
 
 pub mod read {
@@ -73,6 +146,7 @@ pub mod read {
     use crate::engine::db::arangodb::preludes::*;
     use crate::engine::{DbError, EngineError};
     use crate::io::read::Get;
+    use crate::models::id::DocId;
     use crate::models::{album::Album, DocDetails, ReqModelTraits};
 
     #[crate::async_trait]
@@ -94,8 +168,9 @@ pub mod read {
         }
 
         /// Gets a single Albums from storage `Db`
-        async fn get(id: &str, engine: &ArangoDb) -> Result<Self::Document, Self::E> {
-            let query = ArangoDb::aql_get_single(Self::collection_name(), id);
+        async fn get(id: &DocId<Album>, engine: &ArangoDb) -> Result<Self::Document, Self::E> {
+            let key = id.key();
+            let query = ArangoDb::aql_get_single(Self::collection_name(), &key);
             let resp: Option<Self::Document> = engine.db.aql_query(query).await?.pop();
             if let Some(doc) = resp {
                 Ok(doc)
@@ -106,10 +181,9 @@ pub mod read {
 
 
         async fn find<'a>(k: &str, v: &str, engine: &ArangoDb) -> Result<Self::Document, Self::E> {
-            let val = v.trim().to_ascii_lowercase();
             let resp: Option<Self::Document> = engine
                 .db()
-                .aql_query(ArangoDb::aql_filter(k, &val, Self::collection_name()))
+                .aql_query(ArangoDb::aql_find(k, v, Self::collection_name()))
                 .await?
                 .pop();
             if let Some(doc) = resp {
@@ -124,8 +198,6 @@ pub mod read {
 
 #[cfg(test)]
 mod test {
-    use std::borrow::Cow;
-
     use crate::engine::db::test::common;
     use crate::engine::db::DbBasics;
     use crate::engine::session::test::common_session_db;
@@ -133,18 +205,75 @@ mod test {
     use crate::io::read::{EngineGet, Get};
     use crate::io::write::Write;
     use crate::models::album::Album;
+    use crate::models::external_id::ExternalId;
+    use crate::models::validate::{Validate, ValidationError};
 
     type TestResult = Result<(), EngineError>;
 
+    #[test]
+    fn test_read_legacy_foreign_key() {
+        let legacy = serde_json::json!({
+            "_id": "album/1", "_key": "1", "foreign_key": "discogs - 123456",
+            "cat_no": "", "name": "owl house", "description": "",
+            "created": 0, "updated": 0
+        });
+        let album: Album = serde_json::from_value(legacy).unwrap();
+        assert_eq!(album.get_external_ids(), &[ExternalId::discogs(123456)]);
+
+        let blank = serde_json::json!({
+            "_id": "album/1", "_key": "1", "foreign_key": "",
+            "cat_no": "", "name": "owl house", "description": "",
+            "created": 0, "updated": 0
+        });
+        let album: Album = serde_json::from_value(blank).unwrap();
+        assert!(album.get_external_ids().is_empty());
+
+        // Free text of before external ids is kept aside
+        let free_text = serde_json::json!({
+            "_id": "album/1", "_key": "1", "foreign_key": "bought at the fair",
+            "cat_no": "", "name": "owl house", "description": "",
+            "created": 0, "updated": 0
+        });
+        let album: Album = serde_json::from_value(free_text).unwrap();
+        assert!(album.get_external_ids().is_empty());
+        assert_eq!(album.get_legacy_foreign_key(), Some("bought at the fair"));
+        let stored = serde_json::to_value(&album).unwrap();
+        assert_eq!(stored["legacy_foreign_key"], "bought at the fair");
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut album = Album::new();
+        album.name("Owl House");
+        assert!(album.validate().is_ok());
+
+        album.name(" ");
+        album.barcode = Some("123".into());
+        let err = album.validate().unwrap_err();
+        let fields: Vec<&str> = err.errors.iter().map(|e| e.field.as_ref()).collect();
+        assert_eq!(fields, vec!["barcode", "name"]);
+    }
+
+    #[tokio::test]
+    async fn fail_on_invalid_album_db() -> TestResult {
+        let db = common().await?;
+
+        let resp = db.insert(Album::new()).await;
+        let err = resp.err().unwrap();
+        let err = err.downcast_ref::<ValidationError>().unwrap();
+        assert_eq!(err.errors[0].rule, "non_empty");
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_insert_album_db() -> TestResult {
         let db = common().await?;
         let mut new_album = Album::new();
-        new_album.name = Cow::from("Owl House");
+        new_album.name("Owl House");
 
         let resp = db.insert(new_album.clone()).await;
         assert!(resp.is_ok());
-        let resp = dbg!(db.get::<Album>(new_album.id.as_ref()).await);
+        let resp = dbg!(db.get::<Album>(&new_album.id).await);
         assert!(resp.is_ok());
         Ok(())
     }
@@ -154,7 +283,7 @@ mod test {
         let db = common().await?;
 
         let mut new_album = Album::new();
-        new_album.name = Cow::from("Owl House");
+        new_album.name("Owl House");
 
         db.insert(new_album.clone()).await?;
         let resp = db.insert(new_album).await;
@@ -184,7 +313,7 @@ mod test {
         let s_read = s.db().read().await;
 
         let mut a = Album::new();
-        a.name = Cow::from("with session");
+        a.name("with session");
 
         let resp = s_read.insert(a).await;
 
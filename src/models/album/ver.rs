@@ -1,8 +0,0 @@
-
-use crate::models::album::Album;
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(untagged)]
-pub enum AlbumVer {
-    AlbumV1(Album),
-}
@@ -1,56 +1,129 @@
 use std::borrow::Cow;
 
 use crate::macros::*;
+use crate::models::external_id::{self, ExternalId, ExternalIds};
+use crate::models::id::{DocId, DocKey};
+use crate::models::key::Keyed;
+use crate::models::name::{search_key, Name};
 
 #[include_database_fields(timestamp)]
 /// Artist Data type
-#[derive(Debug, ModelTrait, WriteToArango, Default, Clone, Deserialize, Serialize)]
+#[derive(Debug, ModelTrait, WriteToArango, Validate, Default, Clone, Deserialize, Serialize)]
+#[key(ulid)]
+#[serde(remote = "Self")]
 pub struct Artist {
     /// ArangonDb _id
     #[serde(rename(deserialize = "_id", serialize = "_id"))]
-    id: Cow<'static, str>,
+    id: DocId<Artist>,
     /// ArangonDb _key
     #[serde(rename(deserialize = "_key", serialize = "_key"))]
-    key: Cow<'static, str>,
-    /// id/key used by source formatted `Source - ID`
-    ///     # Example:
-    ///     `discogs - 123456`
-    foreign_key: Cow<'static, str>,
-    /// Artist/Band name
-    name: Cow<'static, str>,
+    key: DocKey<Artist>,
+    /// Ids of the artist in the catalogues it was imported from,
+    /// stored as a single `foreign_key` by version 1
+    #[serde(default)]
+    external_ids: Vec<ExternalId>,
+    /// `foreign_key` of version 1 that isn't an external id, kept to be fixed by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    legacy_foreign_key: Option<Cow<'static, str>>,
+    /// Artist/Band name as it is shown, written with its `search_key`
+    #[serde(flatten)]
+    #[validate(non_empty, max_length = 500)]
+    name: Name,
     /// Common variations of the name
+    #[validate(max_length = 100, custom = "no_blank_aliases")]
     aliases: Vec<Cow<'static, str>>,
     /// Description of artist.
     profile: Cow<'static, str>,
 }
 
+crate::versioned!(Artist, [external_id::upgrade_foreign_key]);
+
 impl Artist {
     pub fn new() -> Self {
-        use uuid::Uuid;
-
-        let uid = Uuid::new_v4().to_string()[0..8].to_string();
-        Self {
-            id: format!("{}/{}", Self::collection_name(), uid).into(),
-            key: Cow::from(uid),
-            ..Self::default()
-        }
+        let mut artist = Artist::default();
+        artist.new_key();
+        artist
     }
 
-    pub fn change_id<T: Into<Cow<'static, str>>>(&mut self, new_id: T) {
-        self.key = new_id.into();
+    /// Sets the `_key`, and the `_id` to match
+    pub fn change_id(&mut self, key: DocKey<Artist>) -> &mut Self {
+        self.id = key.id();
+        self.key = key;
+        self
     }
 
     pub fn gen_id(&mut self) -> &mut Self {
-        use uuid::Uuid;
-        let uid = Uuid::new_v4().to_string()[0..8].to_string();
-        self.change_id(uid);
+        self.new_key();
         self
     }
 
-    pub fn name(&mut self, name: &'static str) -> &mut Self {
-        self.name = Cow::from(name.trim().to_ascii_lowercase());
+    pub fn name<T: AsRef<str>>(&mut self, name: T) -> &mut Self {
+        self.name = Name::new(name);
+        self
+    }
+
+    /// Adds another name the artist is known by,
+    /// unless it has the same search key as the name or an alias
+    pub fn alias<T: AsRef<str>>(&mut self, alias: T) -> &mut Self {
+        let alias = alias.as_ref().trim();
+        let key = search_key(alias);
+        if !key.is_empty()
+            && key != self.name.search_key()
+            && !self.aliases.iter().any(|a| search_key(a) == key)
+        {
+            self.aliases.push(Cow::from(alias.to_string()));
+        }
         self
     }
+
+    pub fn profile<T: Into<Cow<'static, str>>>(&mut self, profile: T) -> &mut Self {
+        self.profile = profile.into();
+        self
+    }
+
+    pub fn external_id(&mut self, id: ExternalId) -> &mut Self {
+        external_id::push(&mut self.external_ids, id);
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Key the name is searched by, see `models::name::search_key`
+    pub fn get_search_key(&self) -> String {
+        self.name.search_key()
+    }
+
+    pub fn get_aliases(&self) -> &[Cow<'static, str>] {
+        &self.aliases
+    }
+
+    pub fn get_profile(&self) -> &str {
+        self.profile.as_ref()
+    }
+
+    pub fn get_external_ids(&self) -> &[ExternalId] {
+        &self.external_ids
+    }
+
+    pub fn get_legacy_foreign_key(&self) -> Option<&str> {
+        self.legacy_foreign_key.as_deref()
+    }
+}
+
+/// Aliases are found by their search key, one without a key can never be found
+fn no_blank_aliases(aliases: &[Cow<'static, str>]) -> Result<(), String> {
+    match aliases.iter().find(|a| search_key(a).is_empty()) {
+        Some(a) => Err(format!("alias {:?} has no letters or digits", a)),
+        None => Ok(()),
+    }
+}
+
+impl ExternalIds for Artist {
+    fn external_ids(&self) -> &[ExternalId] {
+        &self.external_ids
+    }
 }
 
 pub mod read {
@@ -67,6 +140,7 @@ pub mod read {
 
     use crate::io::read::Get;
     use crate::models::artist::Artist;
+    use crate::models::id::DocId;
     use crate::models::{DocDetails};
 
 
@@ -93,7 +167,7 @@ pub mod read {
         }
 
         /// Gets a single artists from storage `Db`
-        async fn get(id: &str, engine: &ArangoDb) -> Result<Self::Document, Self::E>
+        async fn get(id: &DocId<Artist>, engine: &ArangoDb) -> Result<Self::Document, Self::E>
         where
             Self: DocDetails,
         {
@@ -101,7 +175,7 @@ pub mod read {
                 .db()
                 .collection(Self::collection_name())
                 .await?
-                .document(id)
+                .document(&id.key())
                 .await?
                 .document;
             Ok(col)
@@ -111,10 +185,9 @@ pub mod read {
         where
             Self: DocDetails,
         {
-            let val = v.trim().to_ascii_lowercase();
             let resp: Vec<Artist> = engine
                 .db
-                .aql_query(ArangoDb::aql_filter(k, &val, Self::collection_name()))
+                .aql_query(ArangoDb::aql_find(k, v, Self::collection_name()))
                 .await?;
             dbg!(&resp);
             if resp.is_empty() {
@@ -129,8 +202,6 @@ pub mod read {
 
 #[cfg(test)]
 mod test {
-    use std::borrow::Cow;
-
     use crate::engine::db::test::common;
     use crate::engine::db::DbBasics;
     use crate::engine::session::test::common_session_db;
@@ -145,7 +216,7 @@ mod test {
     async fn test_insert_artist_db() -> TestResult {
         let db = common().await?;
         let mut data = Artist::new();
-        data.name = Cow::from("Disney");
+        data.name("Disney");
 
         let resp = db.insert(data).await;
         // let resp = db.insert(data).await;
@@ -161,7 +232,7 @@ mod test {
         db.db_info();
 
         let mut data = Artist::new();
-        data.name = Cow::from("Disney");
+        data.name("Disney");
 
         dbg!(db.insert(data.clone()).await?);
         let resp = dbg!(db.insert(data).await);
@@ -191,7 +262,7 @@ mod test {
         let s_read = s.db().read().await;
 
         let mut a = Artist::new();
-        a.name = Cow::from("with session");
+        a.name("with session");
 
         let resp = s_read.insert(a).await;
 
@@ -0,0 +1,213 @@
+//! Product barcodes printed on releases.
+//!
+//! Every barcode is checked against its check digit and normalised so the same
+//! product always has the same code: UPC-A and ISBN-10 are widened to their
+//! 13 digit EAN form, spaces and hyphens are removed.
+
+use std::convert::TryFrom;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use crate::engine::error::Cause;
+use crate::engine::EngineError;
+
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BarcodeError {
+    /// Not 8, 10, 12 or 13 digits long
+    InvalidLength(usize),
+    /// Contains something other than digits, or an `X` outside an ISBN-10 check digit
+    InvalidCharacter,
+    /// Check digit doesn't match the rest of the code
+    Checksum,
+}
+
+impl std::fmt::Display for BarcodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            BarcodeError::InvalidLength(len) => {
+                write!(f, "A barcode can not be {} digits long.", len)
+            }
+            BarcodeError::InvalidCharacter => write!(f, "A barcode may only contain digits."),
+            BarcodeError::Checksum => write!(f, "Barcode check digit does not match."),
+        }
+    }
+}
+
+impl std::error::Error for BarcodeError {}
+
+impl From<BarcodeError> for EngineError {
+    fn from(e: BarcodeError) -> Self {
+        EngineError::Invalid(Cause::new(e))
+    }
+}
+
+/// Numbering system a barcode belongs to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BarcodeKind {
+    UpcA,
+    Ean13,
+    Ean8,
+    /// EAN-13 in the 978/979 book range, given as ISBN-10 or ISBN-13
+    Isbn,
+}
+
+/// A validated barcode, stored in its normalised 8 or 13 digit form
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Barcode(String);
+
+impl Barcode {
+    pub fn new(code: &str) -> Result<Self, BarcodeError> {
+        let code: String = code
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect::<String>()
+            .to_ascii_uppercase();
+        if !code.is_ascii() {
+            return Err(BarcodeError::InvalidCharacter);
+        }
+
+        if code.len() == 10 {
+            return Self::from_isbn10(&code);
+        }
+        let digits = to_digits(&code)?;
+        match digits.len() {
+            8 | 12 | 13 => {}
+            len => return Err(BarcodeError::InvalidLength(len)),
+        }
+        let (body, check) = digits.split_at(digits.len() - 1);
+        if gtin_check_digit(body) != check[0] {
+            return Err(BarcodeError::Checksum);
+        }
+
+        if digits.len() == 12 {
+            Ok(Barcode(format!("0{}", code)))
+        } else {
+            Ok(Barcode(code))
+        }
+    }
+
+    fn from_isbn10(code: &str) -> Result<Self, BarcodeError> {
+        let (body, check) = code.split_at(9);
+        let body = to_digits(body)?;
+        let check = match check {
+            "X" => 10,
+            c => to_digits(c)?[0],
+        };
+        let sum: u32 = body
+            .iter()
+            .zip((2..=10).rev())
+            .map(|(d, w)| d * w)
+            .sum::<u32>()
+            + check;
+        if !sum.is_multiple_of(11) {
+            return Err(BarcodeError::Checksum);
+        }
+
+        let mut ean: Vec<u32> = vec![9, 7, 8];
+        ean.extend(body);
+        ean.push(gtin_check_digit(&ean));
+        Ok(Barcode(ean.iter().map(u32::to_string).collect()))
+    }
+
+    /// Normalised code, 13 digits or 8 for EAN-8
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn kind(&self) -> BarcodeKind {
+        match self.0.as_bytes() {
+            code if code.len() == 8 => BarcodeKind::Ean8,
+            [b'0', ..] => BarcodeKind::UpcA,
+            [b'9', b'7', b'8' | b'9', ..] => BarcodeKind::Isbn,
+            _ => BarcodeKind::Ean13,
+        }
+    }
+
+    /// 12 digit UPC-A form, for codes in the UPC range
+    pub fn upc(&self) -> Option<&str> {
+        match self.kind() {
+            BarcodeKind::UpcA => Some(&self.0[1..]),
+            _ => None,
+        }
+    }
+}
+
+/// GS1 check digit of the digits before it
+fn gtin_check_digit(body: &[u32]) -> u32 {
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d * 3 } else { *d })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+fn to_digits(code: &str) -> Result<Vec<u32>, BarcodeError> {
+    code.chars()
+        .map(|c| c.to_digit(10).ok_or(BarcodeError::InvalidCharacter))
+        .collect()
+}
+
+impl std::fmt::Display for Barcode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Barcode {
+    type Err = BarcodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Barcode::new(s)
+    }
+}
+
+impl TryFrom<String> for Barcode {
+    type Error = BarcodeError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Barcode::new(&s)
+    }
+}
+
+impl From<Barcode> for String {
+    fn from(b: Barcode) -> Self {
+        b.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::barcode::*;
+
+    #[test]
+    fn test_normalise() {
+        let upc = Barcode::new("0 75678-16512 2").unwrap();
+        assert_eq!(upc.as_str(), "0075678165122");
+        assert_eq!(upc.kind(), BarcodeKind::UpcA);
+        assert_eq!(upc.upc(), Some("075678165122"));
+        assert_eq!(Barcode::new("0075678165122").unwrap(), upc);
+
+        let ean = Barcode::new("5099902894928").unwrap();
+        assert_eq!(ean.kind(), BarcodeKind::Ean13);
+        assert_eq!(Barcode::new("96385074").unwrap().kind(), BarcodeKind::Ean8);
+
+        let isbn = Barcode::new("0-306-40615-2").unwrap();
+        assert_eq!(isbn.as_str(), "9780306406157");
+        assert_eq!(isbn.kind(), BarcodeKind::Isbn);
+        assert_eq!(Barcode::new("080442957X").unwrap().as_str(), "9780804429573");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Barcode::new("5099902894927"), Err(BarcodeError::Checksum));
+        assert_eq!(Barcode::new("0-306-40615-3"), Err(BarcodeError::Checksum));
+        assert_eq!(Barcode::new("12345"), Err(BarcodeError::InvalidLength(5)));
+        assert_eq!(Barcode::new("50999O2894928"), Err(BarcodeError::InvalidCharacter));
+        assert_eq!(Barcode::new("978030640é"), Err(BarcodeError::InvalidCharacter));
+        assert!(serde_json::from_value::<Barcode>(serde_json::json!("")).is_err());
+    }
+}
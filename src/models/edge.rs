@@ -1,11 +1,80 @@
 use std::borrow::Cow;
 
 use arangors::aql::AqlQuery;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
 
 use crate::engine::db::ArangoDb;
 use crate::engine::{DbError, EngineError};
+use crate::import::ARTIST_TO;
 use crate::io::Write;
+use crate::models::id::{DocId, DocKey, VertexId};
+use crate::models::key::{KeyStrategy, Keyed};
+use crate::models::validate::{rules, Validate, ValidationError};
 use crate::models::{BoxedDoc, DocDetails, ReqModelTraits};
+use crate::service::genre::{SUB_GENRE, TAGGED};
+use crate::service::label::{RELEASED_ON, SUB_LABEL};
+
+pub use link::*;
+
+mod link;
+
+/// How one vertex relates to another i.e. how an artist is credited on an album.
+///
+/// Stored as a plain string under the edge's `role` attribute, any role not
+/// covered by a named variant is kept as `Other`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum Role {
+    PrimaryArtist,
+    Featured,
+    Producer,
+    Engineer,
+    Remixer,
+    Other(Cow<'static, str>),
+}
+
+impl Role {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::PrimaryArtist => "primary_artist",
+            Role::Featured => "featured",
+            Role::Producer => "producer",
+            Role::Engineer => "engineer",
+            Role::Remixer => "remixer",
+            Role::Other(s) => s.as_ref(),
+        }
+    }
+}
+
+impl From<String> for Role {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "primary_artist" => Role::PrimaryArtist,
+            "featured" => Role::Featured,
+            "producer" => Role::Producer,
+            "engineer" => Role::Engineer,
+            "remixer" => Role::Remixer,
+            _ => Role::Other(s.into()),
+        }
+    }
+}
+
+impl From<Role> for String {
+    fn from(r: Role) -> Self {
+        r.as_str().to_string()
+    }
+}
+
+/// Direction to follow edges when traversing from a vertex.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// Follow edges where the vertex is `_from`
+    Outbound,
+    /// Follow edges where the vertex is `_to`
+    Inbound,
+}
 
 /// A module containing backend components
 /// for handling ArangoDb edge collections
@@ -13,10 +82,17 @@ use crate::models::{BoxedDoc, DocDetails, ReqModelTraits};
 pub struct Edge {
     #[serde(default)]
     edge_name: Cow<'static, str>,
-    _id: Option<String>,
-    _key: Option<String>,
-    _from: Cow<'static, str>,
-    _to: Cow<'static, str>,
+    _id: Option<DocId<Edge>>,
+    _key: Option<DocKey<Edge>>,
+    _from: VertexId,
+    _to: VertexId,
+    /// Discriminator used together with `_from`/`_to` when upserting,
+    /// allowing the same two vertices to be linked once per role.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<Role>,
+    /// Any additional attributes carried by the edge i.e. track credits
+    #[serde(flatten)]
+    payload: Map<String, Value>,
 }
 
 impl DocDetails for Edge {
@@ -24,98 +100,250 @@ impl DocDetails for Edge {
         "generic_edge"
     }
 
-    fn key(&self) -> String {
-        if let Some(k) = self._key.as_ref() {
-            k.to_owned()
-        } else {
-            "NULL".to_string()
-        }
+    /// Edges are stored in any of the edge collections linking documents,
+    /// variants have a model of their own
+    fn owns_collection(collection: &str) -> bool {
+        [
+            Self::collection_name(),
+            ARTIST_TO,
+            SUB_LABEL,
+            RELEASED_ON,
+            SUB_GENRE,
+            TAGGED,
+        ]
+        .contains(&collection)
     }
 
-    fn id(&self) -> String {
-        if let Some(k) = self._id.as_ref() {
-            k.to_owned()
-        } else {
-            "NULL".to_string()
-        }
+    fn key(&self) -> DocKey<Edge> {
+        self._key.clone().unwrap_or_default()
+    }
+
+    fn id(&self) -> DocId<Edge> {
+        self._id.clone().unwrap_or_default()
     }
 }
 
 impl ReqModelTraits for Edge {}
 
+impl Keyed for Edge {
+    const KEY_STRATEGY: KeyStrategy = KeyStrategy::Server;
+
+    fn set_key(&mut self, key: String) {
+        self._id = DocId::parse(format!("{}/{}", self.collection(), key)).ok();
+        self._key = Some(DocKey::valid(key));
+    }
+}
+
+impl Validate for Edge {
+    fn validate(&self) -> Result<(), ValidationError> {
+        let mut errors = ValidationError::default();
+        errors
+            .check("_from", rules::non_empty(&self._from))
+            .check("_from", rules::any_id(&self._from))
+            .check("_to", rules::non_empty(&self._to))
+            .check("_to", rules::any_id(&self._to));
+        errors.into_result()
+    }
+}
+
 impl BoxedDoc for Edge {}
 
 impl Edge {
-    pub fn new<T: Into<Cow<'static, str>>>(edge_name: &'static str, parent: T, child: T) -> Self {
+    pub fn new<P, C>(edge_name: &'static str, parent: P, child: C) -> Self
+    where
+        P: Into<VertexId>,
+        C: Into<VertexId>,
+    {
         Edge {
             edge_name: edge_name.into(),
-            _id: None,
-            _key: None,
             _from: parent.into(),
             _to: child.into(),
+            ..Edge::default()
         }
     }
 
-    /// Method for linking many entities to one
-    /// via arangodb edge
-    /// this method doesn't check if the parent or children
-    pub async fn link_one_to_many(
-        engine: &ArangoDb,
-        edge_name: &'static str,
-        parent: String,
-        children: Vec<String>,
-    ) -> Result<Vec<Box<dyn BoxedDoc>>, EngineError> {
-        let mut jobs = Vec::new();
+    pub fn from_vertex(&self) -> &VertexId {
+        &self._from
+    }
 
-        // create collection of futures
-        for child in children {
-            let edge = Edge::new(edge_name, parent.clone(), child);
-            jobs.push(engine.insert(edge));
+    pub fn to(&self) -> &VertexId {
+        &self._to
+    }
+
+    pub fn role(&mut self, role: Role) -> &mut Self {
+        self.role = Some(role);
+        self
+    }
+
+    pub fn get_role(&self) -> Option<&Role> {
+        self.role.as_ref()
+    }
+
+    /// Sets a single payload attribute on the edge.
+    pub fn attr<V: Into<Value>>(&mut self, k: &str, v: V) -> &mut Self {
+        self.payload.insert(k.to_string(), v.into());
+        self
+    }
+
+    pub fn get_attr<V: DeserializeOwned>(&self, k: &str) -> Option<V> {
+        self.payload
+            .get(k)
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+    }
+
+    /// Merges the fields of a serializable struct into the edge payload.
+    pub fn payload<P: Serialize>(&mut self, payload: P) -> Result<&mut Self, EngineError> {
+        match serde_json::to_value(payload)? {
+            Value::Object(map) => self.payload.extend(map),
+            _ => return DbError::ParseFail.into(),
         }
+        Ok(self)
+    }
 
-        let out = futures::future::join_all(jobs).await;
-        if out.is_empty() {
-            return Err(Box::new(DbError::FailedToCreate));
+    /// Reads the edge payload back as a typed struct.
+    pub fn get_payload<P: DeserializeOwned>(&self) -> Result<P, EngineError> {
+        Ok(serde_json::from_value(Value::Object(self.payload.clone()))?)
+    }
+
+    /// Name of the edge collection this edge is stored in.
+    fn collection(&self) -> &str {
+        if self.edge_name.is_empty() {
+            Self::collection_name()
+        } else {
+            self.edge_name.as_ref()
         }
+    }
 
-        let v = out
-            .into_iter()
-            .filter_map(|o| o.ok())
-            .map(|(_s, d)| d)
+    /// Document sent to the database, system attributes picked up
+    /// from a previous read i.e. `_rev` are left out.
+    fn document(&self) -> Value {
+        let mut doc: Map<String, Value> = self
+            .payload
+            .iter()
+            .filter(|(k, _)| !k.starts_with('_'))
+            .map(|(k, v)| (k.clone(), v.clone()))
             .collect();
+        doc.insert("_from".into(), Value::from(self._from.clone()));
+        doc.insert("_to".into(), Value::from(self._to.clone()));
+        if let Some(role) = self.role.as_ref() {
+            doc.insert("role".into(), Value::from(role.as_str()));
+        }
 
-        Ok(v)
+        Value::Object(doc)
     }
-}
 
-#[crate::async_trait]
-impl Write<Edge> for ArangoDb {
-    type E = EngineError;
-    type Document = Edge;
+    /// Creates a `Linker` for checked linking through the `edge_name` collection.
+    pub fn linker<'a, P: DocDetails, C: DocDetails>(
+        engine: &'a ArangoDb,
+        edge_name: &'static str,
+    ) -> Linker<'a, P, C> {
+        Linker::new(engine, edge_name)
+    }
 
-    async fn insert(&self, doc: Edge) -> Result<(String, Box<dyn BoxedDoc>), Self::E> {
-        use crate::engine::db::arangodb::aql_snippet::UPSERT_EDGE;
-        #[derive(Serialize)]
-        struct _Edge_ {
-            _from: Cow<'static, str>,
-            _to: Cow<'static, str>,
+    /// Method for linking many entities to one
+    /// via arangodb edge.
+    /// Both the parent and each child are checked to exist first,
+    /// the result of every child is reported in the returned collection.
+    pub async fn link_one_to_many<P: DocDetails, C: DocDetails>(
+        engine: &ArangoDb,
+        edge_name: &'static str,
+        parent: DocId<P>,
+        children: Vec<DocId<C>>,
+    ) -> Result<Vec<LinkResult<C>>, EngineError> {
+        if children.is_empty() {
+            return DbError::FailedToCreate.into();
         }
 
-        let edge = _Edge_ {
-            _from: doc._from,
-            _to: doc._to,
-        };
-        let value = serde_json::to_value(&edge).unwrap();
+        Linker::new(engine, edge_name)
+            .link(&parent, &children)
+            .await
+    }
+
+    /// Inserts the edge, or updates the payload of an existing edge
+    /// with the same `_from`, `_to` and `role`.
+    pub async fn upsert(engine: &ArangoDb, doc: Edge) -> Result<Edge, EngineError> {
+        use crate::engine::db::arangodb::aql_snippet::UPSERT_EDGE;
+
         let aql = AqlQuery::builder()
             .query(UPSERT_EDGE)
-            .bind_var("doc", value)
-            .bind_var("@collection", "artist_to")
+            .bind_var("doc", doc.document())
+            .bind_var("@collection", doc.collection())
             .build();
 
-        let resp: Vec<Edge> = self.db.aql_query(aql).await?;
-        let out = resp[0].clone();
+        let resp: Option<Edge> = engine.db().aql_query(aql).await?.pop();
+        let mut out = match resp {
+            Some(e) => e,
+            None => return DbError::FailedToCreate.into(),
+        };
+        out.edge_name = doc.edge_name;
+
+        Ok(out)
+    }
+
+    /// Finds all edges in `edge_name` whose attributes match the `filter`.
+    pub async fn find(
+        engine: &ArangoDb,
+        edge_name: &'static str,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<Edge>, EngineError> {
+        let aql = ArangoDb::aql_find_edges(edge_name, filter.to_value());
+        let mut resp: Vec<Edge> = engine.db().aql_query(aql).await?;
+        resp.iter_mut().for_each(|e| e.edge_name = edge_name.into());
+
+        Ok(resp)
+    }
+
+    /// Traverses one step from `start` through `edge_name`, only following
+    /// edges whose attributes match the `filter`.
+    pub async fn traverse<T: ReqModelTraits>(
+        engine: &ArangoDb,
+        edge_name: &str,
+        start: &str,
+        direction: Direction,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<T>, EngineError> {
+        let aql = ArangoDb::aql_traverse(edge_name, start, direction, filter.to_value());
+        let resp: Vec<T> = engine.db().aql_query(aql).await?;
+
+        Ok(resp)
+    }
+}
+
+/// Example document used to filter edges on their attributes.
+/// An empty filter matches every edge.
+#[derive(Debug, Default, Clone)]
+pub struct EdgeFilter(Map<String, Value>);
+
+impl EdgeFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        Ok((out._id.as_ref().unwrap().to_string(), Box::new(out)))
+    pub fn role(&mut self, role: Role) -> &mut Self {
+        self.0.insert("role".into(), Value::from(role.as_str()));
+        self
+    }
+
+    pub fn attr<V: Into<Value>>(&mut self, k: &str, v: V) -> &mut Self {
+        self.0.insert(k.to_string(), v.into());
+        self
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Object(self.0.clone())
+    }
+}
+
+#[crate::async_trait]
+impl Write<Edge> for ArangoDb {
+    type E = EngineError;
+    type Document = Edge;
+
+    async fn insert(&self, doc: Edge) -> Result<(DocId<Edge>, Box<dyn BoxedDoc>), Self::E> {
+        doc.validate()?;
+        let out = Edge::upsert(self, doc).await?;
+
+        Ok((out.id(), Box::new(out)))
     }
 
     async fn update(&self, _doc: Edge) -> Result<(), Self::E> {
@@ -123,6 +351,9 @@ impl Write<Edge> for ArangoDb {
     }
 }
 
+/// Links each child to the parent without checking either exists,
+/// evaluates to the result of every insert in the order given.
+/// Prefer `Edge::linker` when the vertices may be missing.
 #[macro_export]
 macro_rules! one_to_many {
     ($db:expr, $edge_col:expr, $parent:expr, [$($child:expr)+]) => {{
@@ -139,10 +370,57 @@ mod test {
     use crate::engine::db::arangodb::aql_snippet::FILTER;
     use crate::models::edge::*;
 
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Credit {
+        tracks: Vec<String>,
+    }
+
     #[test]
     fn test_attribute() {
         let aa = Edge::default();
         println!("Arist to Album: {:?}", aa);
         println!("aqul {}", FILTER);
     }
+
+    #[test]
+    fn test_edge_payload() -> Result<(), EngineError> {
+        let mut edge = Edge::new(
+            ARTIST_TO,
+            "artist/1".parse::<VertexId>()?,
+            "album/1".parse::<VertexId>()?,
+        );
+        edge.role(Role::Remixer).payload(Credit {
+            tracks: vec!["A1".into(), "B2".into()],
+        })?;
+        edge.attr("note", "uncredited");
+
+        let doc = edge.document();
+        assert_eq!(doc["role"], "remixer");
+        assert_eq!(doc["note"], "uncredited");
+
+        let credit: Credit = serde_json::from_value(doc.clone())?;
+        assert_eq!(edge.get_payload::<Credit>()?, credit);
+
+        let read: Edge = serde_json::from_value(doc)?;
+        assert_eq!(read.get_role(), Some(&Role::Remixer));
+        assert_eq!(
+            read.get_attr::<String>("note").as_deref(),
+            Some("uncredited")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_owns_collection() {
+        assert!(format!("{}/1", ARTIST_TO).parse::<DocId<Edge>>().is_ok());
+        assert!("album/1".parse::<DocId<Edge>>().is_err());
+        assert!("variant/1".parse::<DocId<Edge>>().is_err());
+    }
+
+    #[test]
+    fn test_role_other() {
+        let role: Role = String::from("written_by").into();
+        assert_eq!(role, Role::Other("written_by".into()));
+        assert_eq!(String::from(role), "written_by");
+    }
 }
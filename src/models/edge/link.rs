@@ -0,0 +1,314 @@
+//! Checked linking of one vertex to many through an edge collection.
+
+use std::collections::HashSet;
+use std::fmt::Formatter;
+use std::marker::PhantomData;
+
+use arangors::AqlQuery;
+use serde_json::Value;
+
+use crate::engine::db::arangodb::aql_snippet::*;
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::models::edge::{Direction, Edge, EdgeFilter, Role};
+use crate::models::id::DocId;
+use crate::models::DocDetails;
+
+/// Reason a single child could not be linked.
+#[derive(Debug)]
+pub enum LinkError {
+    ParentNotFound,
+    ChildNotFound,
+    Engine(EngineError),
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::ParentNotFound => write!(f, "Parent vertex does not exist."),
+            LinkError::ChildNotFound => write!(f, "Child vertex does not exist."),
+            LinkError::Engine(e) => write!(f, "Failed to create edge: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// Outcome of linking a single child to the parent.
+#[derive(Debug)]
+pub struct LinkResult<C> {
+    pub child: DocId<C>,
+    pub outcome: Result<Edge, LinkError>,
+}
+
+impl<C> LinkResult<C> {
+    pub fn is_ok(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+/// Builder for linking, unlinking and reordering the edges between
+/// a single parent vertex and many children.
+///
+/// By default edges point from the parent to the children, use
+/// `direction(Direction::Inbound)` to link the children to the parent instead
+/// i.e. many artists credited on one album. The parent is a document of `P`
+/// and the children documents of `C`.
+#[derive(Debug)]
+pub struct Linker<'a, P, C> {
+    engine: &'a ArangoDb,
+    edge_name: &'static str,
+    direction: Direction,
+    role: Option<Role>,
+    atomic: bool,
+    vertices: PhantomData<fn() -> (P, C)>,
+}
+
+impl<'a, P: DocDetails, C: DocDetails> Linker<'a, P, C> {
+    pub fn new(engine: &'a ArangoDb, edge_name: &'static str) -> Self {
+        Self {
+            engine,
+            edge_name,
+            direction: Direction::Outbound,
+            role: None,
+            atomic: false,
+            vertices: PhantomData,
+        }
+    }
+
+    /// Configure which side of the edge the parent sits on
+    pub fn direction(&mut self, direction: Direction) -> &mut Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Configure the role given to every edge this linker creates or touches
+    pub fn role(&mut self, role: Role) -> &mut Self {
+        self.role = Some(role);
+        self
+    }
+
+    /// When set, `link` checks and writes every edge in a single query,
+    /// either all children are linked or none are.
+    pub fn atomic(&mut self, atomic: bool) -> &mut Self {
+        self.atomic = atomic;
+        self
+    }
+
+    /// Links every child to the parent after checking both endpoints exist,
+    /// a child listed more than once is linked and reported once.
+    pub async fn link(
+        &self,
+        parent: &DocId<P>,
+        children: &[DocId<C>],
+    ) -> Result<Vec<LinkResult<C>>, EngineError> {
+        // Concurrent upserts of the same edge could both insert it
+        let mut seen = HashSet::new();
+        let children: Vec<DocId<C>> = children
+            .iter()
+            .filter(|child| seen.insert(*child))
+            .cloned()
+            .collect();
+
+        if self.atomic {
+            let edges: Vec<Edge> = self
+                .engine
+                .db()
+                .aql_query(self.aql_children(LINK_CHECKED, parent, &children))
+                .await?;
+
+            return edges
+                .into_iter()
+                .map(|mut e| {
+                    e.edge_name = self.edge_name.into();
+                    Ok(LinkResult {
+                        child: self.child_of(&e)?,
+                        outcome: Ok(e),
+                    })
+                })
+                .collect();
+        }
+
+        #[derive(Deserialize)]
+        struct Linked {
+            parent: bool,
+            child: bool,
+            edge: Option<Edge>,
+        }
+
+        // Each child is checked by the query linking it, so neither end can go missing in between
+        let jobs = children.iter().map(|child| async move {
+            let (vertex, other) = self.sides();
+            let aql = AqlQuery::builder()
+                .query(LINK_EXISTING)
+                .bind_var("@collection", self.edge_name)
+                .bind_var("vertex", vertex)
+                .bind_var("other", other)
+                .bind_var("parent", parent.as_str())
+                .bind_var("child", child.as_str())
+                .bind_var("example", self.example())
+                .build();
+            let linked: Result<Option<Linked>, EngineError> = self
+                .engine
+                .db()
+                .aql_query(aql)
+                .await
+                .map(|mut resp: Vec<Linked>| resp.pop())
+                .map_err(EngineError::from);
+            let outcome = match linked {
+                Ok(Some(Linked { parent: false, .. })) => Err(LinkError::ParentNotFound),
+                Ok(Some(Linked { child: false, .. })) => Err(LinkError::ChildNotFound),
+                Ok(Some(Linked {
+                    edge: Some(mut edge),
+                    ..
+                })) => {
+                    edge.edge_name = self.edge_name.into();
+                    Ok(edge)
+                }
+                Ok(_) => Err(LinkError::Engine(EngineError::from(
+                    DbError::FailedToCreate,
+                ))),
+                Err(e) => Err(LinkError::Engine(e)),
+            };
+            LinkResult {
+                child: child.clone(),
+                outcome,
+            }
+        });
+
+        Ok(futures::future::join_all(jobs).await)
+    }
+
+    /// Removes the edges between the parent and the given children,
+    /// returning the removed edges.
+    pub async fn unlink(
+        &self,
+        parent: &DocId<P>,
+        children: &[DocId<C>],
+    ) -> Result<Vec<Edge>, EngineError> {
+        self.run(self.aql_children(UNLINK, parent, children)).await
+    }
+
+    /// Moves `child` from `current` parent to the `target` parent,
+    /// fails without changes if `target` does not exist.
+    pub async fn relink(
+        &self,
+        child: &DocId<C>,
+        current: &DocId<P>,
+        target: &DocId<P>,
+    ) -> Result<Vec<Edge>, EngineError> {
+        let (vertex, other) = self.sides();
+        let aql = AqlQuery::builder()
+            .query(RELINK)
+            .bind_var("@collection", self.edge_name)
+            .bind_var("vertex", vertex)
+            .bind_var("other", other)
+            .bind_var("current", current.as_str())
+            .bind_var("target", target.as_str())
+            .bind_var("child", child.as_str())
+            .bind_var("example", self.example())
+            .build();
+
+        self.run(aql).await
+    }
+
+    /// Replaces every edge of the parent with links to `children`, in order.
+    /// Each edge is given a `position` attribute matching its index so
+    /// credits keep their order. Runs inside a single transaction.
+    pub async fn replace_links(
+        &self,
+        parent: &DocId<P>,
+        children: &[DocId<C>],
+    ) -> Result<Vec<Edge>, EngineError> {
+        let tx = self
+            .engine
+            .begin_transaction(vec![self.edge_name.to_string()])
+            .await?;
+
+        let stale = tx
+            .aql_query::<Edge>(self.aql_children(UNLINK_STALE, parent, children))
+            .await;
+        let linked = match stale {
+            Ok(_) => {
+                tx.aql_query::<Edge>(self.aql_children(LINK_ORDERED, parent, children))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match linked {
+            Ok(mut edges) => {
+                tx.commit().await?;
+                edges
+                    .iter_mut()
+                    .for_each(|e| e.edge_name = self.edge_name.into());
+                Ok(edges)
+            }
+            Err(e) => {
+                if let Err(abort) = tx.abort().await {
+                    log::error!(
+                        "failed to abort replacing the links of {}: {}",
+                        parent,
+                        abort
+                    );
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn run(&self, aql: AqlQuery<'_>) -> Result<Vec<Edge>, EngineError> {
+        let mut edges: Vec<Edge> = self.engine.db().aql_query(aql).await?;
+        edges
+            .iter_mut()
+            .for_each(|e| e.edge_name = self.edge_name.into());
+        Ok(edges)
+    }
+
+    fn aql_children<'q>(
+        &self,
+        query: &'q str,
+        parent: &DocId<P>,
+        children: &[DocId<C>],
+    ) -> AqlQuery<'q> {
+        let (vertex, other) = self.sides();
+        AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.edge_name)
+            .bind_var("vertex", vertex)
+            .bind_var("other", other)
+            .bind_var("parent", parent.as_str())
+            .bind_var("children", ids(children))
+            .bind_var("example", self.example())
+            .build()
+    }
+
+    /// Attribute holding the parent and the child respectively
+    fn sides(&self) -> (&'static str, &'static str) {
+        match self.direction {
+            Direction::Outbound => ("_from", "_to"),
+            Direction::Inbound => ("_to", "_from"),
+        }
+    }
+
+    fn child_of(&self, edge: &Edge) -> Result<DocId<C>, EngineError> {
+        let child = match self.direction {
+            Direction::Outbound => edge.to(),
+            Direction::Inbound => edge.from_vertex(),
+        };
+        Ok(child.typed()?)
+    }
+
+    fn example(&self) -> Value {
+        let mut filter = EdgeFilter::new();
+        if let Some(role) = self.role.clone() {
+            filter.role(role);
+        }
+        filter.to_value()
+    }
+}
+
+/// Ids as bound to a query
+fn ids<T>(docs: &[DocId<T>]) -> Vec<&str> {
+    docs.iter().map(DocId::as_str).collect()
+}
@@ -0,0 +1,211 @@
+//! Identifiers given to albums, artists and labels by the catalogues they were imported from.
+//!
+//! A document can be known to several catalogues, so models hold a list of
+//! `ExternalId`s stored as `Source - ID` strings i.e. `discogs - 123456`.
+//! Documents stored by earlier versions hold a single `foreign_key` string in
+//! the same format, upgraded to a list of one by `upgrade_foreign_key`. Being
+//! free text then, one that isn't an id is kept as `legacy_foreign_key`.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::engine::DbError;
+
+/// Catalogue an identifier belongs to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Source {
+    /// Numeric id of a Discogs artist, label or release
+    Discogs,
+    /// MBID of a MusicBrainz artist, label or release
+    MusicBrainz,
+    /// MBID of the MusicBrainz release group a release belongs to,
+    /// shared by every album of the group
+    MusicBrainzGroup,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Discogs => "discogs",
+            Source::MusicBrainz => "musicbrainz",
+            Source::MusicBrainzGroup => "musicbrainz group",
+        }
+    }
+
+    /// Whether an id of the source belongs to a single document, so documents
+    /// can be matched by it. A release group is shared by every album of the group.
+    pub fn identifies(&self) -> bool {
+        !matches!(self, Source::MusicBrainzGroup)
+    }
+}
+
+impl FromStr for Source {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "discogs" => Ok(Source::Discogs),
+            "musicbrainz" | "mbid" => Ok(Source::MusicBrainz),
+            "musicbrainz group" => Ok(Source::MusicBrainzGroup),
+            _ => Err(DbError::ParseFail),
+        }
+    }
+}
+
+/// An id checked against the format of its source
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ExternalId {
+    source: Source,
+    id: Cow<'static, str>,
+}
+
+impl ExternalId {
+    pub fn new(source: Source, id: &str) -> Result<Self, DbError> {
+        let id = id.trim();
+        let id = match source {
+            Source::Discogs => id
+                .parse::<u64>()
+                .map_err(|_| DbError::ParseFail)?
+                .to_string(),
+            Source::MusicBrainz | Source::MusicBrainzGroup => Uuid::parse_str(id)
+                .map_err(|_| DbError::ParseFail)?
+                .to_hyphenated()
+                .to_string(),
+        };
+        Ok(ExternalId {
+            source,
+            id: Cow::from(id),
+        })
+    }
+
+    pub fn discogs(id: u64) -> Self {
+        ExternalId {
+            source: Source::Discogs,
+            id: Cow::from(id.to_string()),
+        }
+    }
+
+    pub fn musicbrainz(mbid: &str) -> Result<Self, DbError> {
+        ExternalId::new(Source::MusicBrainz, mbid)
+    }
+
+    pub fn musicbrainz_group(mbid: &str) -> Result<Self, DbError> {
+        ExternalId::new(Source::MusicBrainzGroup, mbid)
+    }
+
+    pub fn source(&self) -> Source {
+        self.source
+    }
+
+    /// Id within its source, a number for Discogs and a lowercase MBID for MusicBrainz
+    pub fn id(&self) -> &str {
+        self.id.as_ref()
+    }
+}
+
+impl std::fmt::Display for ExternalId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} - {}", self.source.as_str(), self.id)
+    }
+}
+
+impl FromStr for ExternalId {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (source, id) = s.split_once(" - ").ok_or(DbError::ParseFail)?;
+        ExternalId::new(source.parse()?, id)
+    }
+}
+
+impl TryFrom<String> for ExternalId {
+    type Error = DbError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ExternalId> for String {
+    fn from(id: ExternalId) -> Self {
+        id.to_string()
+    }
+}
+
+/// Models that can be matched to records of other catalogues
+pub trait ExternalIds {
+    fn external_ids(&self) -> &[ExternalId];
+
+    /// The id given by `source`, the first when there are several
+    fn external_id_of(&self, source: Source) -> Option<&ExternalId> {
+        self.external_ids().iter().find(|id| id.source() == source)
+    }
+}
+
+/// Adds `id` to `ids` unless it is already there
+pub(crate) fn push(ids: &mut Vec<ExternalId>, id: ExternalId) {
+    if !ids.contains(&id) {
+        ids.push(id);
+    }
+}
+
+/// Upgrade moving the single `foreign_key` of documents stored before
+/// `external_ids` into the list, a blank key is dropped. A key that isn't an
+/// `ExternalId` is moved to `legacy_foreign_key` rather than failing the read.
+pub(crate) fn upgrade_foreign_key(doc: &mut Map<String, Value>) -> Result<(), DbError> {
+    if let Some(Value::String(key)) = doc.remove("foreign_key") {
+        let mut ids = match doc.remove("external_ids") {
+            Some(Value::Array(ids)) => ids,
+            _ => Vec::new(),
+        };
+        if !key.trim().is_empty() {
+            match key.parse::<ExternalId>() {
+                Ok(id) => {
+                    let id = Value::from(String::from(id));
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+                Err(_) => {
+                    log::warn!(
+                        "Kept foreign key {:?} of {} as legacy_foreign_key, it is not an external id",
+                        key,
+                        doc.get("_id").and_then(Value::as_str).unwrap_or("document")
+                    );
+                    doc.insert("legacy_foreign_key".to_string(), Value::from(key));
+                }
+            }
+        }
+        doc.insert("external_ids".to_string(), Value::Array(ids));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::external_id::*;
+
+    #[test]
+    fn test_parse() {
+        let id: ExternalId = "discogs - 123456".parse().unwrap();
+        assert_eq!(id, ExternalId::discogs(123456));
+        assert_eq!(id.to_string(), "discogs - 123456");
+
+        let mbid = ExternalId::musicbrainz("B10BBBFC-CF9E-42E0-BE17-E2C3E1D2600D").unwrap();
+        assert_eq!(mbid.id(), "b10bbbfc-cf9e-42e0-be17-e2c3e1d2600d");
+        assert_eq!(
+            mbid.to_string().parse::<ExternalId>().unwrap().source(),
+            Source::MusicBrainz
+        );
+
+        assert!("discogs - abc".parse::<ExternalId>().is_err());
+        assert!("musicbrainz - 123".parse::<ExternalId>().is_err());
+        assert!("123456".parse::<ExternalId>().is_err());
+    }
+}
@@ -0,0 +1,432 @@
+//! Physical format of a release, modelled on the format descriptions used by Discogs
+//! i.e. `2 x Vinyl, 12", 33 RPM, LP, Album, Reissue, Red, Limited Edition #12/500`.
+
+use std::borrow::Cow;
+use std::fmt::Formatter;
+
+use serde_json::{Map, Value};
+
+use crate::engine::DbError;
+
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Medium {
+    #[default]
+    Vinyl,
+    /// Pre vinyl 78s
+    Shellac,
+    CD,
+    Cassette,
+    #[serde(rename = "Reel-To-Reel")]
+    ReelToReel,
+    MiniDisc,
+}
+
+impl Medium {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Medium::Vinyl => "Vinyl",
+            Medium::Shellac => "Shellac",
+            Medium::CD => "CD",
+            Medium::Cassette => "Cassette",
+            Medium::ReelToReel => "Reel-To-Reel",
+            Medium::MiniDisc => "MiniDisc",
+        }
+    }
+}
+
+/// Diameter of a disc or reel in inches
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Size {
+    #[serde(rename = "7\"")]
+    Seven,
+    #[serde(rename = "10\"")]
+    Ten,
+    #[serde(rename = "12\"")]
+    Twelve,
+}
+
+impl Size {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Size::Seven => "7\"",
+            Size::Ten => "10\"",
+            Size::Twelve => "12\"",
+        }
+    }
+}
+
+/// Playing speed in revolutions per minute
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Speed {
+    #[serde(rename = "33 RPM")]
+    Rpm33,
+    #[serde(rename = "45 RPM")]
+    Rpm45,
+    #[serde(rename = "78 RPM")]
+    Rpm78,
+}
+
+impl Speed {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Speed::Rpm33 => "33 RPM",
+            Speed::Rpm45 => "45 RPM",
+            Speed::Rpm78 => "78 RPM",
+        }
+    }
+}
+
+/// Free form qualifier of a format, `Other` keeps descriptors not listed here
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Descriptor {
+    LP,
+    EP,
+    Single,
+    Album,
+    Compilation,
+    Reissue,
+    Remastered,
+    PictureDisc,
+    BoxSet,
+    LimitedEdition,
+    Other(Cow<'static, str>),
+}
+
+impl Descriptor {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Descriptor::LP => "LP",
+            Descriptor::EP => "EP",
+            Descriptor::Single => "Single",
+            Descriptor::Album => "Album",
+            Descriptor::Compilation => "Compilation",
+            Descriptor::Reissue => "Reissue",
+            Descriptor::Remastered => "Remastered",
+            Descriptor::PictureDisc => "Picture Disc",
+            Descriptor::BoxSet => "Box Set",
+            Descriptor::LimitedEdition => "Limited Edition",
+            Descriptor::Other(s) => s.as_ref(),
+        }
+    }
+}
+
+impl From<String> for Descriptor {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "LP" => Descriptor::LP,
+            "EP" => Descriptor::EP,
+            "Single" => Descriptor::Single,
+            "Album" => Descriptor::Album,
+            "Compilation" => Descriptor::Compilation,
+            "Reissue" => Descriptor::Reissue,
+            "Remastered" => Descriptor::Remastered,
+            "Picture Disc" => Descriptor::PictureDisc,
+            "Box Set" => Descriptor::BoxSet,
+            "Limited Edition" => Descriptor::LimitedEdition,
+            _ => Descriptor::Other(Cow::from(s)),
+        }
+    }
+}
+
+impl From<Descriptor> for String {
+    fn from(d: Descriptor) -> Self {
+        d.as_str().to_string()
+    }
+}
+
+/// Position of a copy within a numbered run i.e. `#12/500`, either part may be unknown
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Numbering {
+    #[serde(default)]
+    pub number: Option<u32>,
+    /// Number of copies in the run
+    #[serde(default)]
+    pub of: Option<u32>,
+}
+
+impl std::fmt::Display for Numbering {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.number, self.of) {
+            (Some(n), Some(of)) => write!(f, "#{}/{}", n, of),
+            (Some(n), None) => write!(f, "#{}", n),
+            (None, Some(of)) => write!(f, "of {}", of),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Physical format of a variant.
+///
+/// Stored flattened into the `Variant` document so its fields can be filtered on,
+/// documents written with the earlier `medium` and `edition` fields still deserialize.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "FormatDoc")]
+pub struct Format {
+    medium: Medium,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<Size>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<Speed>,
+    /// Number of discs, tapes or reels
+    discs: u16,
+    descriptors: Vec<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    colour: Option<Cow<'static, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    numbering: Option<Numbering>,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::new(Medium::default())
+    }
+}
+
+impl Format {
+    pub fn new(medium: Medium) -> Self {
+        Format {
+            medium,
+            size: None,
+            speed: None,
+            discs: 1,
+            descriptors: Vec::new(),
+            colour: None,
+            numbering: None,
+        }
+    }
+
+    pub fn medium(&mut self, medium: Medium) -> &mut Self {
+        self.medium = medium;
+        self
+    }
+
+    pub fn size(&mut self, size: Size) -> &mut Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn speed(&mut self, speed: Speed) -> &mut Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    pub fn discs(&mut self, discs: u16) -> &mut Self {
+        self.discs = discs;
+        self
+    }
+
+    /// Adds a descriptor, ignoring ones already present
+    pub fn descriptor(&mut self, descriptor: Descriptor) -> &mut Self {
+        if !self.descriptors.contains(&descriptor) {
+            self.descriptors.push(descriptor);
+        }
+        self
+    }
+
+    pub fn colour<T: Into<Cow<'static, str>>>(&mut self, colour: T) -> &mut Self {
+        self.colour = Some(colour.into());
+        self
+    }
+
+    /// Marks this as a limited edition, numbered within the run when `number` is known
+    pub fn limited(&mut self, number: Option<u32>, of: Option<u32>) -> &mut Self {
+        self.descriptor(Descriptor::LimitedEdition);
+        if number.is_some() || of.is_some() {
+            self.numbering = Some(Numbering { number, of });
+        }
+        self
+    }
+
+    pub fn get_medium(&self) -> Medium {
+        self.medium
+    }
+
+    pub fn get_size(&self) -> Option<Size> {
+        self.size
+    }
+
+    pub fn get_speed(&self) -> Option<Speed> {
+        self.speed
+    }
+
+    pub fn get_discs(&self) -> u16 {
+        self.discs
+    }
+
+    pub fn get_descriptors(&self) -> &[Descriptor] {
+        &self.descriptors
+    }
+
+    pub fn get_colour(&self) -> Option<&str> {
+        self.colour.as_deref()
+    }
+
+    pub fn get_numbering(&self) -> Option<Numbering> {
+        self.numbering
+    }
+
+    pub fn has(&self, descriptor: &Descriptor) -> bool {
+        self.descriptors.contains(descriptor)
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.has(&Descriptor::LimitedEdition) || self.numbering.is_some()
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.discs > 1 {
+            write!(f, "{} x ", self.discs)?;
+        }
+        write!(f, "{}", self.medium.as_str())?;
+        if let Some(size) = self.size {
+            write!(f, ", {}", size.as_str())?;
+        }
+        if let Some(speed) = self.speed {
+            write!(f, ", {}", speed.as_str())?;
+        }
+        for descriptor in &self.descriptors {
+            write!(f, ", {}", descriptor.as_str())?;
+        }
+        if let Some(colour) = &self.colour {
+            write!(f, ", {}", colour)?;
+        }
+        match self.numbering {
+            Some(n) if n.number.is_some() || n.of.is_some() => write!(f, " {}", n),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Edition of a variant as stored before `Format`
+#[derive(Debug, Deserialize, Copy, Clone)]
+enum Edition {
+    Standard,
+    Limited,
+}
+
+/// Stored form of `Format`, accepting the fields of earlier versions
+#[derive(Deserialize)]
+struct FormatDoc {
+    #[serde(default)]
+    medium: Medium,
+    #[serde(default)]
+    size: Option<Size>,
+    #[serde(default)]
+    speed: Option<Speed>,
+    #[serde(default)]
+    discs: Option<u16>,
+    #[serde(default)]
+    descriptors: Vec<Descriptor>,
+    #[serde(default)]
+    colour: Option<Cow<'static, str>>,
+    #[serde(default)]
+    numbering: Option<Numbering>,
+    #[serde(default)]
+    edition: Option<Edition>,
+}
+
+impl From<FormatDoc> for Format {
+    fn from(doc: FormatDoc) -> Self {
+        let mut format = Format {
+            medium: doc.medium,
+            size: doc.size,
+            speed: doc.speed,
+            discs: doc.discs.unwrap_or(1),
+            descriptors: doc.descriptors,
+            colour: doc.colour,
+            numbering: doc.numbering,
+        };
+        match doc.edition {
+            Some(Edition::Limited) => {
+                format.descriptor(Descriptor::LimitedEdition);
+            }
+            Some(Edition::Standard) | None => {}
+        }
+        format
+    }
+}
+
+/// Upgrade replacing the `edition` of variants stored before `Format` with the
+/// `Limited Edition` descriptor, a variant without `discs` has one.
+pub(crate) fn upgrade_edition(doc: &mut Map<String, Value>) -> Result<(), DbError> {
+    let format: Format =
+        serde_json::from_value(Value::Object(doc.clone())).map_err(|_| DbError::ParseFail)?;
+    doc.remove("edition");
+    if let Value::Object(fields) = serde_json::to_value(&format).map_err(|_| DbError::ParseFail)? {
+        doc.extend(fields);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::models::format::*;
+
+    #[test]
+    fn test_display() {
+        let mut format = Format::new(Medium::Vinyl);
+        format
+            .discs(2)
+            .size(Size::Twelve)
+            .speed(Speed::Rpm33)
+            .descriptor(Descriptor::LP)
+            .descriptor(Descriptor::Reissue)
+            .colour("Red")
+            .limited(Some(12), Some(500));
+        assert_eq!(
+            format.to_string(),
+            "2 x Vinyl, 12\", 33 RPM, LP, Reissue, Limited Edition, Red #12/500"
+        );
+        assert_eq!(Format::new(Medium::MiniDisc).to_string(), "MiniDisc");
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut format = Format::new(Medium::Vinyl);
+        format
+            .size(Size::Seven)
+            .speed(Speed::Rpm45)
+            .descriptor(Descriptor::Other("Promo".into()));
+        let v = serde_json::to_value(&format).unwrap();
+        assert_eq!(
+            v,
+            json!({
+                "medium": "Vinyl",
+                "size": "7\"",
+                "speed": "45 RPM",
+                "discs": 1,
+                "descriptors": ["Promo"],
+            })
+        );
+        assert_eq!(serde_json::from_value::<Format>(v).unwrap(), format);
+    }
+
+    #[test]
+    fn test_read_legacy() {
+        let format: Format =
+            serde_json::from_value(json!({"medium": "CD", "edition": "Limited"})).unwrap();
+        assert_eq!(format.get_medium(), Medium::CD);
+        assert_eq!(format.get_discs(), 1);
+        assert!(format.is_limited());
+    }
+
+    #[test]
+    fn test_upgrade_edition() {
+        let mut doc = json!({"medium": "CD", "edition": "Limited", "details": "kept"});
+        upgrade_edition(doc.as_object_mut().unwrap()).unwrap();
+        assert_eq!(
+            doc,
+            json!({
+                "medium": "CD",
+                "discs": 1,
+                "descriptors": ["Limited Edition"],
+                "details": "kept",
+            })
+        );
+    }
+}
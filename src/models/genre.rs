@@ -0,0 +1,68 @@
+use std::borrow::Cow;
+
+use crate::macros::*;
+use crate::models::id::{DocId, DocKey};
+use crate::models::key::Keyed;
+
+#[include_database_fields(timestamp)]
+/// Genre or style, arranged in a tree i.e. `electronic` > `techno` > `detroit techno`
+#[derive(Debug, Clone, ModelTrait, WriteToArango, Validate, Default, Deserialize, Serialize)]
+#[key(slug = "name")]
+#[serde(remote = "Self")]
+pub struct Genre {
+    /// ArangonDb _id
+    #[serde(rename(deserialize = "_id", serialize = "_id"))]
+    id: DocId<Genre>,
+    /// ArangonDb _key
+    #[serde(rename(deserialize = "_key", serialize = "_key"))]
+    key: DocKey<Genre>,
+    /// Genre name, stored lowercase
+    #[validate(non_empty, max_length = 200)]
+    name: Cow<'static, str>,
+    /// `_id` of the broader genre this is a sub genre of,
+    /// kept in step with the `sub_genre` edge by `service::genre`
+    #[serde(default)]
+    parent: Option<DocId<Genre>>,
+    #[serde(default)]
+    description: Cow<'static, str>,
+}
+
+crate::versioned!(Genre);
+
+impl Genre {
+    /// Creates a new blank genre, keyed by a slug of its name when inserted
+    pub fn new() -> Self {
+        let mut genre = Genre::default();
+        genre.new_key();
+        genre
+    }
+
+    pub fn name<T: AsRef<str>>(&mut self, name: T) -> &mut Self {
+        self.name = Cow::from(name.as_ref().trim().to_ascii_lowercase());
+        self
+    }
+
+    pub fn description<T: Into<Cow<'static, str>>>(&mut self, desc: T) -> &mut Self {
+        self.description = desc.into();
+        self
+    }
+
+    /// Sets the parent of a new genre, use `service::genre::set_parent`
+    /// for stored genres so the tree is updated too.
+    pub fn parent(&mut self, parent_id: DocId<Genre>) -> &mut Self {
+        self.parent = Some(parent_id);
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    pub fn get_description(&self) -> &str {
+        self.description.as_ref()
+    }
+
+    pub fn get_parent(&self) -> Option<&DocId<Genre>> {
+        self.parent.as_ref()
+    }
+}
@@ -0,0 +1,308 @@
+//! Condition grading of records and sleeves.
+//!
+//! Grades follow the Discogs scale
+//! https://support.discogs.com/hc/en-us/articles/360001566193-How-To-Grade-Items
+//! and can be parsed from either the Discogs condition strings i.e. `Near Mint (NM or M-)`
+//! or the short Goldmine style notation i.e. `NM`, `VG+`.
+
+use std::convert::TryFrom;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+use crate::engine::DbError;
+
+/// Condition of a record, ordered from worst to best so `Grade::VeryGoodPlus > Grade::Good`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Grade {
+    Poor,
+    Fair,
+    Good,
+    GoodPlus,
+    VeryGood,
+    VeryGoodPlus,
+    NearMint,
+    Mint,
+}
+
+impl Grade {
+    /// Every grade from worst to best
+    pub const SCALE: [Grade; 8] = [
+        Grade::Poor,
+        Grade::Fair,
+        Grade::Good,
+        Grade::GoodPlus,
+        Grade::VeryGood,
+        Grade::VeryGoodPlus,
+        Grade::NearMint,
+        Grade::Mint,
+    ];
+
+    /// Short notation i.e. `VG+`
+    pub fn abbreviation(&self) -> &'static str {
+        match self {
+            Grade::Poor => "P",
+            Grade::Fair => "F",
+            Grade::Good => "G",
+            Grade::GoodPlus => "G+",
+            Grade::VeryGood => "VG",
+            Grade::VeryGoodPlus => "VG+",
+            Grade::NearMint => "NM",
+            Grade::Mint => "M",
+        }
+    }
+
+    /// Condition string used by Discogs i.e. `Very Good Plus (VG+)`
+    pub fn discogs(&self) -> &'static str {
+        match self {
+            Grade::Poor => "Poor (P)",
+            Grade::Fair => "Fair (F)",
+            Grade::Good => "Good (G)",
+            Grade::GoodPlus => "Good Plus (G+)",
+            Grade::VeryGood => "Very Good (VG)",
+            Grade::VeryGoodPlus => "Very Good Plus (VG+)",
+            Grade::NearMint => "Near Mint (NM or M-)",
+            Grade::Mint => "Mint (M)",
+        }
+    }
+
+    /// This grade and every better one, i.e. for "at least VG+" filters
+    pub fn at_least(&self) -> Vec<Grade> {
+        Grade::SCALE.iter().copied().filter(|g| g >= self).collect()
+    }
+}
+
+impl std::fmt::Display for Grade {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.abbreviation())
+    }
+}
+
+impl FromStr for Grade {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // Discogs strings carry the abbreviation in brackets
+        let short = match (s.rfind('('), s.ends_with(')')) {
+            (Some(i), true) => &s[i + 1..s.len() - 1],
+            _ => s,
+        };
+        let grade = match short.trim().to_ascii_uppercase().as_str() {
+            "M" | "MINT" => Grade::Mint,
+            "NM" | "M-" | "NM OR M-" | "NEAR MINT" => Grade::NearMint,
+            // `EX`/`VG++` sit between VG+ and NM, rounded down
+            "VG+" | "VGP" | "VG++" | "EX" | "VERY GOOD PLUS" => Grade::VeryGoodPlus,
+            "VG" | "VERY GOOD" => Grade::VeryGood,
+            "G+" | "GP" | "GOOD PLUS" => Grade::GoodPlus,
+            "G" | "GOOD" => Grade::Good,
+            "F" | "FAIR" => Grade::Fair,
+            "P" | "POOR" => Grade::Poor,
+            _ => return Err(DbError::ParseFail),
+        };
+        Ok(grade)
+    }
+}
+
+impl TryFrom<String> for Grade {
+    type Error = DbError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Grade> for String {
+    fn from(g: Grade) -> Self {
+        g.abbreviation().to_string()
+    }
+}
+
+/// Condition of a sleeve, which unlike the media may be missing or generic
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SleeveGrade {
+    Graded(Grade),
+    /// Plain replacement sleeve, not the original artwork
+    Generic,
+    NoCover,
+    #[default]
+    NotGraded,
+}
+
+impl SleeveGrade {
+    pub fn grade(&self) -> Option<Grade> {
+        match self {
+            SleeveGrade::Graded(g) => Some(*g),
+            _ => None,
+        }
+    }
+
+    /// Condition string used by Discogs
+    pub fn discogs(&self) -> &'static str {
+        match self {
+            SleeveGrade::Graded(g) => g.discogs(),
+            SleeveGrade::Generic => "Generic",
+            SleeveGrade::NoCover => "No Cover",
+            SleeveGrade::NotGraded => "Not Graded",
+        }
+    }
+}
+
+impl std::fmt::Display for SleeveGrade {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SleeveGrade::Graded(g) => write!(f, "{}", g),
+            _ => write!(f, "{}", self.discogs()),
+        }
+    }
+}
+
+impl FromStr for SleeveGrade {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "generic" => Ok(SleeveGrade::Generic),
+            "no cover" => Ok(SleeveGrade::NoCover),
+            "not graded" | "" => Ok(SleeveGrade::NotGraded),
+            _ => s.parse().map(SleeveGrade::Graded),
+        }
+    }
+}
+
+impl TryFrom<String> for SleeveGrade {
+    type Error = DbError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<SleeveGrade> for String {
+    fn from(g: SleeveGrade) -> Self {
+        g.to_string()
+    }
+}
+
+impl From<Grade> for SleeveGrade {
+    fn from(g: Grade) -> Self {
+        SleeveGrade::Graded(g)
+    }
+}
+
+/// Minimum media and sleeve grades a variant must meet
+#[derive(Debug, Default, Copy, Clone)]
+pub struct GradeFilter {
+    media: Option<Grade>,
+    sleeve: Option<Grade>,
+}
+
+impl GradeFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn media_at_least(&mut self, grade: Grade) -> &mut Self {
+        self.media = Some(grade);
+        self
+    }
+
+    /// Sleeves that are generic, missing or ungraded never match once this is set
+    pub fn sleeve_at_least(&mut self, grade: Grade) -> &mut Self {
+        self.sleeve = Some(grade);
+        self
+    }
+
+    pub fn matches(&self, media: Grade, sleeve: SleeveGrade) -> bool {
+        let media_ok = self.media.is_none_or(|min| media >= min);
+        let sleeve_ok = self
+            .sleeve
+            .is_none_or(|min| sleeve.grade().is_some_and(|g| g >= min));
+        media_ok && sleeve_ok
+    }
+
+    /// Accepted media grades, `None` when any grade is accepted
+    pub fn media_grades(&self) -> Option<Vec<Grade>> {
+        self.media.map(|g| g.at_least())
+    }
+
+    /// Accepted sleeve grades, `None` when any sleeve is accepted
+    pub fn sleeve_grades(&self) -> Option<Vec<Grade>> {
+        self.sleeve.map(|g| g.at_least())
+    }
+}
+
+/// Upgrade moving the `quality` of variants stored before grading to `media_grade`
+/// in the current notation, their sleeve is written as not graded.
+pub(crate) fn upgrade_quality(doc: &mut Map<String, Value>) -> Result<(), DbError> {
+    if let Some(quality) = doc.remove("quality") {
+        let grade: Grade = quality.as_str().ok_or(DbError::ParseFail)?.parse()?;
+        doc.insert("media_grade".to_string(), Value::from(String::from(grade)));
+        doc.entry("sleeve_grade")
+            .or_insert_with(|| Value::from(String::from(SleeveGrade::NotGraded)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::grade::*;
+
+    #[test]
+    fn test_parse_notations() {
+        assert_eq!("VG+".parse::<Grade>().unwrap(), Grade::VeryGoodPlus);
+        assert_eq!("NM or M-".parse::<Grade>().unwrap(), Grade::NearMint);
+        assert_eq!("Near Mint (NM or M-)".parse::<Grade>().unwrap(), Grade::NearMint);
+        assert_eq!("g+".parse::<Grade>().unwrap(), Grade::GoodPlus);
+        // Names used by the previous `Quality` enum
+        assert_eq!("Vgp".parse::<Grade>().unwrap(), Grade::VeryGoodPlus);
+        assert_eq!("GP".parse::<Grade>().unwrap(), Grade::GoodPlus);
+        assert!("VG-".parse::<Grade>().is_err());
+
+        assert_eq!("No Cover".parse::<SleeveGrade>().unwrap(), SleeveGrade::NoCover);
+        assert_eq!(
+            "Very Good (VG)".parse::<SleeveGrade>().unwrap(),
+            SleeveGrade::Graded(Grade::VeryGood)
+        );
+    }
+
+    #[test]
+    fn test_ordering_and_filter() {
+        assert!(Grade::Mint > Grade::NearMint);
+        assert!(Grade::GoodPlus < Grade::VeryGood);
+        assert_eq!(
+            Grade::VeryGoodPlus.at_least(),
+            vec![Grade::VeryGoodPlus, Grade::NearMint, Grade::Mint]
+        );
+
+        let mut filter = GradeFilter::new();
+        filter
+            .media_at_least(Grade::VeryGoodPlus)
+            .sleeve_at_least(Grade::VeryGood);
+        assert!(filter.matches(Grade::NearMint, Grade::VeryGood.into()));
+        assert!(!filter.matches(Grade::VeryGood, Grade::Mint.into()));
+        assert!(!filter.matches(Grade::Mint, SleeveGrade::Generic));
+    }
+
+    #[test]
+    fn test_upgrade_quality() {
+        let mut doc = serde_json::json!({"quality": "GP"});
+        upgrade_quality(doc.as_object_mut().unwrap()).unwrap();
+        assert_eq!(
+            doc,
+            serde_json::json!({"media_grade": "G+", "sleeve_grade": "Not Graded"})
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let v = serde_json::to_value(Grade::VeryGoodPlus).unwrap();
+        assert_eq!(v, "VG+");
+        let s: SleeveGrade = serde_json::from_value(serde_json::json!("Generic")).unwrap();
+        assert_eq!(s, SleeveGrade::Generic);
+        assert_eq!(serde_json::to_value(s).unwrap(), "Generic");
+    }
+}
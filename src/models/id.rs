@@ -0,0 +1,499 @@
+//! Typed `_id` and `_key` of documents.
+//!
+//! A `DocId<T>` is the `collection/key` of a document of the model `T` and a
+//! `DocKey<T>` its key, so the id of an album can't be passed where the id of
+//! an artist is wanted. Both are checked as they are parsed and are written as
+//! plain strings. A blank id or key, as held by a default model, stands for one
+//! not made yet. A `VertexId` is the checked id of a document of any model, as
+//! held by the `_from` and `_to` of an edge.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt::Formatter;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::engine::DbError;
+use crate::models::validate::{rules, Text};
+use crate::models::DocDetails;
+
+/// `_key` of a document of `T`
+pub struct DocKey<T> {
+    key: Cow<'static, str>,
+    model: PhantomData<fn() -> T>,
+}
+
+/// `_id` of a document of `T`, its collection and key joined by `/`
+pub struct DocId<T> {
+    id: Cow<'static, str>,
+    model: PhantomData<fn() -> T>,
+}
+
+impl<T> DocKey<T> {
+    /// Checks `key` holds only the characters ArangoDb allows in a key
+    pub fn new<K: Into<Cow<'static, str>>>(key: K) -> Result<Self, DbError> {
+        let key = key.into();
+        if key.is_empty() || rules::key(key.as_ref()).is_err() {
+            return Err(DbError::InvalidIdentification);
+        }
+        Ok(DocKey::valid(key))
+    }
+
+    /// A key known to be valid i.e. one made by a `KeyStrategy`
+    pub(crate) fn valid<K: Into<Cow<'static, str>>>(key: K) -> Self {
+        DocKey {
+            key: key.into(),
+            model: PhantomData,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.key.as_ref()
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.key.is_empty()
+    }
+}
+
+impl<T: DocDetails> DocKey<T> {
+    /// Id of the document with this key, blank for a blank key
+    pub fn id(&self) -> DocId<T> {
+        if self.is_blank() {
+            return DocId::default();
+        }
+        DocId {
+            id: format!("{}/{}", T::collection_name(), self.key).into(),
+            model: PhantomData,
+        }
+    }
+}
+
+impl<T> DocId<T> {
+    pub fn as_str(&self) -> &str {
+        self.id.as_ref()
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.id.is_empty()
+    }
+
+    /// Collection the document is stored in
+    pub fn collection(&self) -> &str {
+        self.id.split_once('/').map_or("", |(c, _)| c)
+    }
+
+    pub fn key(&self) -> DocKey<T> {
+        let key = self.id.split_once('/').map_or("", |(_, k)| k);
+        DocKey::valid(key.to_string())
+    }
+}
+
+impl<T: DocDetails> DocId<T> {
+    /// Checks `id` is the `collection/key` of a document of `T`
+    pub fn parse<I: Into<Cow<'static, str>>>(id: I) -> Result<Self, DbError> {
+        let id = id.into();
+        match id.split_once('/') {
+            Some((collection, key)) if T::owns_collection(collection) => {
+                DocKey::<T>::new(key.to_string())?;
+            }
+            _ => return Err(DbError::InvalidIdentification),
+        }
+        Ok(DocId {
+            id,
+            model: PhantomData,
+        })
+    }
+}
+
+/// `_id` of a document of any model, the end of an edge
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct VertexId(Cow<'static, str>);
+
+impl VertexId {
+    /// Checks `id` is the `collection/key` of a document
+    pub fn parse<I: Into<Cow<'static, str>>>(id: I) -> Result<Self, DbError> {
+        let id = id.into();
+        if id.is_empty() || rules::any_id(id.as_ref()).is_err() {
+            return Err(DbError::InvalidIdentification);
+        }
+        Ok(VertexId(id))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Id of a document of `T`, if the vertex is one
+    pub fn typed<T: DocDetails>(&self) -> Result<DocId<T>, DbError> {
+        DocId::parse(self.0.clone())
+    }
+}
+
+// Implemented by hand as derives would only apply when `T` implements them
+
+impl<T> Clone for DocKey<T> {
+    fn clone(&self) -> Self {
+        DocKey::valid(self.key.clone())
+    }
+}
+
+impl<T> Clone for DocId<T> {
+    fn clone(&self) -> Self {
+        DocId {
+            id: self.id.clone(),
+            model: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for DocKey<T> {
+    fn default() -> Self {
+        DocKey::valid("")
+    }
+}
+
+impl<T> Default for DocId<T> {
+    fn default() -> Self {
+        DocId {
+            id: Cow::from(""),
+            model: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for DocKey<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T> Eq for DocKey<T> {}
+
+impl<T> PartialEq for DocId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for DocId<T> {}
+
+impl<T> PartialEq<str> for DocId<T> {
+    fn eq(&self, other: &str) -> bool {
+        self.id == other
+    }
+}
+
+impl<T> PartialEq<&str> for DocId<T> {
+    fn eq(&self, other: &&str) -> bool {
+        self.id == *other
+    }
+}
+
+impl<T> Hash for DocKey<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state)
+    }
+}
+
+impl<T> Hash for DocId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl<T> std::fmt::Debug for DocKey<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.key)
+    }
+}
+
+impl<T> std::fmt::Debug for DocId<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.id)
+    }
+}
+
+impl<T> std::fmt::Display for DocKey<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.key)
+    }
+}
+
+impl<T> std::fmt::Display for DocId<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+impl<T> AsRef<str> for DocKey<T> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<T> AsRef<str> for DocId<T> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<T> Deref for DocKey<T> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<T> Deref for DocId<T> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<T> FromStr for DocKey<T> {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DocKey::new(s.to_string())
+    }
+}
+
+impl<T: DocDetails> FromStr for DocId<T> {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DocId::parse(s.to_string())
+    }
+}
+
+impl<T: DocDetails> TryFrom<String> for DocId<T> {
+    type Error = DbError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        DocId::parse(s)
+    }
+}
+
+impl<T: DocDetails> TryFrom<&str> for DocId<T> {
+    type Error = DbError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl<T> From<DocId<T>> for String {
+    fn from(id: DocId<T>) -> Self {
+        id.id.into_owned()
+    }
+}
+
+impl<T> From<DocId<T>> for Cow<'static, str> {
+    fn from(id: DocId<T>) -> Self {
+        id.id
+    }
+}
+
+impl<T> From<&DocId<T>> for Cow<'static, str> {
+    fn from(id: &DocId<T>) -> Self {
+        id.id.clone()
+    }
+}
+
+impl<T> From<DocKey<T>> for String {
+    fn from(key: DocKey<T>) -> Self {
+        key.key.into_owned()
+    }
+}
+
+impl<T> From<DocKey<T>> for Value {
+    fn from(key: DocKey<T>) -> Self {
+        Value::from(String::from(key))
+    }
+}
+
+impl<T> From<DocId<T>> for Value {
+    fn from(id: DocId<T>) -> Self {
+        Value::from(String::from(id))
+    }
+}
+
+impl<T> From<DocId<T>> for VertexId {
+    fn from(id: DocId<T>) -> Self {
+        VertexId(id.id)
+    }
+}
+
+impl<T> From<&DocId<T>> for VertexId {
+    fn from(id: &DocId<T>) -> Self {
+        VertexId(id.id.clone())
+    }
+}
+
+impl<T> PartialEq<VertexId> for DocId<T> {
+    fn eq(&self, other: &VertexId) -> bool {
+        self.id == other.0
+    }
+}
+
+impl PartialEq<str> for VertexId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl std::fmt::Display for VertexId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for VertexId {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl FromStr for VertexId {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        VertexId::parse(s.to_string())
+    }
+}
+
+impl From<VertexId> for Value {
+    fn from(id: VertexId) -> Self {
+        Value::from(id.0.into_owned())
+    }
+}
+
+impl Text for VertexId {
+    fn text(&self) -> Option<&str> {
+        Some(self.as_str())
+    }
+}
+
+impl Serialize for VertexId {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.0)
+    }
+}
+
+/// A blank string is read as a blank id, as written for a default edge
+impl<'de> Deserialize<'de> for VertexId {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(d)?;
+        if id.is_empty() {
+            return Ok(VertexId::default());
+        }
+        VertexId::parse(id).map_err(D::Error::custom)
+    }
+}
+
+impl<T> Text for DocKey<T> {
+    fn text(&self) -> Option<&str> {
+        Some(self.as_str())
+    }
+}
+
+impl<T> Text for DocId<T> {
+    fn text(&self) -> Option<&str> {
+        Some(self.as_str())
+    }
+}
+
+impl<T> Serialize for DocKey<T> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.key)
+    }
+}
+
+impl<T> Serialize for DocId<T> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.id)
+    }
+}
+
+/// A blank string is read as a blank key, as written for a default model
+impl<'de, T> Deserialize<'de> for DocKey<T> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(d)?;
+        if key.is_empty() {
+            return Ok(DocKey::default());
+        }
+        DocKey::new(key).map_err(D::Error::custom)
+    }
+}
+
+/// A blank string is read as a blank id, as written for a default model
+impl<'de, T: DocDetails> Deserialize<'de> for DocId<T> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(d)?;
+        if id.is_empty() {
+            return Ok(DocId::default());
+        }
+        DocId::parse(id).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::album::Album;
+    use crate::models::artist::Artist;
+    use crate::models::id::*;
+
+    #[test]
+    fn test_parse() {
+        let id: DocId<Album> = "album/123".parse().unwrap();
+        assert_eq!(id.collection(), "album");
+        assert_eq!(id.key(), DocKey::new("123").unwrap());
+        assert_eq!(id.key().id(), id);
+
+        assert!("artist/123".parse::<DocId<Album>>().is_err());
+        assert!("album".parse::<DocId<Album>>().is_err());
+        assert!("album/".parse::<DocId<Album>>().is_err());
+        assert!("album/a/b".parse::<DocId<Album>>().is_err());
+        assert!("a b".parse::<DocKey<Artist>>().is_err());
+        assert!(DocKey::<Artist>::default().id().is_blank());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let id: DocId<Album> = "album/123".parse().unwrap();
+        let value = serde_json::to_value(&id).unwrap();
+        assert_eq!(value, serde_json::json!("album/123"));
+        assert_eq!(serde_json::from_value::<DocId<Album>>(value).unwrap(), id);
+
+        assert!(serde_json::from_value::<DocId<Artist>>(serde_json::json!("album/123")).is_err());
+        let blank: DocId<Album> = serde_json::from_value(serde_json::json!("")).unwrap();
+        assert!(blank.is_blank());
+    }
+
+    #[test]
+    fn test_vertex() {
+        let id: DocId<Album> = "album/123".parse().unwrap();
+        let vertex = VertexId::from(&id);
+        assert_eq!(id, vertex);
+        assert_eq!(vertex.typed::<Album>().unwrap(), id);
+        assert!(vertex.typed::<Artist>().is_err());
+
+        assert!("album".parse::<VertexId>().is_err());
+        assert!("".parse::<VertexId>().is_err());
+        assert!(serde_json::from_value::<VertexId>(serde_json::json!("a b/1")).is_err());
+    }
+}
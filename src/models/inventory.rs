@@ -1,33 +1,40 @@
-use std::borrow::Cow;
-
-use uuid::Uuid;
-
 use crate::macros::*;
+use crate::models::id::{DocId, DocKey};
+use crate::models::key::Keyed;
 
 #[include_database_fields(timestamp)]
-#[derive(Debug, Clone, ModelTrait, WriteToArango, Default, Deserialize, Serialize)]
+/// Stock held of a single `Variant`
+#[derive(Debug, Clone, ModelTrait, WriteToArango, Validate, Default, Deserialize, Serialize)]
+#[key(uuid)]
+#[serde(remote = "Self")]
 pub struct Inventory {
     /// ArangonDb _id
     #[serde(rename(deserialize = "_id", serialize = "_id"))]
-    id: Cow<'static, str>,
+    id: DocId<Inventory>,
     /// ArangonDb _key
     #[serde(rename(deserialize = "_key", serialize = "_key"))]
-    key: Cow<'static, str>,
-    count: u8,
+    key: DocKey<Inventory>,
+    /// Copies in stock, only changed through `service::inventory` once stored
+    /// so every change is recorded in the ledger
+    #[validate(read_only)]
+    count: u32,
 }
 
+crate::versioned!(Inventory);
+
 impl Inventory {
     pub fn new() -> Self {
-        let uid = Uuid::new_v4().to_string()[0..8].to_string();
-        Inventory {
-            id: format!("{}/{}", Self::collection_name(), &uid).into(),
-            key: Cow::from(uid),
-            ..Inventory::default()
-        }
+        let mut inventory = Inventory::default();
+        inventory.new_key();
+        inventory
     }
 
-    pub fn amount(&mut self, count: u8) -> &mut Self {
+    pub fn amount(&mut self, count: u32) -> &mut Self {
         self.count = count;
         self
     }
+
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
 }
@@ -0,0 +1,231 @@
+//! How the `_key` of a new document is made.
+//!
+//! Each model picks a `KeyStrategy` with a `key` attribute on `ModelTrait`,
+//! `Uuid` when none is given:
+//!
+//! ```ignore
+//! #[derive(ModelTrait)]
+//! #[key(slug = "name")]
+//! pub struct Genre { .. }
+//! ```
+//!
+//! Keys are made by `new()`, or on insert for a document without one. When a key
+//! made this way is already taken by another document the insert is run again
+//! with the next key of its strategy, see `ArangoDb::insert_keyed`. Keys given
+//! by hand are kept, failing the insert when taken.
+
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::models::name::search_key;
+use crate::models::DocDetails;
+
+/// Times an insert is run before a key collision is given up on
+pub const MAX_KEY_ATTEMPTS: u32 = 5;
+
+/// Longest slug a key is made from, leaving room for a suffix
+const MAX_SLUG_LENGTH: usize = 64;
+
+/// Start of the time held by `Snowflake` keys, 2020-01-01T00:00:00Z in ms
+const SNOWFLAKE_EPOCH: u64 = 1_577_836_800_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyStrategy {
+    /// Hyphenated random UUID
+    Uuid,
+    /// ULID, sorting in the order the keys were made
+    Ulid,
+    /// 19 digit time ordered number of the time, a node and a sequence
+    Snowflake,
+    /// Lowercase words of `Keyed::slug_source` joined by `-` i.e. `detroit-techno`,
+    /// numbered from `-2` when taken. A `Ulid` when the source has no letters or digits.
+    Slug,
+    /// Left blank for the database to assign on insert
+    Server,
+}
+
+impl KeyStrategy {
+    /// Key for the `attempt`th insert of a document, counted from 0.
+    /// `None` for `Server`, and for `Slug` without a source.
+    pub fn generate(&self, source: Option<&str>, attempt: u32) -> Option<String> {
+        match self {
+            KeyStrategy::Uuid => Some(Uuid::new_v4().to_string()),
+            KeyStrategy::Ulid => Some(Ulid::new().to_string()),
+            KeyStrategy::Snowflake => Some(format!("{:019}", snowflake())),
+            KeyStrategy::Slug => {
+                let source = source.filter(|s| !s.trim().is_empty())?;
+                let slug = slug(source);
+                if slug.is_empty() {
+                    KeyStrategy::Ulid.generate(None, attempt)
+                } else if attempt == 0 {
+                    Some(slug)
+                } else {
+                    Some(format!("{}-{}", slug, attempt + 1))
+                }
+            }
+            KeyStrategy::Server => None,
+        }
+    }
+
+    /// Whether `key` is one the strategy makes from `source`, rather than one given by hand
+    pub fn made(&self, key: &str, source: Option<&str>) -> bool {
+        match self {
+            KeyStrategy::Uuid => key.len() == 36 && Uuid::parse_str(key).is_ok(),
+            KeyStrategy::Ulid => key.len() == 26 && Ulid::from_string(key).is_ok(),
+            KeyStrategy::Snowflake => key.len() == 19 && key.bytes().all(|b| b.is_ascii_digit()),
+            KeyStrategy::Slug => {
+                let slug = source.map(slug).unwrap_or_default();
+                if slug.is_empty() {
+                    return KeyStrategy::Ulid.made(key, None);
+                }
+                key == slug
+                    || key
+                        .strip_prefix(&slug)
+                        .and_then(|n| n.strip_prefix('-'))
+                        .is_some_and(|n| n.parse::<u32>().is_ok())
+            }
+            KeyStrategy::Server => false,
+        }
+    }
+}
+
+/// Models whose `_key` is made by a `KeyStrategy`, implemented by `ModelTrait`
+pub trait Keyed: DocDetails + Sized {
+    const KEY_STRATEGY: KeyStrategy;
+
+    /// Text `Slug` keys are made from
+    fn slug_source(&self) -> Option<&str> {
+        None
+    }
+
+    /// Sets `_key`, and `_id` to match
+    fn set_key(&mut self, key: String);
+
+    /// Key for the `attempt`th insert, see `KeyStrategy::generate`
+    fn generate_key(&self, attempt: u32) -> Option<String> {
+        Self::KEY_STRATEGY.generate(self.slug_source(), attempt)
+    }
+
+    /// Whether the key was made by the strategy, only those are replaced when taken
+    fn key_made(&self) -> bool {
+        Self::KEY_STRATEGY.made(self.key().as_str(), self.slug_source())
+    }
+
+    /// Gives the document a new key, unless its strategy can't make one yet
+    fn new_key(&mut self) {
+        if let Some(key) = self.generate_key(0) {
+            self.set_key(key);
+        }
+    }
+}
+
+/// Words of the search key of `source`, only keeping ascii letters and digits
+fn slug(source: &str) -> String {
+    let mut slug = String::new();
+    for word in search_key(source).split(' ') {
+        let word: String = word.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        if word.is_empty() || slug.len() + word.len() >= MAX_SLUG_LENGTH {
+            continue;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word);
+    }
+    slug
+}
+
+/// Time, node and sequence of the last snowflake made
+struct Snowflake {
+    node: u64,
+    time: u64,
+    sequence: u64,
+}
+
+static SNOWFLAKE: Mutex<Option<Snowflake>> = Mutex::new(None);
+
+/// 41 bits of ms since `SNOWFLAKE_EPOCH`, 10 bits of a node picked at random
+/// for the process and 12 bits counting keys made within the same ms
+fn snowflake() -> u64 {
+    let mut last = SNOWFLAKE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = last.get_or_insert_with(|| {
+        let random = Uuid::new_v4();
+        let bytes = random.as_bytes();
+        Snowflake {
+            node: u64::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+            time: 0,
+            sequence: 0,
+        }
+    });
+
+    let mut now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+        .saturating_sub(SNOWFLAKE_EPOCH)
+        .max(state.time);
+    if now == state.time {
+        state.sequence = (state.sequence + 1) & 0xfff;
+        // Sequence ran out within the ms, borrow the next one
+        if state.sequence == 0 {
+            now += 1;
+        }
+    } else {
+        state.sequence = 0;
+    }
+    state.time = now;
+
+    (now & 0x1ff_ffff_ffff) << 22 | (state.node & 0x3ff) << 12 | state.sequence
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::album::Album;
+    use crate::models::genre::Genre;
+    use crate::models::key::*;
+
+    #[test]
+    fn test_generate() {
+        let uuid = KeyStrategy::Uuid.generate(None, 0).unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_ne!(Some(uuid), KeyStrategy::Uuid.generate(None, 0));
+        assert_eq!(KeyStrategy::Ulid.generate(None, 0).unwrap().len(), 26);
+        assert_eq!(KeyStrategy::Server.generate(Some("name"), 0), None);
+
+        let first = KeyStrategy::Snowflake.generate(None, 0).unwrap();
+        let second = KeyStrategy::Snowflake.generate(None, 0).unwrap();
+        assert_eq!(first.len(), 19);
+        assert!(second > first);
+    }
+
+    #[test]
+    fn test_slug() {
+        let slug = |s, attempt| KeyStrategy::Slug.generate(Some(s), attempt);
+        assert_eq!(slug("Detroit Techno", 0).unwrap(), "detroit-techno");
+        assert_eq!(slug("The Drum & Bass", 1).unwrap(), "drum-and-bass-2");
+        assert_eq!(slug("Électronique", 0).unwrap(), "electronique");
+        assert_eq!(slug("!!!", 0).unwrap().len(), 26);
+        assert_eq!(KeyStrategy::Slug.generate(Some(" "), 0), None);
+    }
+
+    #[test]
+    fn test_model_keys() {
+        let album = Album::new();
+        assert_eq!(album.key().len(), 26);
+        assert_eq!(album.id(), format!("album/{}", album.key()).as_str());
+
+        // Slugs are made on insert once there is a name
+        let mut genre = Genre::new();
+        assert!(genre.key().is_blank());
+        genre.name("Detroit Techno");
+        assert_eq!(genre.generate_key(0).unwrap(), "detroit-techno");
+        genre.set_key("detroit-techno-2".to_string());
+        assert!(genre.key_made());
+        genre.set_key("techno".to_string());
+        assert_eq!(genre.id(), "genre/techno");
+        assert!(!genre.key_made());
+        assert!(album.key_made());
+    }
+}
@@ -0,0 +1,95 @@
+use std::borrow::Cow;
+
+use crate::macros::*;
+use crate::models::external_id::{self, ExternalId, ExternalIds};
+use crate::models::id::{DocId, DocKey};
+use crate::models::key::Keyed;
+
+#[include_database_fields(timestamp)]
+/// Record label or imprint releases are issued on
+#[derive(Debug, Clone, ModelTrait, WriteToArango, Validate, Default, Deserialize, Serialize)]
+#[key(ulid)]
+#[serde(remote = "Self")]
+pub struct Label {
+    /// ArangonDb _id
+    #[serde(rename(deserialize = "_id", serialize = "_id"))]
+    id: DocId<Label>,
+    /// ArangonDb _key
+    #[serde(rename(deserialize = "_key", serialize = "_key"))]
+    key: DocKey<Label>,
+    /// Ids of the label in the catalogues it was imported from,
+    /// stored as a single `foreign_key` by version 1
+    #[serde(default)]
+    external_ids: Vec<ExternalId>,
+    /// `foreign_key` of version 1 that isn't an external id, kept to be fixed by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    legacy_foreign_key: Option<Cow<'static, str>>,
+    /// Label name, stored lowercase
+    #[validate(non_empty, max_length = 500)]
+    name: Cow<'static, str>,
+    /// `_id` of the label this is a sub label or imprint of,
+    /// kept in step with the `sub_label` edge by `service::label`
+    #[serde(default)]
+    parent: Option<DocId<Label>>,
+    /// Description of the label
+    #[serde(default)]
+    profile: Cow<'static, str>,
+}
+
+crate::versioned!(Label, [external_id::upgrade_foreign_key]);
+
+impl Label {
+    pub fn new() -> Self {
+        let mut label = Label::default();
+        label.new_key();
+        label
+    }
+
+    pub fn name<T: AsRef<str>>(&mut self, name: T) -> &mut Self {
+        self.name = Cow::from(name.as_ref().trim().to_ascii_lowercase());
+        self
+    }
+
+    pub fn profile<T: Into<Cow<'static, str>>>(&mut self, profile: T) -> &mut Self {
+        self.profile = profile.into();
+        self
+    }
+
+    pub fn external_id(&mut self, id: ExternalId) -> &mut Self {
+        external_id::push(&mut self.external_ids, id);
+        self
+    }
+
+    /// Sets the parent of a new label, use `service::label::set_parent`
+    /// for stored labels so the hierarchy is updated too.
+    pub fn parent(&mut self, parent_id: DocId<Label>) -> &mut Self {
+        self.parent = Some(parent_id);
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    pub fn get_profile(&self) -> &str {
+        self.profile.as_ref()
+    }
+
+    pub fn get_external_ids(&self) -> &[ExternalId] {
+        &self.external_ids
+    }
+
+    pub fn get_legacy_foreign_key(&self) -> Option<&str> {
+        self.legacy_foreign_key.as_deref()
+    }
+
+    pub fn get_parent(&self) -> Option<&DocId<Label>> {
+        self.parent.as_ref()
+    }
+}
+
+impl ExternalIds for Label {
+    fn external_ids(&self) -> &[ExternalId] {
+        &self.external_ids
+    }
+}
@@ -1,13 +1,41 @@
 pub mod album;
 pub mod artist;
+pub mod barcode;
+pub mod external_id;
+pub mod format;
+pub mod genre;
+pub mod grade;
+pub mod id;
 pub mod inventory;
+pub mod key;
+pub mod label;
+pub mod name;
+pub mod price_change;
+pub mod stock_movement;
+pub mod track;
+pub mod validate;
 pub mod variant;
+pub mod version;
 
 #[cfg(feature = "arangodb")]
 pub mod edge;
 
+/// Version of the layout documents are stored in by this release, raised with the
+/// `Versioned::VERSION` of any model. Recorded in dumps so they are only restored
+/// by releases that can read them.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Models that can be written, checked with `Validate` before every write
+/// and given a key by their `Keyed::KEY_STRATEGY` when they have none
 pub trait ReqModelTraits:
-    serde::de::DeserializeOwned + serde::ser::Serialize + DocDetails + Sync + Send + Clone
+    serde::de::DeserializeOwned
+    + serde::ser::Serialize
+    + DocDetails
+    + key::Keyed
+    + validate::Validate
+    + Sync
+    + Send
+    + Clone
 {
 }
 
@@ -16,7 +44,16 @@ pub trait BoxedDoc: std::fmt::Debug {}
 pub trait DocDetails {
     fn collection_name<'a>() -> &'a str;
 
-    fn key(&self) -> String;
+    /// Whether documents of `collection` are of this model, checked by `DocId::parse`
+    fn owns_collection(collection: &str) -> bool {
+        collection == Self::collection_name()
+    }
+
+    fn key(&self) -> id::DocKey<Self>
+    where
+        Self: Sized;
 
-    fn id(&self) -> String;
+    fn id(&self) -> id::DocId<Self>
+    where
+        Self: Sized;
 }
@@ -0,0 +1,135 @@
+//! Display names stored together with the key they are searched by.
+//!
+//! A name is shown as it was given, "Björk" or "The Beatles", while lookups
+//! compare search keys so differences in case, diacritics, spacing and a leading
+//! article don't matter. "The Beatles", "Beatles, The" and "beatles" all have the
+//! search key "beatles", "Björk" and "BJORK" both have "bjork".
+
+use std::borrow::Cow;
+
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Articles dropped from the start of a name, or from the end after a comma
+const ARTICLES: [&str; 3] = ["the", "a", "an"];
+
+/// Whether `c` is a diacritic on a Latin, Greek or Cyrillic letter,
+/// marks that are part of other scripts i.e. kana voicing are kept
+fn is_diacritic(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}')
+}
+
+/// Key a name is searched by: compatibility decomposed with diacritics removed,
+/// case folded, `&` read as "and", punctuation dropped, whitespace collapsed to
+/// single spaces and a leading article, or trailing ", The", removed.
+pub fn search_key(name: &str) -> String {
+    let stripped: String = name.nfkd().filter(|c| !is_diacritic(*c)).nfc().collect();
+    let folded = caseless::default_case_fold_str(&stripped).replace('&', " and ");
+    let mut words: Vec<&str> = folded
+        .split(|c: char| !(c.is_alphanumeric() || is_combining_mark(c)))
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    if words.len() > 1 {
+        let last = words.len() - 1;
+        if ARTICLES.contains(&words[0]) {
+            words.remove(0);
+        } else if ARTICLES.contains(&words[last]) && folded.contains(',') {
+            words.pop();
+        }
+    }
+
+    words.join(" ")
+}
+
+/// A display name, written with its `search_key` alongside so the key is
+/// never out of step with the name. Flattened into the model it names.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Name(Cow<'static, str>);
+
+impl Name {
+    pub fn new<T: AsRef<str>>(name: T) -> Self {
+        Name(Cow::from(name.as_ref().trim().to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    pub fn search_key(&self) -> String {
+        search_key(&self.0)
+    }
+}
+
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("name", &self.0)?;
+        map.serialize_entry("search_key", &self.search_key())?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        /// Stored `search_key` is left out, it is derived from the name again
+        #[derive(Deserialize)]
+        struct Stored {
+            #[serde(default)]
+            name: Cow<'static, str>,
+        }
+
+        Ok(Name(Stored::deserialize(d)?.name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::name::*;
+
+    #[test]
+    fn test_search_key() {
+        assert_eq!(search_key("The Beatles"), "beatles");
+        assert_eq!(search_key("Beatles, The"), "beatles");
+        assert_eq!(search_key("  Simon &  GARFUNKEL "), "simon and garfunkel");
+        assert_eq!(search_key("The The"), "the");
+        assert_eq!(search_key("Björk"), "bjork");
+        assert_eq!(search_key("MOTÖRHEAD"), "motorhead");
+        assert_eq!(search_key("Die Ärzte"), "die arzte");
+        assert_eq!(search_key("Straßenjungs"), "strassenjungs");
+        assert_eq!(search_key("ＡＢＢＡ"), "abba");
+        assert_eq!(search_key("坂本龍一"), "坂本龍一");
+        assert_eq!(search_key("ドリカム"), "ドリカム");
+        assert_eq!(search_key("Sigur Rós"), search_key("sigur ros"));
+    }
+
+    #[test]
+    fn test_serialize() {
+        #[derive(Debug, Deserialize, Serialize)]
+        struct Named {
+            #[serde(flatten)]
+            name: Name,
+            rank: u32,
+        }
+
+        let named = Named {
+            name: Name::new(" Björk "),
+            rank: 1,
+        };
+        let value = serde_json::to_value(&named).unwrap();
+        assert_eq!(value["name"], "Björk");
+        assert_eq!(value["search_key"], "bjork");
+
+        let read: Named =
+            serde_json::from_value(serde_json::json!({"name": "Björk", "rank": 1})).unwrap();
+        assert_eq!(read.name, named.name);
+        assert_eq!(read.rank, 1);
+    }
+}
@@ -0,0 +1,59 @@
+use std::borrow::Cow;
+
+use crate::macros::*;
+use crate::models::id::{DocId, DocKey};
+use crate::models::key::Keyed;
+use crate::models::variant::{Prices, Variant};
+
+#[include_database_fields(timestamp)]
+/// Historic record of the prices a `Variant` was set to
+#[derive(Debug, Clone, ModelTrait, WriteToArango, Validate, Default, Deserialize, Serialize)]
+#[key(snowflake)]
+#[serde(remote = "Self")]
+pub struct PriceChange {
+    /// ArangonDb _id
+    #[serde(rename(deserialize = "_id", serialize = "_id"))]
+    id: DocId<PriceChange>,
+    /// ArangonDb _key
+    #[serde(rename(deserialize = "_key", serialize = "_key"))]
+    key: DocKey<PriceChange>,
+    /// `_id` of the variant, set when the change is applied
+    #[serde(default)]
+    #[validate(non_empty)]
+    variant: DocId<Variant>,
+    #[serde(flatten)]
+    prices: Prices,
+    /// Who made the change
+    #[serde(default)]
+    actor: Cow<'static, str>,
+}
+
+crate::versioned!(PriceChange);
+
+impl PriceChange {
+    pub fn new(prices: Prices) -> Self {
+        let mut price_change = PriceChange {
+            prices,
+            ..PriceChange::default()
+        };
+        price_change.new_key();
+        price_change
+    }
+
+    pub fn actor<T: Into<Cow<'static, str>>>(&mut self, actor: T) -> &mut Self {
+        self.actor = actor.into();
+        self
+    }
+
+    pub fn get_variant(&self) -> &DocId<Variant> {
+        &self.variant
+    }
+
+    pub fn get_prices(&self) -> Prices {
+        self.prices
+    }
+
+    pub fn get_actor(&self) -> &str {
+        self.actor.as_ref()
+    }
+}
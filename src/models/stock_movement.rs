@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+
+use crate::macros::*;
+use crate::models::id::{DocId, DocKey};
+use crate::models::inventory::Inventory;
+use crate::models::key::Keyed;
+
+#[include_database_fields(timestamp)]
+/// Ledger entry recording a single change to the count of an `Inventory`
+#[derive(Debug, Clone, ModelTrait, WriteToArango, Validate, Default, Deserialize, Serialize)]
+#[key(snowflake)]
+#[serde(remote = "Self")]
+pub struct StockMovement {
+    /// ArangonDb _id
+    #[serde(rename(deserialize = "_id", serialize = "_id"))]
+    id: DocId<StockMovement>,
+    /// ArangonDb _key
+    #[serde(rename(deserialize = "_key", serialize = "_key"))]
+    key: DocKey<StockMovement>,
+    /// `_id` of the inventory that changed, blank until the movement is applied
+    #[serde(default)]
+    inventory: DocId<Inventory>,
+    /// Change made to the count, set when the movement is applied
+    #[serde(default)]
+    delta: i64,
+    reason: Reason,
+    /// External reference i.e. an order or invoice number
+    #[serde(default)]
+    reference: Cow<'static, str>,
+    /// Who or what made the change
+    #[serde(default)]
+    actor: Cow<'static, str>,
+}
+
+crate::versioned!(StockMovement);
+
+/// Why the stock of an item changed
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Purchase,
+    Sale,
+    Return,
+    #[default]
+    Adjustment,
+    Damage,
+}
+
+impl StockMovement {
+    pub fn new(reason: Reason) -> Self {
+        let mut stock_movement = StockMovement {
+            reason,
+            ..StockMovement::default()
+        };
+        stock_movement.new_key();
+        stock_movement
+    }
+
+    pub fn reference<T: Into<Cow<'static, str>>>(&mut self, reference: T) -> &mut Self {
+        self.reference = reference.into();
+        self
+    }
+
+    pub fn actor<T: Into<Cow<'static, str>>>(&mut self, actor: T) -> &mut Self {
+        self.actor = actor.into();
+        self
+    }
+
+    pub fn get_inventory(&self) -> &DocId<Inventory> {
+        &self.inventory
+    }
+
+    pub fn get_delta(&self) -> i64 {
+        self.delta
+    }
+
+    pub fn get_reason(&self) -> Reason {
+        self.reason
+    }
+
+    pub fn get_reference(&self) -> &str {
+        self.reference.as_ref()
+    }
+
+    pub fn get_actor(&self) -> &str {
+        self.actor.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::stock_movement::*;
+    use crate::models::validate::Validate;
+
+    #[test]
+    fn test_validate() {
+        let mut movement = StockMovement::new(Reason::Sale);
+        movement.reference("order 12");
+        assert!(movement.get_inventory().is_blank());
+        assert!(movement.validate().is_ok());
+    }
+}
@@ -0,0 +1,230 @@
+//! Tracks of an album, stored in order as the `tracklist` of the `Album` document.
+
+use std::borrow::Cow;
+
+use crate::engine::DbError;
+use crate::models::validate::{Invalid, ValidationError};
+
+/// Kind of tracklist entry, as used by Discogs
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackKind {
+    #[default]
+    Track,
+    /// Title grouping the tracks that follow it i.e. a side or a suite, has no position
+    Heading,
+    /// Single track made of the `sub_tracks` i.e. movements, `A3a`, `A3b`
+    Index,
+}
+
+/// An entry of an album's tracklist
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Track {
+    #[serde(default)]
+    kind: TrackKind,
+    /// Position on the release i.e. `A1`, `B2`, `CD1-03`
+    #[serde(default)]
+    position: Cow<'static, str>,
+    title: Cow<'static, str>,
+    /// Running time in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration: Option<u32>,
+    /// `_id`s of the artists credited on this track when they differ from the album's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    artists: Vec<Cow<'static, str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sub_tracks: Vec<Track>,
+}
+
+impl Track {
+    pub fn new<P, T>(position: P, title: T) -> Self
+    where
+        P: Into<Cow<'static, str>>,
+        T: Into<Cow<'static, str>>,
+    {
+        Track {
+            position: position.into(),
+            title: title.into(),
+            ..Track::default()
+        }
+    }
+
+    pub fn heading<T: Into<Cow<'static, str>>>(title: T) -> Self {
+        Track {
+            kind: TrackKind::Heading,
+            title: title.into(),
+            ..Track::default()
+        }
+    }
+
+    /// Running time in seconds
+    pub fn duration(&mut self, seconds: u32) -> &mut Self {
+        self.duration = Some(seconds);
+        self
+    }
+
+    pub fn artist<T: Into<Cow<'static, str>>>(&mut self, artist_id: T) -> &mut Self {
+        self.artists.push(artist_id.into());
+        self
+    }
+
+    /// Adds a sub track, making this an index track
+    pub fn sub_track(&mut self, track: Track) -> &mut Self {
+        self.kind = TrackKind::Index;
+        self.sub_tracks.push(track);
+        self
+    }
+
+    pub fn get_kind(&self) -> TrackKind {
+        self.kind
+    }
+
+    pub fn get_position(&self) -> &str {
+        self.position.as_ref()
+    }
+
+    pub fn get_title(&self) -> &str {
+        self.title.as_ref()
+    }
+
+    pub fn get_duration(&self) -> Option<u32> {
+        self.duration
+    }
+
+    pub fn get_artists(&self) -> &[Cow<'static, str>] {
+        &self.artists
+    }
+
+    pub fn get_sub_tracks(&self) -> &[Track] {
+        &self.sub_tracks
+    }
+
+    /// Running time in seconds, an index track without its own duration
+    /// takes the total of its sub tracks. Headings have none.
+    pub fn running_time(&self) -> u32 {
+        match (self.kind, self.duration) {
+            (TrackKind::Heading, _) => 0,
+            (_, Some(seconds)) => seconds,
+            (_, None) => running_time(&self.sub_tracks),
+        }
+    }
+}
+
+/// Total running time of a tracklist in seconds
+pub fn running_time(tracks: &[Track]) -> u32 {
+    tracks.iter().map(Track::running_time).sum()
+}
+
+/// Reorders `tracks` to follow `positions`, entries without a position
+/// i.e. headings are kept in front of the track that followed them.
+/// Fails on `positions` if one is unknown or not every positioned track is listed once.
+pub fn reorder(tracks: Vec<Track>, positions: &[&str]) -> Result<Vec<Track>, ValidationError> {
+    // Group each positioned track with the headings before it
+    let mut groups: Vec<(Cow<'static, str>, Vec<Track>)> = Vec::new();
+    let mut pending = Vec::new();
+    for track in tracks {
+        if track.position.is_empty() {
+            pending.push(track);
+        } else {
+            let position = track.position.clone();
+            pending.push(track);
+            groups.push((position, std::mem::take(&mut pending)));
+        }
+    }
+
+    let mut errors = ValidationError::default();
+    if groups.len() != positions.len() {
+        errors.check(
+            "positions",
+            Err(Invalid::new(
+                "complete",
+                format!("must list each of the {} positions once", groups.len()),
+            )),
+        );
+        return Err(errors);
+    }
+
+    let mut ordered = Vec::new();
+    for position in positions {
+        match groups.iter().position(|(p, _)| p == position) {
+            Some(i) => ordered.extend(groups.swap_remove(i).1),
+            None => {
+                errors.check(
+                    "positions",
+                    Err(Invalid::new(
+                        "known",
+                        format!("{:?} is not a position of the tracklist", position),
+                    )),
+                );
+            }
+        }
+    }
+    errors.into_result()?;
+    // Trailing headings stay at the end
+    ordered.extend(pending);
+    Ok(ordered)
+}
+
+/// Parses a running time written as `m:ss` or `h:mm:ss` into seconds
+pub fn parse_duration(s: &str) -> Result<u32, DbError> {
+    s.trim()
+        .split(':')
+        .try_fold(0u32, |total, part| {
+            let part: u32 = part.parse().ok()?;
+            total.checked_mul(60)?.checked_add(part)
+        })
+        .ok_or(DbError::ParseFail)
+}
+
+/// Formats seconds as `m:ss`, or `h:mm:ss` from an hour up
+pub fn format_duration(seconds: u32) -> String {
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::track::*;
+
+    fn tracklist() -> Vec<Track> {
+        let mut suite = Track::new("A2", "Suite");
+        suite
+            .sub_track(Track::new("A2a", "Part One").duration(60).clone())
+            .sub_track(Track::new("A2b", "Part Two").duration(90).clone());
+        vec![
+            Track::heading("Side A"),
+            Track::new("A1", "Intro").duration(125).clone(),
+            suite,
+            Track::heading("Side B"),
+            Track::new("B1", "Outro").duration(200).clone(),
+        ]
+    }
+
+    #[test]
+    fn test_running_time() {
+        let tracks = tracklist();
+        assert_eq!(tracks[2].get_kind(), TrackKind::Index);
+        assert_eq!(running_time(&tracks), 125 + 150 + 200);
+        assert_eq!(format_duration(running_time(&tracks)), "7:55");
+        assert_eq!(format_duration(3723), "1:02:03");
+        assert_eq!(parse_duration("1:02:03").unwrap(), 3723);
+        assert_eq!(parse_duration("4:05").unwrap(), 245);
+        assert!(parse_duration("4:xx").is_err());
+    }
+
+    #[test]
+    fn test_reorder() {
+        let tracks = reorder(tracklist(), &["B1", "A1", "A2"]).unwrap();
+        let titles: Vec<&str> = tracks.iter().map(Track::get_title).collect();
+        assert_eq!(titles, vec!["Side B", "Outro", "Side A", "Intro", "Suite"]);
+
+        let err = reorder(tracklist(), &["B1", "A1"]).unwrap_err();
+        assert_eq!(err.field("positions").next().unwrap().rule, "complete");
+        let err = reorder(tracklist(), &["B1", "A1", "C1"]).unwrap_err();
+        assert_eq!(err.field("positions").next().unwrap().rule, "known");
+    }
+}
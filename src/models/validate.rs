@@ -0,0 +1,458 @@
+//! Checks a document has to pass before it is written.
+//!
+//! Models derive `Validate`, giving the rules of each field in a `validate`
+//! attribute, and every problem found is returned in one `ValidationError`.
+//! `EngineWrite::insert`/`update` validate a document before sending it.
+//!
+//! ```ignore
+//! #[derive(Validate)]
+//! pub struct Track {
+//!     #[validate(non_empty, max_length = 200)]
+//!     title: Cow<'static, str>,
+//!     #[validate(regex = "^[A-Z][0-9]+$")]
+//!     position: Cow<'static, str>,
+//!     #[validate(range(min = 1, max = 7200), custom = "whole_minutes")]
+//!     duration: u32,
+//! }
+//! ```
+//!
+//! Rules of the derive:
+//! * `non_empty` - text or list with something in it
+//! * `max_length = N` - at most `N` characters or items
+//! * `regex = "pattern"` - text matching `pattern`
+//! * `range(min = A, max = B)` - number within `A..=B`, either bound can be left out
+//! * `id` - the document's own `_id`, `collection/key` of its collection
+//! * `id = "collection"` - the `_id` of a document of `collection`
+//! * `key` - a valid `_key`
+//! * `custom = "path::to_fn"` - `fn(&Field) -> Result<(), String>`, named by the error
+//! * `read_only` - left out of `EngineWrite::update`, only changed by the service keeping
+//!   a record of why it changed i.e. the count of an inventory
+//!
+//! Rules other than `non_empty` pass a blank or missing value, so optional
+//! fields are only checked when set.
+
+use std::borrow::Cow;
+use std::fmt::Formatter;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::models::name::Name;
+
+/// Longest `_key` accepted by ArangoDb
+pub const MAX_KEY_LENGTH: usize = 254;
+
+/// Models checked before they are written, see the module docs for the derive
+pub trait Validate {
+    /// Checks every field, listing each problem found
+    fn validate(&self) -> Result<(), ValidationError>;
+
+    /// Stored names of the `read_only` fields
+    fn read_only() -> &'static [&'static str]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+}
+
+/// A field failing one of its rules
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// Name of the field as it is stored i.e. `_id`
+    pub field: Cow<'static, str>,
+    /// Rule the field broke i.e. `max_length`
+    pub rule: Cow<'static, str>,
+    pub message: String,
+}
+
+/// Every field of a document failing its rules
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationError {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationError {
+    /// Records the problem of `field` if `check` failed
+    pub fn check(&mut self, field: &'static str, check: Result<(), Invalid>) -> &mut Self {
+        if let Err(invalid) = check {
+            self.errors.push(FieldError {
+                field: field.into(),
+                rule: invalid.rule,
+                message: invalid.message,
+            });
+        }
+        self
+    }
+
+    /// Problems of `field`
+    pub fn field<'a>(&'a self, field: &'a str) -> impl Iterator<Item = &'a FieldError> + 'a {
+        self.errors.iter().filter(move |e| e.field == field)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// `Err` holding the problems recorded, if there are any
+    pub fn into_result(self) -> Result<(), ValidationError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Validation failed")?;
+        for (i, e) in self.errors.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { ", " };
+            write!(f, "{}{} {}", sep, e.field, e.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// A value failing a rule, given the field it belongs to by `ValidationError::check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invalid {
+    pub rule: Cow<'static, str>,
+    pub message: String,
+}
+
+impl Invalid {
+    pub fn new<R: Into<Cow<'static, str>>, M: Into<String>>(rule: R, message: M) -> Self {
+        Invalid {
+            rule: rule.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Values holding text, `None` when there is none to check
+pub trait Text {
+    fn text(&self) -> Option<&str>;
+}
+
+impl Text for str {
+    fn text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl Text for String {
+    fn text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl Text for Cow<'_, str> {
+    fn text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl Text for Name {
+    fn text(&self) -> Option<&str> {
+        Some(self.as_str())
+    }
+}
+
+impl<T: Text> Text for Option<T> {
+    fn text(&self) -> Option<&str> {
+        self.as_ref().and_then(Text::text)
+    }
+}
+
+/// Values with a length, characters for text and items for lists
+pub trait Length {
+    fn length(&self) -> usize;
+}
+
+impl<T: Text + ?Sized> Length for T {
+    fn length(&self) -> usize {
+        self.text().map_or(0, |t| t.chars().count())
+    }
+}
+
+impl<T> Length for [T] {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> Length for Vec<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Numbers checked by `range`, `None` when not set
+pub trait Number {
+    fn number(&self) -> Option<f64>;
+}
+
+macro_rules! number {
+    ($($t:ty),*) => {
+        $(impl Number for $t {
+            fn number(&self) -> Option<f64> {
+                Some(*self as f64)
+            }
+        })*
+    };
+}
+
+number!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+
+impl<T: Number> Number for Option<T> {
+    fn number(&self) -> Option<f64> {
+        self.as_ref().and_then(Number::number)
+    }
+}
+
+/// A `regex` rule's pattern, compiled the first time it is used
+pub struct Pattern {
+    pattern: &'static str,
+    regex: OnceLock<Regex>,
+}
+
+impl Pattern {
+    pub const fn new(pattern: &'static str) -> Self {
+        Pattern {
+            pattern,
+            regex: OnceLock::new(),
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex
+            .get_or_init(|| Regex::new(self.pattern).expect("pattern checked by the derive"))
+            .is_match(text)
+    }
+}
+
+/// Implementations of the derive's rules
+pub mod rules {
+    use crate::models::validate::*;
+
+    pub fn non_empty<V: Length + ?Sized>(value: &V) -> Result<(), Invalid> {
+        if value.length() == 0 {
+            return Err(Invalid::new("non_empty", "must not be empty"));
+        }
+        Ok(())
+    }
+
+    pub fn max_length<V: Length + ?Sized>(value: &V, max: usize) -> Result<(), Invalid> {
+        if value.length() > max {
+            return Err(Invalid::new(
+                "max_length",
+                format!("must be at most {} long", max),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn regex<V: Text + ?Sized>(value: &V, pattern: &Pattern) -> Result<(), Invalid> {
+        match value.text() {
+            Some(t) if !t.is_empty() && !pattern.is_match(t) => Err(Invalid::new(
+                "regex",
+                format!("must match `{}`", pattern.pattern),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn range<V: Number + ?Sized>(
+        value: &V,
+        min: Option<f64>,
+        max: Option<f64>,
+    ) -> Result<(), Invalid> {
+        let n = match value.number() {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+        let message = match (min, max) {
+            (Some(min), Some(max)) if n < min || n > max => {
+                format!("must be between {} and {}", min, max)
+            }
+            (Some(min), _) if n < min => format!("must be at least {}", min),
+            (_, Some(max)) if n > max => format!("must be at most {}", max),
+            _ => return Ok(()),
+        };
+        Err(Invalid::new("range", message))
+    }
+
+    /// `_key` of 1 to 254 of the characters ArangoDb allows
+    pub fn key<V: Text + ?Sized>(value: &V) -> Result<(), Invalid> {
+        match value.text() {
+            Some(k) if !k.is_empty() && !is_key(k) => Err(Invalid::new(
+                "key",
+                format!(
+                    "must be at most {} letters, digits or any of _-:.@()+,=;$!*'%",
+                    MAX_KEY_LENGTH
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// `_id` of a document in `collection`
+    pub fn id<V: Text + ?Sized>(value: &V, collection: &str) -> Result<(), Invalid> {
+        let id = match value.text() {
+            Some(id) if !id.is_empty() => id,
+            _ => return Ok(()),
+        };
+        match id.split_once('/') {
+            Some((c, k)) if c == collection && is_key(k) => Ok(()),
+            _ => Err(Invalid::new(
+                "id",
+                format!("must be the id of a document in {}", collection),
+            )),
+        }
+    }
+
+    /// `_id` of a document in any collection
+    pub fn any_id<V: Text + ?Sized>(value: &V) -> Result<(), Invalid> {
+        let id = match value.text() {
+            Some(id) if !id.is_empty() => id,
+            _ => return Ok(()),
+        };
+        match id.split_once('/') {
+            Some((c, k)) if is_collection(c) && is_key(k) => Ok(()),
+            _ => Err(Invalid::new("id", "must be a document id i.e. album/123")),
+        }
+    }
+
+    /// Adapts the message of a `custom` rule, named `rule`
+    pub fn custom(rule: &'static str, check: Result<(), String>) -> Result<(), Invalid> {
+        check.map_err(|message| Invalid::new(rule, message))
+    }
+
+    fn is_key(k: &str) -> bool {
+        !k.is_empty()
+            && k.len() <= MAX_KEY_LENGTH
+            && k.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-:.@()+,=;$!*'%".contains(c))
+    }
+
+    fn is_collection(c: &str) -> bool {
+        let mut chars = c.chars();
+        matches!(chars.next(), Some(f) if f.is_ascii_alphabetic() || f == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::macros::Validate;
+    use crate::models::id::{DocId, DocKey};
+    use crate::models::validate::rules::*;
+    use crate::models::validate::*;
+
+    #[derive(Serialize, Validate)]
+    struct Release {
+        #[serde(rename(serialize = "_id"))]
+        #[validate(id)]
+        id: Cow<'static, str>,
+        #[validate(non_empty, max_length = 5)]
+        title: String,
+        #[validate(regex = "^[A-Z]+-[0-9]+$")]
+        cat_no: Option<String>,
+        #[validate(range(min = 1900, max = 2100))]
+        year: u32,
+        #[validate(max_length = 2, custom = "no_blank_tracks")]
+        tracks: Vec<String>,
+        #[validate(id = "label")]
+        label: Cow<'static, str>,
+        #[validate(read_only)]
+        pressed: u32,
+    }
+
+    impl crate::models::DocDetails for Release {
+        fn collection_name<'a>() -> &'a str {
+            "release"
+        }
+
+        fn key(&self) -> DocKey<Self> {
+            self.id().key()
+        }
+
+        fn id(&self) -> DocId<Self> {
+            DocId::parse(self.id.clone()).unwrap_or_default()
+        }
+    }
+
+    fn no_blank_tracks(tracks: &[String]) -> Result<(), String> {
+        if tracks.iter().any(|t| t.trim().is_empty()) {
+            return Err("must not hold a blank track".to_string());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules() {
+        assert!(non_empty("").is_err());
+        assert!(non_empty(&Some("a".to_string())).is_ok());
+        assert!(max_length("äöü", 3).is_ok());
+        assert!(range(&-1i64, Some(0.0), None).is_err());
+        assert!(range(&None::<u32>, Some(1.0), Some(2.0)).is_ok());
+        assert!(key("a:b(1)").is_ok());
+        assert!(key("a/b").is_err());
+        assert!(id("album/123", "album").is_ok());
+        assert!(id("artist/123", "album").is_err());
+        assert!(id("album/", "album").is_err());
+        assert!(any_id("inventory/1").is_ok());
+        assert!(any_id("1").is_err());
+    }
+
+    #[test]
+    fn test_derive() {
+        let mut release = Release {
+            id: "release/1".into(),
+            title: "Fabric".into(),
+            cat_no: None,
+            year: 2001,
+            tracks: vec!["A1".into(), " ".into(), "B1".into()],
+            label: "artist/2".into(),
+            pressed: 500,
+        };
+        let err = release.validate().unwrap_err();
+        let fields: Vec<(&str, &str)> = err
+            .errors
+            .iter()
+            .map(|e| (e.field.as_ref(), e.rule.as_ref()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("title", "max_length"),
+                ("tracks", "max_length"),
+                ("tracks", "no_blank_tracks"),
+                ("label", "id"),
+            ]
+        );
+
+        release.id = "label/1".into();
+        release.title = "".into();
+        release.cat_no = Some("fab 1".into());
+        release.year = 1800;
+        let err = release.validate().unwrap_err();
+        assert_eq!(err.field("_id").count(), 1);
+        assert_eq!(err.field("title").next().unwrap().rule, "non_empty");
+        assert_eq!(err.field("cat_no").count(), 1);
+        assert_eq!(err.field("year").count(), 1);
+
+        release.id = "release/1".into();
+        release.title = "Fab".into();
+        release.cat_no = Some("FABRIC-1".into());
+        release.year = 2001;
+        release.tracks = vec!["A1".into()];
+        release.label = "label/2".into();
+        assert!(release.validate().is_ok());
+        assert_eq!(Release::read_only(), ["pressed"]);
+    }
+}
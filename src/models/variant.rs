@@ -1,61 +1,96 @@
 use std::borrow::Cow;
 
 use model_write_derive::*;
-use uuid::Uuid;
 
 use crate::models::album::Album;
+use crate::models::format::{self, Format};
+use crate::models::grade::{self, Grade, SleeveGrade};
+use crate::models::id::{DocId, DocKey};
 use crate::models::inventory::Inventory;
-
-#[derive(Debug, Clone, ModelTrait, WriteToArango, Serialize, Deserialize)]
-struct Variant {
+use crate::models::key::Keyed;
+use crate::money::Money;
+
+/// Edge from an `Album` to the `Inventory` holding its stock,
+/// describing the physical release the stock is of.
+#[derive(Debug, Clone, ModelTrait, WriteToArango, Validate, Serialize, Deserialize)]
+#[key(uuid)]
+#[serde(remote = "Self")]
+pub struct Variant {
     /// ArangonDb _id
     #[serde(rename(deserialize = "_id", serialize = "_id"))]
-    id: Cow<'static, str>,
+    id: DocId<Variant>,
     /// ArangonDb _key
     #[serde(rename(deserialize = "_key", serialize = "_key"))]
-    key: Cow<'static, str>,
-    pub _from: Cow<'static, str>,
-    pub _to: Cow<'static, str>,
+    key: DocKey<Variant>,
+    #[validate(non_empty)]
+    pub _from: DocId<Album>,
+    #[validate(non_empty)]
+    pub _to: DocId<Inventory>,
     #[serde(default)]
     details: Cow<'static, str>,
-    medium: Medium,
-    quality: Quality,
-    edition: Edition,
+    #[serde(flatten)]
+    format: Format,
+    /// Grade of the record itself, stored as `quality` by version 1
+    media_grade: Grade,
+    #[serde(default)]
+    sleeve_grade: SleeveGrade,
+    #[serde(flatten, default)]
+    prices: Prices,
+}
+
+crate::versioned!(Variant, [grade::upgrade_quality, format::upgrade_edition]);
+
+/// Prices of a variant per copy, each is unset until known
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Prices {
+    /// What was paid for a copy
+    #[serde(default)]
+    pub purchase_price: Option<Money>,
+    /// Price a copy is normally sold at
+    #[serde(default)]
+    pub list_price: Option<Money>,
+    /// Reduced price, takes precedence over `list_price` while set
+    #[serde(default)]
+    pub sale_price: Option<Money>,
+}
+
+impl Prices {
+    /// Price a copy currently sells at
+    pub fn selling_price(&self) -> Option<Money> {
+        self.sale_price.or(self.list_price)
+    }
 }
 
 impl Default for Variant {
     fn default() -> Self {
-        let uid = Uuid::new_v4().to_string()[0..8].to_string();
-
         Variant {
             id: Default::default(),
-            key: Cow::from(uid),
+            key: Default::default(),
             _from: Default::default(),
             _to: Default::default(),
             details: Default::default(),
-            medium: Medium::Vinyl,
-            quality: Quality::F,
-            edition: Edition::Standard,
+            format: Format::default(),
+            media_grade: Grade::Fair,
+            sleeve_grade: SleeveGrade::NotGraded,
+            prices: Prices::default(),
         }
     }
 }
 
 impl Variant {
     pub fn new() -> Self {
-        let uid = Uuid::new_v4().to_string()[0..8].to_string();
-        Variant {
-            key: Cow::from(uid),
-            ..Variant::default()
-        }
+        let mut variant = Variant::default();
+        variant.new_key();
+        variant
     }
 
-    pub fn vertex<T: Into<Cow<'static, str>>>(&mut self, vtx: T) -> &mut Self {
-        self._from = vtx.into();
+    pub fn vertex(&mut self, vtx: DocId<Album>) -> &mut Self {
+        self._from = vtx;
         self
     }
 
-    pub fn dest<T: Into<Cow<'static, str>>>(&mut self, dest: T) -> &mut Self {
-        self._to = dest.into();
+    pub fn dest(&mut self, dest: DocId<Inventory>) -> &mut Self {
+        self._to = dest;
         self
     }
 
@@ -64,60 +99,57 @@ impl Variant {
         self
     }
 
-    pub fn medium(&mut self, medium: Medium) -> &mut Self {
-        self.medium = medium;
+    pub fn format(&mut self, format: Format) -> &mut Self {
+        self.format = format;
         self
     }
 
-    pub fn quality(&mut self, quality: Quality) -> &mut Self {
-        self.quality = quality;
+    pub fn media_grade(&mut self, grade: Grade) -> &mut Self {
+        self.media_grade = grade;
         self
     }
 
-    pub fn edition(&mut self, edition: Edition) -> &mut Self {
-        self.edition = edition;
+    pub fn sleeve_grade<T: Into<SleeveGrade>>(&mut self, grade: T) -> &mut Self {
+        self.sleeve_grade = grade.into();
         self
     }
 
-    /// Use this to connect the edge to its vertex —`vtx`— and generate a new
-    /// `Inventory` document to be the destination with the variant.
-    pub fn create_inventory_variant(
-        &mut self,
-        vtx: &Album,
-        count: u8,
-        _var: Variant,
-    ) -> Result<Inventory, EngineError> {
-        let mut inventory = Inventory::new();
-        inventory.amount(count);
-        self.vertex(vtx.id()).dest(inventory.id());
-        Ok(inventory)
+    /// Sets the prices of a new variant, use `service::pricing::set_prices`
+    /// for stored variants so the change is kept in the price history.
+    pub fn prices(&mut self, prices: Prices) -> &mut Self {
+        self.prices = prices;
+        self
     }
-}
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
-enum Medium {
-    Vinyl,
-    CD,
-    Cassette,
-}
+    /// `_id` of the album this is a variant of
+    pub fn get_vertex(&self) -> &DocId<Album> {
+        &self._from
+    }
 
-/// Quality rating is based off of Discogs
-/// https://support.discogs.com/hc/en-us/articles/360001566193-How-To-Grade-Items
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
-enum Quality {
-    F,
-    G,
-    GP,
-    VG,
-    Vgp,
-    NM,
-    M,
-}
+    /// `_id` of the inventory holding the stock of this variant
+    pub fn get_dest(&self) -> &DocId<Inventory> {
+        &self._to
+    }
+
+    pub fn get_details(&self) -> &str {
+        self.details.as_ref()
+    }
+
+    pub fn get_format(&self) -> &Format {
+        &self.format
+    }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
-enum Edition {
-    Standard,
-    Limited,
+    pub fn get_media_grade(&self) -> Grade {
+        self.media_grade
+    }
+
+    pub fn get_sleeve_grade(&self) -> SleeveGrade {
+        self.sleeve_grade
+    }
+
+    pub fn get_prices(&self) -> Prices {
+        self.prices
+    }
 }
 
 // #[async_trait]
@@ -146,6 +178,8 @@ mod test {
     // use crate::io::write::Write;
 
     use crate::io::EngineWrite;
+    use crate::models::format::Medium;
+    use crate::models::grade::Grade;
     use crate::models::variant::Variant;
 
     type TestResult = Result<(), EngineError>;
@@ -154,10 +188,30 @@ mod test {
     async fn test_associate_variant() -> TestResult {
         let db = common().await?;
         let mut v = Variant::default();
-        v._from = Cow::from("album/7782da0a");
-        v._to = Cow::from("inventory/1158719");
+        v._from = "album/7782da0a".parse()?;
+        v._to = "inventory/1158719".parse()?;
         v.details = Cow::from("Test Variant");
         dbg!(db.insert(v).await);
         Ok(())
     }
+
+    #[test]
+    fn test_read_legacy_variant() {
+        let v: Variant = serde_json::from_value(serde_json::json!({
+            "_id": "variant/1", "_key": "1",
+            "_from": "album/1", "_to": "inventory/1",
+            "medium": "Cassette", "quality": "Vgp", "edition": "Limited",
+            "list_price": {"amount": 999, "currency": "GBP"}
+        }))
+        .unwrap();
+        assert_eq!(v.get_format().get_medium(), Medium::Cassette);
+        assert!(v.get_format().is_limited());
+        assert_eq!(v.get_media_grade(), Grade::VeryGoodPlus);
+        assert_eq!(v.get_prices().list_price.map(|p| p.amount()), Some(999));
+
+        let stored = serde_json::to_value(&v).unwrap();
+        assert_eq!(stored["schema_version"], 3);
+        assert_eq!(stored["media_grade"], "VG+");
+        assert!(stored.get("quality").is_none() && stored.get("edition").is_none());
+    }
 }
@@ -0,0 +1,161 @@
+//! Schema versions of stored documents and the upgrades between them.
+//!
+//! Every model is written with a `schema_version`, documents written before
+//! versioning count as version 1. A model registers an upgrade for each change
+//! to its layout, the first turning a version 1 document into version 2 and so on.
+//! Documents are upgraded as they are read, `service::migrate::upgrade_all`
+//! rewrites stored documents so the upgrades don't have to run on every read.
+
+use serde_json::{Map, Value};
+
+use crate::engine::DbError;
+
+/// Attribute the version of a stored document is kept in
+pub const SCHEMA_VERSION_FIELD: &str = "schema_version";
+
+/// Turns a document of one version into the next, in place
+pub type Upgrade = fn(&mut Map<String, Value>) -> Result<(), DbError>;
+
+/// Models whose stored documents are upgraded as they are read, implemented with `versioned!`
+pub trait Versioned {
+    /// Upgrades in order, the first from version 1 to 2
+    const UPGRADES: &'static [Upgrade];
+
+    /// Version documents are written in, one more than the number of upgrades
+    const VERSION: u32 = Self::UPGRADES.len() as u32 + 1;
+}
+
+/// Version `doc` was written in, 1 when written before versioning
+pub fn version_of(doc: &Value) -> u32 {
+    doc.get(SCHEMA_VERSION_FIELD)
+        .and_then(Value::as_u64)
+        .map_or(1, |v| v.max(1) as u32)
+}
+
+/// Runs the upgrades `doc` is missing, returns whether there were any.
+/// Documents of a later version than `T` are left as they are.
+pub fn upgrade<T: Versioned>(doc: &mut Value) -> Result<bool, DbError> {
+    let from = version_of(doc) as usize;
+    let fields = match doc {
+        Value::Object(fields) if from < T::VERSION as usize => fields,
+        _ => return Ok(false),
+    };
+    for upgrade in T::UPGRADES.iter().skip(from - 1) {
+        upgrade(fields)?;
+    }
+    fields.insert(SCHEMA_VERSION_FIELD.to_string(), Value::from(T::VERSION));
+
+    Ok(true)
+}
+
+/// Implements `Versioned`, with the upgrades given in order, and serde's traits
+/// for a model deriving them with `#[serde(remote = "Self")]`.
+/// Documents are written with the current `schema_version` and upgraded before
+/// they are read.
+#[macro_export]
+macro_rules! versioned {
+    ($model:ty) => {
+        $crate::versioned!($model, []);
+    };
+    ($model:ty, [$($upgrade:expr),* $(,)?]) => {
+        impl $crate::models::version::Versioned for $model {
+            const UPGRADES: &'static [$crate::models::version::Upgrade] = &[$($upgrade),*];
+        }
+
+        impl ::serde::Serialize for $model {
+            fn serialize<S: ::serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                use ::serde::ser::Error;
+                use $crate::models::version::{Versioned, SCHEMA_VERSION_FIELD};
+
+                let mut doc = <$model>::serialize(self, ::serde_json::value::Serializer)
+                    .map_err(S::Error::custom)?;
+                if let ::serde_json::Value::Object(fields) = &mut doc {
+                    fields.insert(
+                        SCHEMA_VERSION_FIELD.to_string(),
+                        <$model as Versioned>::VERSION.into(),
+                    );
+                }
+                doc.serialize(s)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $model {
+            fn deserialize<D: ::serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                use ::serde::de::Error;
+
+                let mut doc = ::serde_json::Value::deserialize(d)?;
+                $crate::models::version::upgrade::<$model>(&mut doc).map_err(D::Error::custom)?;
+                <$model>::deserialize(doc).map_err(D::Error::custom)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::models::version::*;
+
+    /// Release whose `title` was called `name` in version 1,
+    /// and which gained `year` in version 3
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    #[serde(remote = "Self")]
+    struct Release {
+        title: String,
+        year: u32,
+    }
+
+    fn rename_name(doc: &mut Map<String, Value>) -> Result<(), DbError> {
+        let name = doc.remove("name").ok_or(DbError::ParseFail)?;
+        doc.insert("title".into(), name);
+        Ok(())
+    }
+
+    fn add_year(doc: &mut Map<String, Value>) -> Result<(), DbError> {
+        doc.entry("year").or_insert_with(|| Value::from(0));
+        Ok(())
+    }
+
+    crate::versioned!(Release, [rename_name, add_year]);
+
+    #[test]
+    fn test_upgrade_on_read() {
+        assert_eq!(Release::VERSION, 3);
+        let expected = Release {
+            title: "Sublime".into(),
+            year: 0,
+        };
+
+        let v1: Release = serde_json::from_value(json!({"name": "Sublime"})).unwrap();
+        assert_eq!(v1, expected);
+        let v2: Release =
+            serde_json::from_value(json!({"title": "Sublime", "schema_version": 2})).unwrap();
+        assert_eq!(v2, expected);
+        // Upgrades already applied are not run again
+        let v3: Release =
+            serde_json::from_value(json!({"title": "Sublime", "year": 1996, "schema_version": 3}))
+                .unwrap();
+        assert_eq!(v3.year, 1996);
+
+        assert!(serde_json::from_value::<Release>(json!({"title": "Sublime"})).is_err());
+    }
+
+    #[test]
+    fn test_write_version() {
+        let doc = serde_json::to_value(Release {
+            title: "Sublime".into(),
+            year: 1996,
+        })
+        .unwrap();
+        assert_eq!(
+            doc,
+            json!({"title": "Sublime", "year": 1996, "schema_version": 3})
+        );
+
+        let mut stored = json!({"name": "Sublime"});
+        assert!(upgrade::<Release>(&mut stored).unwrap());
+        assert_eq!(version_of(&stored), 3);
+        assert!(!upgrade::<Release>(&mut stored).unwrap());
+    }
+}
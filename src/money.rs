@@ -0,0 +1,245 @@
+//! Exact monetary amounts stored as integer minor units i.e. pence or cents.
+
+use std::convert::TryFrom;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use crate::engine::error::Cause;
+use crate::engine::EngineError;
+
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MoneyError {
+    /// Currency code is not three ASCII letters
+    InvalidCurrency,
+    /// Arithmetic between two different currencies
+    CurrencyMismatch,
+    Overflow,
+    ParseFail,
+}
+
+impl std::fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            MoneyError::InvalidCurrency => write!(f, "Currency must be an ISO 4217 code."),
+            MoneyError::CurrencyMismatch => {
+                write!(f, "Can not combine amounts of different currencies.")
+            }
+            MoneyError::Overflow => write!(f, "Amount is too large."),
+            MoneyError::ParseFail => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+impl From<MoneyError> for EngineError {
+    fn from(e: MoneyError) -> Self {
+        EngineError::Invalid(Cause::new(e))
+    }
+}
+
+/// ISO 4217 currency code i.e. `GBP`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const GBP: Currency = Currency(*b"GBP");
+    pub const EUR: Currency = Currency(*b"EUR");
+    pub const USD: Currency = Currency(*b"USD");
+    pub const JPY: Currency = Currency(*b"JPY");
+
+    pub fn new(code: &str) -> Result<Self, MoneyError> {
+        let code = code.trim().to_ascii_uppercase();
+        let bytes = code.as_bytes();
+        if bytes.len() != 3 || !bytes.iter().all(u8::is_ascii_alphabetic) {
+            return Err(MoneyError::InvalidCurrency);
+        }
+        Ok(Currency([bytes[0], bytes[1], bytes[2]]))
+    }
+
+    pub fn code(&self) -> &str {
+        // Only ever built from ASCII letters
+        std::str::from_utf8(&self.0).unwrap_or("XXX")
+    }
+
+    /// Number of minor unit digits i.e. 2 for pence, 0 for yen
+    pub fn exponent(&self) -> u32 {
+        match &self.0 {
+            b"BIF" | b"CLP" | b"DJF" | b"GNF" | b"ISK" | b"JPY" | b"KMF" | b"KRW" | b"PYG"
+            | b"RWF" | b"UGX" | b"VND" | b"VUV" | b"XAF" | b"XOF" | b"XPF" => 0,
+            b"BHD" | b"IQD" | b"JOD" | b"KWD" | b"LYD" | b"OMR" | b"TND" => 3,
+            _ => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = MoneyError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Currency::new(&s)
+    }
+}
+
+impl From<Currency> for String {
+    fn from(c: Currency) -> Self {
+        c.code().to_string()
+    }
+}
+
+/// An amount of money in the minor unit of its currency.
+///
+/// Serialized as `{"amount": 1299, "currency": "GBP"}` so every engine
+/// stores the same integer value, no floating point is involved.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Money {
+    amount: i64,
+    currency: Currency,
+}
+
+impl Money {
+    /// Creates an amount from minor units i.e. `Money::new(1299, Currency::GBP)` is £12.99
+    pub fn new(amount: i64, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Self::new(0, currency)
+    }
+
+    /// Amount in minor units
+    pub fn amount(&self) -> i64 {
+        self.amount
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.amount == 0
+    }
+
+    pub fn checked_add(&self, other: Money) -> Result<Money, MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch);
+        }
+        self.amount
+            .checked_add(other.amount)
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_sub(&self, other: Money) -> Result<Money, MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch);
+        }
+        self.amount
+            .checked_sub(other.amount)
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Multiplies by a quantity i.e. the value of several copies
+    pub fn checked_mul(&self, quantity: i64) -> Result<Money, MoneyError> {
+        self.amount
+            .checked_mul(quantity)
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let exp = self.currency.exponent();
+        let sign = if self.amount < 0 { "-" } else { "" };
+        let abs = self.amount.unsigned_abs();
+        if exp == 0 {
+            return write!(f, "{}{} {}", sign, abs, self.currency);
+        }
+        let unit = 10u64.pow(exp);
+        write!(
+            f,
+            "{}{}.{:0width$} {}",
+            sign,
+            abs / unit,
+            abs % unit,
+            self.currency,
+            width = exp as usize
+        )
+    }
+}
+
+/// Parses the `Display` format i.e. `12.99 GBP`
+impl FromStr for Money {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (amount, code) = s.trim().rsplit_once(' ').ok_or(MoneyError::ParseFail)?;
+        let currency = Currency::new(code)?;
+        let exp = currency.exponent() as usize;
+
+        let amount = amount.trim();
+        let (negative, amount) = match amount.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, amount),
+        };
+        let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if !digits(whole) || fraction.len() > exp || !(fraction.is_empty() || digits(fraction)) {
+            return Err(MoneyError::ParseFail);
+        }
+
+        let padded = format!("{}{:0<width$}", whole, fraction, width = exp);
+        let minor: i64 = padded.parse().map_err(|_| MoneyError::Overflow)?;
+        Ok(Money::new(if negative { -minor } else { minor }, currency))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::money::*;
+
+    #[test]
+    fn test_parse_display() {
+        let m: Money = "12.5 GBP".parse().unwrap();
+        assert_eq!(m, Money::new(1250, Currency::GBP));
+        assert_eq!(m.to_string(), "12.50 GBP");
+        assert_eq!("-0.05 usd".parse::<Money>().unwrap().amount(), -5);
+        assert_eq!("1500 JPY".parse::<Money>().unwrap().to_string(), "1500 JPY");
+        assert!("1.999 GBP".parse::<Money>().is_err());
+        assert!("12.99".parse::<Money>().is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Money::new(1999, Currency::GBP);
+        assert_eq!(a.checked_mul(3).unwrap().amount(), 5997);
+        assert_eq!(a.checked_sub(a).unwrap(), Money::zero(Currency::GBP));
+        assert_eq!(
+            a.checked_add(Money::new(1, Currency::EUR)),
+            Err(MoneyError::CurrencyMismatch)
+        );
+        assert_eq!(
+            Money::new(i64::MAX, Currency::GBP).checked_add(Money::new(1, Currency::GBP)),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let m = Money::new(1299, Currency::GBP);
+        let v = serde_json::to_value(m).unwrap();
+        assert_eq!(v, serde_json::json!({"amount": 1299, "currency": "GBP"}));
+        assert_eq!(serde_json::from_value::<Money>(v).unwrap(), m);
+        assert!(serde_json::from_value::<Currency>(serde_json::json!("POUND")).is_err());
+    }
+}
@@ -10,6 +10,9 @@ pub use crate::engine::db::{arangodb::preludes::*, AuthType, Db, DbBasics, DbBui
 pub use crate::engine::session::Session;
 pub use crate::engine::{DbError, EngineError};
 pub use crate::io::delete;
+pub use crate::io::dump;
 pub use crate::io::read;
 pub use crate::io::write;
-pub use crate::models::{album::Album, artist::Artist};
+pub use crate::models::{
+    album::Album, artist::Artist, inventory::Inventory, label::Label, variant::Variant,
+};
@@ -0,0 +1,509 @@
+//! Full dumps of the database that can be restored into any engine.
+//!
+//! A dump is a gzip compressed JSON Lines stream, written and read a batch of
+//! documents at a time so no collection has to fit in memory:
+//!
+//! ```text
+//! {"format":1,"schema_version":3,"created":..,"collections":[]}   header
+//! {"collection":"album","kind":"document"}                        start of a collection
+//! {"_key":"1",..}                                                 one document per line
+//! {"count":1000,"crc32":..}                                       end of a batch
+//! ..
+//! {"format":1,..,"collections":[{"name":"album",..}]}             manifest
+//! ```
+//!
+//! Each batch ends with the number of its documents and the CRC-32 of their
+//! lines, a batch that doesn't match is refused before any of it is written.
+//! The `Manifest` comes last, recording the same for every collection, so a
+//! dump missing a batch or cut short is refused when it is read.
+//!
+//! Documents keep their `_key` and edges their `_from` and `_to`, so links
+//! between restored documents stay intact. Restoring over existing data
+//! replaces the documents with the same `_key` and leaves the rest alone.
+
+use std::fmt::Formatter;
+use std::io::{BufRead, BufReader, Read, Write};
+
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::engine::error::Cause;
+use crate::engine::EngineError;
+use crate::import::ARTIST_TO;
+use crate::io::dump::{CollectionKind, EngineDump};
+use crate::models::album::Album;
+use crate::models::artist::Artist;
+use crate::models::genre::Genre;
+use crate::models::inventory::Inventory;
+use crate::models::label::Label;
+use crate::models::price_change::PriceChange;
+use crate::models::stock_movement::StockMovement;
+use crate::models::variant::Variant;
+use crate::models::{DocDetails, SCHEMA_VERSION};
+use crate::service::dedup::REDIRECT;
+use crate::service::genre::{SUB_GENRE, TAGGED};
+use crate::service::label::{RELEASED_ON, SUB_LABEL};
+
+/// Version of the dump layout, independent of the layout of the documents in it
+pub const DUMP_FORMAT: u32 = 1;
+
+/// Number of documents handed to the engine at once when restoring
+const BATCH: usize = 1000;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DumpError {
+    /// The dump is empty or ends before its manifest
+    MissingManifest,
+    /// A line of the dump is out of place i.e. a document outside of any collection
+    Misplaced(String),
+    /// Written in a dump layout this release can't read
+    UnsupportedFormat(u32),
+    /// Holds documents in a newer layout than this release can read
+    NewerSchema(u32),
+    /// The dump ended before every document of the collection was read
+    Truncated(String),
+    /// The documents of the collection don't match the checksum of their batch or the manifest
+    ChecksumMismatch(String),
+}
+
+impl std::fmt::Display for DumpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpError::MissingManifest => write!(f, "Dump has no manifest"),
+            DumpError::Misplaced(line) => write!(f, "Line out of place in dump: {}", line),
+            DumpError::UnsupportedFormat(v) => write!(f, "Unsupported dump format {}", v),
+            DumpError::NewerSchema(v) => write!(
+                f,
+                "Dump holds schema version {}, newer than {}",
+                v, SCHEMA_VERSION
+            ),
+            DumpError::Truncated(c) => write!(f, "Dump ends within collection {}", c),
+            DumpError::ChecksumMismatch(c) => {
+                write!(f, "Checksum of collection {} does not match", c)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+/// A dump that can't be read is invalid input, nothing is restored from it
+impl From<DumpError> for EngineError {
+    fn from(e: DumpError) -> Self {
+        EngineError::Invalid(Cause::new(e))
+    }
+}
+
+/// Contents of a dump, written as its last line. The first line is a header
+/// holding the same without any collections.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub schema_version: u32,
+    /// Milliseconds since the epoch when the dump was taken
+    pub created: i64,
+    pub collections: Vec<CollectionEntry>,
+}
+
+impl Manifest {
+    /// Number of documents in the dump
+    pub fn total(&self) -> u64 {
+        self.collections.iter().map(|c| c.count).sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CollectionEntry {
+    pub name: String,
+    pub kind: CollectionKind,
+    pub count: u64,
+    /// CRC-32 of the collection's lines, newlines included
+    pub crc32: u32,
+}
+
+/// Line of a dump that isn't a document, documents are told apart by their `_key`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Marker {
+    /// Start of the documents of a collection
+    Collection {
+        collection: String,
+        kind: CollectionKind,
+    },
+    /// End of a batch of documents of the current collection
+    Batch {
+        count: u64,
+        crc32: u32,
+    },
+    Manifest(Manifest),
+}
+
+/// Writes `value` as a line of a dump
+fn write_line<W: Write, T: Serialize>(out: &mut W, value: &T) -> Result<(), EngineError> {
+    serde_json::to_writer(&mut *out, value)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Every collection included in a dump, documents before the edges linking them
+pub fn collections() -> Vec<(&'static str, CollectionKind)> {
+    use CollectionKind::{Document, Edge};
+
+    vec![
+        (Album::collection_name(), Document),
+        (Artist::collection_name(), Document),
+        (Label::collection_name(), Document),
+        (Genre::collection_name(), Document),
+        (Inventory::collection_name(), Document),
+        (StockMovement::collection_name(), Document),
+        (PriceChange::collection_name(), Document),
+        (REDIRECT, Document),
+        (ARTIST_TO, Edge),
+        (Variant::collection_name(), Edge),
+        (SUB_LABEL, Edge),
+        (RELEASED_ON, Edge),
+        (SUB_GENRE, Edge),
+        (TAGGED, Edge),
+    ]
+}
+
+/// Writes every collection of `engine` to `writer` a batch at a time,
+/// returns the manifest written.
+pub async fn dump<D, W>(engine: &D, writer: W) -> Result<Manifest, EngineError>
+where
+    D: EngineDump + Sync,
+    D::E: Into<EngineError>,
+    W: Write,
+{
+    let mut manifest = Manifest {
+        format: DUMP_FORMAT,
+        schema_version: SCHEMA_VERSION,
+        created: Utc::now().timestamp_millis(),
+        collections: Vec::new(),
+    };
+    let mut out = GzEncoder::new(writer, Compression::default());
+    write_line(&mut out, &manifest)?;
+
+    for (name, kind) in collections() {
+        write_line(
+            &mut out,
+            &Marker::Collection {
+                collection: name.to_string(),
+                kind,
+            },
+        )?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut count = 0;
+        let mut after = String::new();
+        loop {
+            let docs = engine
+                .export(name, &after, BATCH)
+                .await
+                .map_err(Into::into)?;
+            let mut buf = Vec::new();
+            for doc in docs.iter() {
+                serde_json::to_writer(&mut buf, doc)?;
+                buf.push(b'\n');
+            }
+            if !docs.is_empty() {
+                hasher.update(&buf);
+                out.write_all(&buf)?;
+                let crc32 = crc32fast::hash(&buf);
+                write_line(
+                    &mut out,
+                    &Marker::Batch {
+                        count: docs.len() as u64,
+                        crc32,
+                    },
+                )?;
+                count += docs.len() as u64;
+            }
+
+            // A batch short of `BATCH` is the last
+            let last = docs.last().and_then(|doc| doc["_key"].as_str());
+            match last {
+                Some(key) if docs.len() == BATCH && !key.is_empty() => after = key.to_string(),
+                _ => break,
+            }
+        }
+        manifest.collections.push(CollectionEntry {
+            name: name.to_string(),
+            kind,
+            count,
+            crc32: hasher.finalize(),
+        });
+    }
+
+    write_line(&mut out, &manifest)?;
+    out.finish()?;
+
+    Ok(manifest)
+}
+
+/// Restores a dump read from `reader` into `engine`, returns its manifest.
+/// Batches are restored one at a time in the order of the dump, those
+/// before a damaged batch stay restored.
+pub async fn restore<D, R>(engine: &D, reader: R) -> Result<Manifest, EngineError>
+where
+    D: EngineDump + Sync,
+    D::E: Into<EngineError>,
+    R: Read,
+{
+    let mut lines = BufReader::new(GzDecoder::new(reader)).lines();
+    let header: Manifest = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => return Err(DumpError::MissingManifest.into()),
+    };
+    if header.format != DUMP_FORMAT {
+        return Err(DumpError::UnsupportedFormat(header.format).into());
+    }
+    if header.schema_version > SCHEMA_VERSION {
+        return Err(DumpError::NewerSchema(header.schema_version).into());
+    }
+
+    // Collections read so far, and the lines of the current one and of its current batch
+    let mut restored: Vec<CollectionEntry> = Vec::new();
+    let mut collection_crc = crc32fast::Hasher::new();
+    let mut batch_crc = crc32fast::Hasher::new();
+    let mut docs: Vec<Value> = Vec::new();
+    for line in lines {
+        let line = line?;
+        let value: Value = serde_json::from_str(&line)?;
+        if value.get("_key").is_some() {
+            if restored.is_empty() {
+                return Err(DumpError::Misplaced(line).into());
+            }
+            for hasher in [&mut collection_crc, &mut batch_crc] {
+                hasher.update(line.as_bytes());
+                hasher.update(b"\n");
+            }
+            docs.push(value);
+            continue;
+        }
+
+        let marker: Marker = match serde_json::from_value(value) {
+            Ok(marker) => marker,
+            Err(_) => return Err(DumpError::Misplaced(line).into()),
+        };
+        let entry = restored.last_mut();
+        match (marker, entry) {
+            (Marker::Batch { count, crc32 }, Some(entry)) => {
+                let hasher = std::mem::replace(&mut batch_crc, crc32fast::Hasher::new());
+                if count != docs.len() as u64 || crc32 != hasher.finalize() {
+                    return Err(DumpError::ChecksumMismatch(entry.name.clone()).into());
+                }
+                engine
+                    .import(&entry.name, entry.kind, std::mem::take(&mut docs))
+                    .await
+                    .map_err(Into::into)?;
+                entry.count += count;
+                entry.crc32 = collection_crc.clone().finalize();
+            }
+            // Documents not followed by the end of their batch
+            (_, Some(entry)) if !docs.is_empty() => {
+                return Err(DumpError::Truncated(entry.name.clone()).into())
+            }
+            (Marker::Collection { collection, kind }, _) => {
+                collection_crc = crc32fast::Hasher::new();
+                restored.push(CollectionEntry {
+                    name: collection,
+                    kind,
+                    count: 0,
+                    crc32: collection_crc.clone().finalize(),
+                });
+            }
+            (Marker::Manifest(manifest), _) => {
+                // A batch or collection missing from the dump
+                let expected = &manifest.collections;
+                let damaged = (0..expected.len().max(restored.len()))
+                    .find(|&i| expected.get(i) != restored.get(i))
+                    .and_then(|i| expected.get(i).or_else(|| restored.get(i)));
+                return match damaged {
+                    Some(entry) => Err(DumpError::ChecksumMismatch(entry.name.clone()).into()),
+                    None => Ok(manifest),
+                };
+            }
+            (Marker::Batch { .. }, None) => return Err(DumpError::Misplaced(line).into()),
+        }
+    }
+
+    match restored.last() {
+        Some(entry) if !docs.is_empty() => Err(DumpError::Truncated(entry.name.clone()).into()),
+        _ => Err(DumpError::MissingManifest.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use std::sync::Mutex;
+
+    use serde_json::json;
+
+    use crate::service::backup::*;
+
+    /// Engine keeping collections in memory, keyed by `_key`
+    #[derive(Debug, Default)]
+    struct Memory(Mutex<BTreeMap<String, BTreeMap<String, Value>>>);
+
+    #[crate::async_trait]
+    impl EngineDump for Memory {
+        type E = EngineError;
+
+        async fn export(
+            &self,
+            collection: &str,
+            after: &str,
+            limit: usize,
+        ) -> Result<Vec<Value>, Self::E> {
+            let collections = self.0.lock().unwrap();
+            Ok(collections
+                .get(collection)
+                .map(|docs| {
+                    docs.range::<str, _>((Bound::Excluded(after), Bound::Unbounded))
+                        .take(limit)
+                        .map(|(_, doc)| doc.clone())
+                        .collect()
+                })
+                .unwrap_or_default())
+        }
+
+        async fn import(
+            &self,
+            collection: &str,
+            _kind: CollectionKind,
+            docs: Vec<Value>,
+        ) -> Result<u64, Self::E> {
+            let mut collections = self.0.lock().unwrap();
+            let stored = collections.entry(collection.to_string()).or_default();
+            let count = docs.len() as u64;
+            for doc in docs {
+                stored.insert(doc["_key"].as_str().unwrap_or_default().to_string(), doc);
+            }
+            Ok(count)
+        }
+    }
+
+    async fn seeded() -> Memory {
+        let engine = Memory::default();
+        let albums = (0..1500)
+            .map(|i| json!({"_key": format!("{:04}", i), "name": format!("Album {}", i)}))
+            .collect();
+        engine
+            .import("album", CollectionKind::Document, albums)
+            .await
+            .unwrap();
+        let credits =
+            vec![json!({"_key": "1", "_from": "artist/1", "_to": "album/0001", "role": "remixer"})];
+        engine
+            .import(ARTIST_TO, CollectionKind::Edge, credits)
+            .await
+            .unwrap();
+        engine
+    }
+
+    /// Decompressed lines of a dump
+    fn unpack(dump: &[u8]) -> Vec<String> {
+        BufReader::new(GzDecoder::new(dump))
+            .lines()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn pack(lines: &[String]) -> Vec<u8> {
+        let mut out = GzEncoder::new(Vec::new(), Compression::default());
+        for line in lines {
+            writeln!(out, "{}", line).unwrap();
+        }
+        out.finish().unwrap()
+    }
+
+    /// Every document of a collection of `engine`
+    async fn all(engine: &Memory, collection: &str) -> Vec<Value> {
+        engine.export(collection, "", usize::MAX).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() -> Result<(), EngineError> {
+        let source = seeded().await;
+        let mut buf = Vec::new();
+        let manifest = dump(&source, &mut buf).await?;
+        assert_eq!(manifest.total(), 1501);
+        assert_eq!(manifest.collections.len(), collections().len());
+        // Albums are written in two batches
+        let lines = unpack(&buf);
+        assert_eq!(lines.iter().filter(|l| l.contains("crc32")).count(), 3 + 1);
+
+        let target = Memory::default();
+        let restored = restore(&target, buf.as_slice()).await?;
+        assert_eq!(restored, manifest);
+        assert_eq!(all(&target, "album").await, all(&source, "album").await);
+        assert_eq!(all(&target, ARTIST_TO).await, all(&source, ARTIST_TO).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_damaged() -> Result<(), EngineError> {
+        let mut buf = Vec::new();
+        dump(&seeded().await, &mut buf).await?;
+        let lines = unpack(&buf);
+
+        // The batch holding the change is refused before any of it is written
+        let mut changed = lines.clone();
+        changed[10] = changed[10].replace("Album", "Albun");
+        let target = Memory::default();
+        let err = restore(&target, pack(&changed).as_slice())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DumpError>(),
+            Some(DumpError::ChecksumMismatch(c)) if c == "album"
+        ));
+        assert!(all(&target, "album").await.is_empty());
+
+        let err = restore(&Memory::default(), pack(&lines[..100]).as_slice())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DumpError>(),
+            Some(DumpError::Truncated(c)) if c == "album"
+        ));
+
+        // Cut short between collections, or missing a whole batch
+        let err = restore(
+            &Memory::default(),
+            pack(&lines[..lines.len() - 1]).as_slice(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DumpError>(),
+            Some(DumpError::MissingManifest)
+        ));
+        let second_batch = lines.iter().position(|l| l.contains("crc32")).unwrap() + 1;
+        let mut missing = lines.clone();
+        missing.drain(second_batch..second_batch + 501);
+        let err = restore(&Memory::default(), pack(&missing).as_slice())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DumpError>(),
+            Some(DumpError::ChecksumMismatch(c)) if c == "album"
+        ));
+
+        let mut manifest: Manifest = serde_json::from_str(&lines[0])?;
+        manifest.schema_version = SCHEMA_VERSION + 1;
+        let mut newer = lines.clone();
+        newer[0] = serde_json::to_string(&manifest)?;
+        let target = Memory::default();
+        assert!(restore(&target, pack(&newer).as_slice()).await.is_err());
+        assert!(all(&target, "album").await.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//! Finding and merging artists stored more than once, i.e. imported from
+//! catalogues that spell the name differently.
+//!
+//! Artists are candidate duplicates when their names or aliases have the same search
+//! key, or they share an external id. A merged artist leaves a redirect in
+//! the `redirect` collection so `resolve` still finds it by its old `_id`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use arangors::AqlQuery;
+
+use crate::engine::db::arangodb::aql_snippet::{
+    ADD_REDIRECT, MERGE_ARTIST, MERGE_PAIR_CHECK, REMOVE_DOCUMENT, RESOLVE, RETARGET_REDIRECTS,
+};
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::import::ARTIST_TO;
+use crate::io::EngineGet;
+use crate::models::artist::Artist;
+use crate::models::edge::Direction;
+use crate::models::external_id::{ExternalId, ExternalIds};
+use crate::models::name::search_key;
+use crate::models::{DocDetails, ReqModelTraits};
+use crate::service::genre::TAGGED;
+use crate::service::tree;
+
+/// Collection of redirects from the `_id` of a merged document to the one it was merged into
+pub const REDIRECT: &str = "redirect";
+
+/// Edge collections linking artists, moved to the kept artist on a merge
+const ARTIST_LINKS: [(&str, Direction); 2] = [
+    (ARTIST_TO, Direction::Outbound),
+    (TAGGED, Direction::Outbound),
+];
+
+/// What a set of duplicates have in common
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Match {
+    /// Search key of a name or alias
+    Name(String),
+    ExternalId(ExternalId),
+}
+
+/// Artists that are likely the same artist
+#[derive(Debug, Clone)]
+pub struct Duplicates {
+    pub artists: Vec<Artist>,
+    /// Every name and id shared by at least two of the artists
+    pub matches: Vec<Match>,
+}
+
+/// Everything an artist can be matched on
+fn matches_of(artist: &Artist) -> BTreeSet<Match> {
+    let names = std::iter::once(artist.get_name())
+        .chain(artist.get_aliases().iter().map(|a| a.as_ref()))
+        .map(search_key)
+        .filter(|n| !n.is_empty())
+        .map(Match::Name);
+    let ids = artist.external_ids().iter().cloned().map(Match::ExternalId);
+
+    names.chain(ids).collect()
+}
+
+/// Groups artists matching each other, directly or through another artist of the group.
+/// Artists without a duplicate are left out.
+pub fn group_duplicates(artists: &[Artist]) -> Vec<Duplicates> {
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    let mut shared: BTreeMap<Match, Vec<usize>> = BTreeMap::new();
+    for (i, artist) in artists.iter().enumerate() {
+        for m in matches_of(artist) {
+            shared.entry(m).or_default().push(i);
+        }
+    }
+
+    let mut parent: Vec<usize> = (0..artists.len()).collect();
+    for members in shared.values() {
+        for &other in members.iter().skip(1) {
+            let (a, b) = (root(&mut parent, members[0]), root(&mut parent, other));
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Duplicates> = BTreeMap::new();
+    for (m, members) in shared.into_iter().filter(|(_, members)| members.len() > 1) {
+        let group = groups
+            .entry(root(&mut parent, members[0]))
+            .or_insert(Duplicates {
+                artists: Vec::new(),
+                matches: Vec::new(),
+            });
+        group.matches.push(m);
+    }
+    for (i, artist) in artists.iter().enumerate() {
+        if let Some(group) = groups.get_mut(&root(&mut parent, i)) {
+            group.artists.push(artist.clone());
+        }
+    }
+
+    groups.into_values().collect()
+}
+
+/// Every set of stored artists that are likely duplicates.
+/// All artists are read to compare them, so this is meant to be run now and then
+/// rather than on every write.
+pub async fn find_duplicates(engine: &ArangoDb) -> Result<Vec<Duplicates>, EngineError> {
+    let artists = engine.get_all::<Artist>().await?;
+    Ok(group_duplicates(&artists))
+}
+
+/// Merges the artist `remove` into `keep` in one transaction. Credits and tags of
+/// `remove` are moved to `keep`, dropping any `keep` already has, its name, aliases
+/// and external ids are added to those of `keep` and it is replaced by a redirect.
+/// Returns the kept artist.
+pub async fn merge_artists(
+    engine: &ArangoDb,
+    keep: &str,
+    remove: &str,
+) -> Result<Artist, EngineError> {
+    let pair = |query: &'static str, collection: Option<(&'static str, &'static str)>| {
+        let builder = AqlQuery::builder()
+            .query(query)
+            .bind_var("keep", keep)
+            .bind_var("remove", remove);
+        match collection {
+            Some((name, collection)) => builder.bind_var(name, collection).build(),
+            None => builder.build(),
+        }
+    };
+
+    let mut queries = vec![
+        pair(MERGE_PAIR_CHECK, None),
+        pair(
+            MERGE_ARTIST,
+            Some(("@collection", Artist::collection_name())),
+        ),
+    ];
+    for (collection, direction) in ARTIST_LINKS.iter() {
+        queries.extend(tree::move_links(collection, *direction, keep, remove));
+    }
+    queries.push(pair(RETARGET_REDIRECTS, Some(("@redirect", REDIRECT))));
+    queries.push(pair(ADD_REDIRECT, Some(("@redirect", REDIRECT))));
+    queries.push(
+        AqlQuery::builder()
+            .query(REMOVE_DOCUMENT)
+            .bind_var("@collection", Artist::collection_name())
+            .bind_var("id", remove)
+            .build(),
+    );
+
+    let mut write: Vec<String> = ARTIST_LINKS.iter().map(|(c, _)| c.to_string()).collect();
+    write.push(REDIRECT.to_string());
+    write.push(Artist::collection_name().to_string());
+
+    tree::in_transaction(engine, write, queries, keep).await
+}
+
+/// Gets a document by `_id`, following the redirect left when it was merged into another.
+pub async fn resolve<T: ReqModelTraits>(engine: &ArangoDb, id: &str) -> Result<T, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(RESOLVE)
+        .bind_var("@redirect", REDIRECT)
+        .bind_var("id", id)
+        .build();
+
+    let resp: Vec<Option<T>> = engine.db().aql_query(aql).await?;
+    match resp.into_iter().flatten().next() {
+        Some(doc) => Ok(doc),
+        None => DbError::ItemNotFound.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::db::test::common;
+    use crate::io::EngineWrite;
+    use crate::models::album::Album;
+    use crate::models::edge::{Edge, EdgeFilter};
+    use crate::service::dedup::*;
+
+    type TestResult = Result<(), EngineError>;
+
+    fn artist(name: &str) -> Artist {
+        let mut artist = Artist::new();
+        artist.name(name);
+        artist
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let mut aliased = artist("Fab Four");
+        aliased.alias("Beatles, The");
+        let mut imported = artist("Fab 4");
+        imported.external_id(ExternalId::discogs(82730));
+        let mut listed = artist("The Fab Four");
+        listed.external_id(ExternalId::discogs(82730));
+        let artists = vec![
+            artist("The Beatles"),
+            artist("Björk"),
+            aliased,
+            imported,
+            listed,
+            artist("björk"),
+            artist("Bjork"),
+        ];
+
+        let groups = group_duplicates(&artists);
+        assert_eq!(groups.len(), 2);
+        // Linked through the alias and name of the third artist and the id of the fourth
+        assert_eq!(groups[0].artists.len(), 4);
+        assert_eq!(
+            groups[0].matches,
+            vec![
+                Match::Name("beatles".into()),
+                Match::Name("fab four".into()),
+                Match::ExternalId(ExternalId::discogs(82730)),
+            ]
+        );
+        assert_eq!(groups[1].artists.len(), 3);
+        assert_eq!(groups[1].matches, vec![Match::Name("bjork".into())]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_artists() -> TestResult {
+        let db = common().await?;
+
+        let mut keep = artist("The Beatles");
+        keep.external_id(ExternalId::discogs(82730));
+        let mut remove = artist("Beatles, The");
+        remove.alias("Fab Four").profile("Liverpool band");
+        db.insert(keep.clone()).await?;
+        db.insert(remove.clone()).await?;
+
+        let mut album = Album::new();
+        album.name("merged artist album");
+        db.insert(album.clone()).await?;
+        Edge::upsert(&db, Edge::new(ARTIST_TO, remove.id(), album.id())).await?;
+
+        assert!(merge_artists(&db, &keep.id(), &keep.id()).await.is_err());
+        let merged = merge_artists(&db, &keep.id(), &remove.id()).await?;
+        assert!(merged.get_aliases().iter().any(|a| a == "Fab Four"));
+        assert!(merged.get_aliases().iter().any(|a| a == "Beatles, The"));
+        assert_eq!(merged.get_profile(), "Liverpool band");
+
+        let credits = Edge::find(&db, ARTIST_TO, EdgeFilter::new().attr("_to", album.id())).await?;
+        assert_eq!(credits.len(), 1);
+        assert_eq!(keep.id(), *credits[0].from_vertex());
+
+        let resolved: Artist = resolve(&db, &remove.id()).await?;
+        assert_eq!(resolved.id(), keep.id());
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+//! Approximate name lookups for "did you mean" prompts and matching imported records.
+//!
+//! Names are compared by their search key, see `models::name::search_key`, so case,
+//! diacritics and punctuation don't count. The database compares the stored key of a
+//! name; aliases have none stored and are lowercased with punctuation read as a space.
+//! Two names score the higher of their trigram similarity, the share of three letter
+//! sequences they have in common, and their edit similarity, one less the Levenshtein
+//! distance over the length of the longer name. Both range from 0 to 1 for identical
+//! names. Trigrams match words in another order, edits match typos in short names.
+
+use std::collections::HashSet;
+
+use arangors::AqlQuery;
+
+use crate::engine::db::arangodb::aql_snippet::FUZZY_FIND;
+use crate::engine::db::ArangoDb;
+use crate::engine::EngineError;
+use crate::models::album::Album;
+use crate::models::artist::Artist;
+use crate::models::genre::Genre;
+use crate::models::label::Label;
+use crate::models::name::search_key;
+use crate::models::ReqModelTraits;
+
+/// A document close enough to the name looked up
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Candidate<T> {
+    pub doc: T,
+    pub score: f64,
+    /// Name or alias of the document that scored highest
+    pub matched: String,
+}
+
+/// Models that can be looked up by name
+pub trait Names {
+    /// Every name the document is known by, its name first
+    fn names(&self) -> Vec<&str>;
+}
+
+impl Names for Artist {
+    fn names(&self) -> Vec<&str> {
+        std::iter::once(self.get_name())
+            .chain(self.get_aliases().iter().map(|a| a.as_ref()))
+            .collect()
+    }
+}
+
+impl Names for Album {
+    fn names(&self) -> Vec<&str> {
+        vec![self.get_name()]
+    }
+}
+
+impl Names for Label {
+    fn names(&self) -> Vec<&str> {
+        vec![self.get_name()]
+    }
+}
+
+impl Names for Genre {
+    fn names(&self) -> Vec<&str> {
+        vec![self.get_name()]
+    }
+}
+
+/// How close a name has to be and how many candidates are returned
+#[derive(Debug, Copy, Clone)]
+pub struct FuzzyOptions {
+    threshold: f64,
+    limit: u32,
+}
+
+impl Default for FuzzyOptions {
+    fn default() -> Self {
+        FuzzyOptions {
+            threshold: 0.5,
+            limit: 10,
+        }
+    }
+}
+
+impl FuzzyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lowest score returned, 0.5 by default
+    pub fn threshold(&mut self, threshold: f64) -> &mut Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Most candidates returned, 10 by default
+    pub fn limit(&mut self, limit: u32) -> &mut Self {
+        self.limit = limit;
+        self
+    }
+}
+
+/// Name as it is compared, its search key
+pub fn fold(name: &str) -> String {
+    search_key(name)
+}
+
+/// Number of single character insertions, deletions and substitutions turning `a` into `b`
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Three character sequences of a name, padded so its start and end count more
+fn trigrams(name: &str) -> HashSet<[char; 3]> {
+    let padded: Vec<char> = format!("  {} ", name).chars().collect();
+    padded.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+/// Similarity of two names from 0 to 1, see the module documentation
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (fold(a), fold(b));
+    let (ga, gb) = (trigrams(&a), trigrams(&b));
+    let trigram = ga.intersection(&gb).count() as f64 / ga.union(&gb).count() as f64;
+    let longest = a.chars().count().max(b.chars().count()).max(1);
+    let edit = 1.0 - levenshtein(&a, &b) as f64 / longest as f64;
+
+    trigram.max(edit)
+}
+
+/// Scores documents already in memory against `query`, i.e. records of an import,
+/// returning those over the threshold best first.
+pub fn rank<T: Names + Clone>(
+    query: &str,
+    docs: &[T],
+    options: &FuzzyOptions,
+) -> Vec<Candidate<T>> {
+    let mut candidates: Vec<Candidate<T>> = docs
+        .iter()
+        .filter_map(|doc| {
+            doc.names()
+                .into_iter()
+                .map(|name| (similarity(query, name), name))
+                .fold(
+                    None,
+                    |best: Option<(f64, &str)>, (score, name)| match best {
+                        Some((top, _)) if top >= score => best,
+                        _ => Some((score, name)),
+                    },
+                )
+                .filter(|(score, _)| *score >= options.threshold)
+                .map(|(score, name)| Candidate {
+                    doc: doc.clone(),
+                    score,
+                    matched: name.to_string(),
+                })
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    candidates.truncate(options.limit as usize);
+
+    candidates
+}
+
+/// Stored documents whose name, or any alias, is close to `query`, best first.
+/// Scored by the database, every document of the collection is compared.
+pub async fn fuzzy_find<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    query: &str,
+    options: &FuzzyOptions,
+) -> Result<Vec<Candidate<T>>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(FUZZY_FIND)
+        .bind_var("@collection", T::collection_name())
+        .bind_var("query", fold(query))
+        .bind_var("threshold", options.threshold)
+        .bind_var("limit", options.limit)
+        .build();
+
+    let resp: Vec<Candidate<T>> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::db::test::common;
+    use crate::io::EngineWrite;
+    use crate::models::DocDetails;
+    use crate::service::fuzzy::*;
+
+    fn artist(name: &str, aliases: &[&str]) -> Artist {
+        let mut artist = Artist::new();
+        artist.name(name);
+        aliases.iter().for_each(|a| {
+            artist.alias(a);
+        });
+        artist
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("björk", "bjork"), 1);
+        assert!((similarity("Aphex  Twin", "aphex twin") - 1.0).abs() < f64::EPSILON);
+        assert!((similarity("Björk", "bjork") - 1.0).abs() < f64::EPSILON);
+        assert!((similarity("Sigur Rós", "sigur ros!") - 1.0).abs() < f64::EPSILON);
+        assert!(similarity("Aphex Twin", "Afex Twin") >= 0.8);
+        assert!(similarity("Twin Aphex", "Aphex Twin") > 0.6);
+        assert!(similarity("Aphex Twin", "Boards of Canada") < 0.3);
+    }
+
+    #[test]
+    fn test_rank() {
+        let artists = vec![
+            artist("Richard D. James", &["Aphex Twin", "AFX"]),
+            artist("Boards of Canada", &[]),
+            artist("Aphex Twins Tribute Band", &[]),
+        ];
+
+        let mut options = FuzzyOptions::new();
+        let found = rank("Aphex Twn", &artists, &options);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].doc.get_name(), "Richard D. James");
+        assert_eq!(found[0].matched, "Aphex Twin");
+
+        options.threshold(0.2).limit(2);
+        let found = rank("Aphex Twn", &artists, &options);
+        assert_eq!(found.len(), 2);
+        assert!(found[0].score >= found[1].score);
+    }
+
+    #[tokio::test]
+    async fn test_fuzzy_find() -> Result<(), EngineError> {
+        let db = common().await?;
+        let stored = artist("Squarepusher", &["Chaos A.D."]);
+        let accented = artist("Múm", &[]);
+        db.insert(accented.clone()).await?;
+        db.insert(stored.clone()).await?;
+
+        let found = fuzzy_find::<Artist>(&db, "squarepushr", &FuzzyOptions::new()).await?;
+        assert!(found.iter().any(|c| c.doc.id() == stored.id()));
+        let found = fuzzy_find::<Artist>(&db, "chaos ad", &FuzzyOptions::new()).await?;
+        assert!(found.iter().any(|c| c.matched == "Chaos A.D."));
+        let found = fuzzy_find::<Artist>(&db, "MUM", &FuzzyOptions::new()).await?;
+        assert!(found.iter().any(|c| c.doc.id() == accented.id()));
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! Genre tree and the albums and artists tagged with each genre.
+//!
+//! Tags are `tagged` edges from an `Album` or `Artist` to a `Genre`,
+//! sub genres are `sub_genre` edges from the broader genre.
+
+use arangors::AqlQuery;
+
+use crate::engine::db::arangodb::aql_snippet::{GENRE_COUNTS, RENAME, TAG, TAGGED_IN, UNTAG};
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::models::album::Album;
+use crate::models::artist::Artist;
+use crate::models::edge::{Direction, Edge, EdgeFilter};
+use crate::models::genre::Genre;
+use crate::models::id::DocId;
+use crate::models::{DocDetails, ReqModelTraits};
+use crate::service::tree::{self, MAX_DEPTH};
+
+/// Edge collection linking albums and artists to their genres
+pub const TAGGED: &str = "tagged";
+/// Edge collection linking a genre to its sub genres
+pub const SUB_GENRE: &str = "sub_genre";
+
+/// Number of albums and artists tagged with a genre or any of its sub genres.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GenreCount {
+    pub genre: Genre,
+    pub albums: u64,
+    pub artists: u64,
+}
+
+/// Stores a new genre, linking it under its parent when one is set.
+/// Fails without writing anything if the parent doesn't exist.
+pub async fn create_genre(engine: &ArangoDb, genre: Genre) -> Result<Genre, EngineError> {
+    let parent = genre.get_parent().map(DocId::to_string);
+    tree::create(engine, SUB_GENRE, genre, parent.as_deref()).await
+}
+
+/// Moves a stored genre under `parent_id`, or to the top of the tree when `None`.
+/// Fails if either genre is missing or the move would create a cycle.
+pub async fn set_parent(
+    engine: &ArangoDb,
+    genre_id: &str,
+    parent_id: Option<&str>,
+) -> Result<Genre, EngineError> {
+    tree::set_parent(engine, SUB_GENRE, genre_id, parent_id).await
+}
+
+/// Every sub genre below a genre, nearest first.
+pub async fn sub_genres(engine: &ArangoDb, genre_id: &str) -> Result<Vec<Genre>, EngineError> {
+    tree::descendants(engine, SUB_GENRE, genre_id).await
+}
+
+/// The broader genres of a genre, nearest first.
+pub async fn parent_genres(engine: &ArangoDb, genre_id: &str) -> Result<Vec<Genre>, EngineError> {
+    tree::ancestors(engine, SUB_GENRE, genre_id).await
+}
+
+/// Tags an album or artist with a genre, tagging twice has no effect.
+/// Fails if either doesn't exist.
+pub async fn tag(engine: &ArangoDb, doc_id: &str, genre_id: &str) -> Result<Edge, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(TAG)
+        .bind_var("@collection", TAGGED)
+        .bind_var("doc", doc_id)
+        .bind_var("genre", genre_id)
+        .build();
+
+    let resp: Option<Edge> = engine.db().aql_query(aql).await?.pop();
+    if let Some(edge) = resp {
+        Ok(edge)
+    } else {
+        DbError::FailedToCreate.into()
+    }
+}
+
+/// Removes a genre from an album or artist, returning the removed tag.
+pub async fn untag(engine: &ArangoDb, doc_id: &str, genre_id: &str) -> Result<Edge, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(UNTAG)
+        .bind_var("@collection", TAGGED)
+        .bind_var("doc", doc_id)
+        .bind_var("genre", genre_id)
+        .build();
+
+    let resp: Option<Edge> = engine.db().aql_query(aql).await?.pop();
+    if let Some(edge) = resp {
+        Ok(edge)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Genres an album or artist is tagged with.
+pub async fn genres_of(engine: &ArangoDb, doc_id: &str) -> Result<Vec<Genre>, EngineError> {
+    Edge::traverse(engine, TAGGED, doc_id, Direction::Outbound, &EdgeFilter::new()).await
+}
+
+/// Albums tagged with a genre or any of its sub genres.
+pub async fn albums_in(engine: &ArangoDb, genre_id: &str) -> Result<Vec<Album>, EngineError> {
+    tagged_in(engine, genre_id).await
+}
+
+/// Artists tagged with a genre or any of its sub genres.
+pub async fn artists_in(engine: &ArangoDb, genre_id: &str) -> Result<Vec<Artist>, EngineError> {
+    tagged_in(engine, genre_id).await
+}
+
+async fn tagged_in<T: ReqModelTraits>(engine: &ArangoDb, genre_id: &str) -> Result<Vec<T>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(TAGGED_IN)
+        .bind_var("@sub_genre", SUB_GENRE)
+        .bind_var("@tagged", TAGGED)
+        .bind_var("genre", genre_id)
+        .bind_var("collection", T::collection_name())
+        .bind_var("depth", MAX_DEPTH)
+        .build();
+
+    let resp: Vec<T> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+/// Album and artist counts of every genre, including those tagged with a sub genre.
+pub async fn genre_counts(engine: &ArangoDb) -> Result<Vec<GenreCount>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(GENRE_COUNTS)
+        .bind_var("@collection", Genre::collection_name())
+        .bind_var("@sub_genre", SUB_GENRE)
+        .bind_var("@tagged", TAGGED)
+        .bind_var("album", Album::collection_name())
+        .bind_var("artist", Artist::collection_name())
+        .bind_var("depth", MAX_DEPTH)
+        .build();
+
+    let resp: Vec<GenreCount> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+/// Renames a genre, tags and sub genres are linked by `_id` so they are kept.
+/// Fails if another genre already has the name, use `merge_genres` instead.
+pub async fn rename_genre(engine: &ArangoDb, genre_id: &str, name: &str) -> Result<Genre, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(RENAME)
+        .bind_var("@collection", Genre::collection_name())
+        .bind_var("id", genre_id)
+        .bind_var("name", name.trim().to_ascii_lowercase())
+        .build();
+
+    let resp: Option<Genre> = engine.db().aql_query(aql).await?.pop();
+    if let Some(genre) = resp {
+        Ok(genre)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Merges `remove` into `keep`: its tags and sub genres are moved to `keep`
+/// and it is deleted, all in one transaction. Fails if `keep` is `remove`
+/// or one of its sub genres.
+pub async fn merge_genres(engine: &ArangoDb, keep: &str, remove: &str) -> Result<Genre, EngineError> {
+    tree::merge(engine, SUB_GENRE, &[(TAGGED, Direction::Inbound)], keep, remove).await
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::db::test::common;
+    use crate::engine::EngineError;
+    use crate::io::EngineWrite;
+    use crate::models::album::Album;
+    use crate::models::genre::Genre;
+    use crate::models::DocDetails;
+    use crate::service::genre::*;
+
+    type TestResult = Result<(), EngineError>;
+
+    #[tokio::test]
+    async fn test_genre_tree() -> TestResult {
+        let db = common().await?;
+
+        let mut electronic = Genre::new();
+        electronic.name("Electronic");
+        let electronic = create_genre(&db, electronic).await?;
+        let mut techno = Genre::new();
+        techno.name("Techno").parent(electronic.id());
+        let techno = create_genre(&db, techno).await?;
+        let mut detroit = Genre::new();
+        detroit.name("Detroit Techno").parent(techno.id());
+        let detroit = create_genre(&db, detroit).await?;
+
+        let mut album = Album::new();
+        album.name("genre album");
+        db.insert(album.clone()).await?;
+        tag(&db, &album.id(), &detroit.id()).await?;
+
+        let albums = albums_in(&db, &electronic.id()).await?;
+        assert!(albums.iter().any(|a| a.id() == album.id()));
+        assert_eq!(sub_genres(&db, &electronic.id()).await?.len(), 2);
+
+        let renamed = rename_genre(&db, &detroit.id(), "Detroit").await?;
+        assert_eq!(renamed.get_name(), "detroit");
+
+        // Folding the sub genre back in keeps the album tagged
+        assert!(merge_genres(&db, &detroit.id(), &techno.id()).await.is_err());
+        merge_genres(&db, &techno.id(), &detroit.id()).await?;
+        let genres = genres_of(&db, &album.id()).await?;
+        assert_eq!(genres.len(), 1);
+        assert_eq!(genres[0].id(), techno.id());
+        Ok(())
+    }
+}
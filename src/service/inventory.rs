@@ -0,0 +1,358 @@
+use std::convert::TryFrom;
+
+use arangors::AqlQuery;
+
+use crate::engine::db::arangodb::aql_snippet::{
+    ADJUST_STOCK, CREATE_INVENTORY_VARIANT, FIND_BY_BARCODE, GET_DOCUMENT, GRADED_VARIANTS_OF,
+    SET_STOCK, VARIANTS_OF,
+};
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::models::album::Album;
+use crate::models::barcode::Barcode;
+use crate::models::grade::{Grade, GradeFilter};
+use crate::models::inventory::Inventory;
+use crate::models::stock_movement::StockMovement;
+use crate::models::validate::Validate;
+use crate::models::variant::Variant;
+use crate::models::DocDetails;
+
+/// A variant of an album together with the inventory holding its stock.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VariantStock {
+    pub variant: Variant,
+    pub inventory: Inventory,
+}
+
+impl VariantStock {
+    pub fn count(&self) -> u32 {
+        self.inventory.get_count()
+    }
+}
+
+/// An album with every variant of it and their stock.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AlbumStock {
+    pub album: Album,
+    pub variants: Vec<VariantStock>,
+}
+
+impl AlbumStock {
+    /// Copies in stock across every variant
+    pub fn count(&self) -> u32 {
+        self.variants.iter().map(VariantStock::count).sum()
+    }
+}
+
+/// Creates a new `Inventory` holding `count` copies and the `Variant` edge
+/// from `album` to it, recording the initial count in the ledger as `movement`.
+/// Fails without writing anything if the album doesn't exist.
+pub async fn create_inventory_variant(
+    engine: &ArangoDb,
+    album: &Album,
+    variant: Variant,
+    count: u32,
+    movement: StockMovement,
+) -> Result<VariantStock, EngineError> {
+    let mut inventory = Inventory::new();
+    inventory.amount(count);
+    inventory.validate()?;
+    movement.validate()?;
+
+    let aql = AqlQuery::builder()
+        .query(CREATE_INVENTORY_VARIANT)
+        .bind_var("@inventory", Inventory::collection_name())
+        .bind_var("@variant", Variant::collection_name())
+        .bind_var("@ledger", StockMovement::collection_name())
+        .bind_var("album", album.id())
+        .bind_var("inventory", serde_json::to_value(&inventory)?)
+        .bind_var("variant", serde_json::to_value(&variant)?)
+        .bind_var("movement", serde_json::to_value(&movement)?)
+        .build();
+
+    let resp: Option<VariantStock> = engine.db().aql_query(aql).await?.pop();
+    if let Some(stock) = resp {
+        Ok(stock)
+    } else {
+        DbError::FailedToCreate.into()
+    }
+}
+
+/// Lists every variant of an album with its current stock.
+pub async fn variants_of(engine: &ArangoDb, album_id: &str) -> Result<Vec<VariantStock>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(VARIANTS_OF)
+        .bind_var("@variant", Variant::collection_name())
+        .bind_var("album", album_id)
+        .build();
+
+    let resp: Vec<VariantStock> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+/// Finds the album with the given barcode along with its variants and current stock.
+pub async fn find_by_barcode(engine: &ArangoDb, barcode: &Barcode) -> Result<AlbumStock, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(FIND_BY_BARCODE)
+        .bind_var("@collection", Album::collection_name())
+        .bind_var("@variant", Variant::collection_name())
+        .bind_var("barcode", barcode.as_str())
+        .build();
+
+    let resp: Option<AlbumStock> = engine.db().aql_query(aql).await?.pop();
+    if let Some(stock) = resp {
+        Ok(stock)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Lists the variants of an album meeting the minimum grades of `filter`,
+/// best media grade first with ties broken by the sleeve grade.
+pub async fn graded_variants_of(
+    engine: &ArangoDb,
+    album_id: &str,
+    filter: &GradeFilter,
+) -> Result<Vec<VariantStock>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(GRADED_VARIANTS_OF)
+        .bind_var("@variant", Variant::collection_name())
+        .bind_var("album", album_id)
+        .bind_var("media", serde_json::to_value(filter.media_grades())?)
+        .bind_var("sleeve", serde_json::to_value(filter.sleeve_grades())?)
+        .bind_var("scale", serde_json::to_value(Grade::SCALE)?)
+        .build();
+
+    let resp: Vec<VariantStock> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+/// Adds `delta` copies to the stock of an inventory, a negative `delta` removes them.
+/// The change is written to the ledger as `movement` in the same query.
+///
+/// The change is made server side with an exclusive lock on the collection, so
+/// concurrent sales are applied one after another and can never take the count
+/// below zero. Fails with `DbError::InsufficientStock` if there isn't enough stock
+/// and with `DbError::StockOverflow` if the count can't hold the change.
+pub async fn adjust_stock(
+    engine: &ArangoDb,
+    inventory_id: &str,
+    delta: i64,
+    movement: StockMovement,
+) -> Result<Inventory, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(ADJUST_STOCK)
+        .bind_var("@collection", Inventory::collection_name())
+        .bind_var("@ledger", StockMovement::collection_name())
+        .bind_var("id", inventory_id)
+        .bind_var("delta", delta)
+        .bind_var("max", u32::MAX)
+        .bind_var("movement", serde_json::to_value(&movement)?)
+        .build();
+
+    let resp: Option<Inventory> = engine.db().aql_query(aql).await?.pop();
+    if let Some(inventory) = resp {
+        return Ok(inventory);
+    }
+
+    // Nothing was updated, either the inventory is missing or the guard failed
+    let aql = AqlQuery::builder()
+        .query(GET_DOCUMENT)
+        .bind_var("id", inventory_id)
+        .build();
+    let current: Vec<Option<Inventory>> = engine.db().aql_query(aql).await?;
+    let available = match current.into_iter().flatten().next() {
+        Some(inventory) => inventory.get_count(),
+        None => return DbError::ItemNotFound.into(),
+    };
+    match u32::try_from(delta.unsigned_abs()) {
+        Ok(requested) if delta < 0 => DbError::InsufficientStock {
+            available,
+            requested,
+        }
+        .into(),
+        _ => DbError::StockOverflow { available, delta }.into(),
+    }
+}
+
+/// Overwrites the stock of an inventory i.e. after a stock take,
+/// the difference is written to the ledger as `movement`.
+pub async fn set_stock(
+    engine: &ArangoDb,
+    inventory_id: &str,
+    count: u32,
+    movement: StockMovement,
+) -> Result<Inventory, EngineError> {
+    let aql = set_stock_query(inventory_id, count, &movement)?;
+
+    let resp: Option<Inventory> = engine.db().aql_query(aql).await?.pop();
+    if let Some(inventory) = resp {
+        Ok(inventory)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Query of `set_stock`, to be run along with other writes
+pub(crate) fn set_stock_query(
+    inventory_id: &str,
+    count: u32,
+    movement: &StockMovement,
+) -> Result<AqlQuery<'static>, EngineError> {
+    Ok(AqlQuery::builder()
+        .query(SET_STOCK)
+        .bind_var("@collection", Inventory::collection_name())
+        .bind_var("@ledger", StockMovement::collection_name())
+        .bind_var("id", inventory_id)
+        .bind_var("count", count)
+        .bind_var("movement", serde_json::to_value(movement)?)
+        .build())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::db::test::common;
+    use crate::engine::{DbError, EngineError};
+    use crate::io::EngineWrite;
+    use crate::models::album::Album;
+    use crate::models::grade::{Grade, GradeFilter, SleeveGrade};
+    use crate::models::id::DocKey;
+    use crate::models::stock_movement::{Reason, StockMovement};
+    use crate::models::format::{Format, Medium};
+    use crate::models::variant::Variant;
+    use crate::models::DocDetails;
+    use crate::service::inventory::*;
+
+    type TestResult = Result<(), EngineError>;
+
+    #[tokio::test]
+    async fn test_create_inventory_variant() -> TestResult {
+        let db = common().await?;
+        let mut album = Album::new();
+        album.name("variant album");
+        db.insert(album.clone()).await?;
+
+        let mut variant = Variant::new();
+        variant
+            .format(Format::new(Medium::CD))
+            .details("Test Variant");
+        let purchase = StockMovement::new(Reason::Purchase);
+        let stock = create_inventory_variant(&db, &album, variant, 3, purchase).await?;
+        assert_eq!(stock.count(), 3);
+        assert_eq!(stock.variant.get_vertex(), &album.id());
+
+        let variants = variants_of(&db, &album.id()).await?;
+        assert_eq!(variants.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_by_barcode() -> TestResult {
+        let db = common().await?;
+        let barcode = Barcode::new("5099902894928")?;
+        let mut album = Album::new();
+        album.name("barcode album").barcode(barcode.clone());
+        // Ignore a duplicate left by a previous run
+        if db.insert(album.clone()).await.is_ok() {
+            let purchase = StockMovement::new(Reason::Purchase);
+            create_inventory_variant(&db, &album, Variant::new(), 2, purchase).await?;
+        }
+
+        let found = find_by_barcode(&db, &barcode).await?;
+        assert_eq!(found.album.get_barcode(), Some(barcode));
+        assert!(found.count() >= 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fail_on_missing_album() -> TestResult {
+        let db = common().await?;
+        let album = Album::new();
+
+        let purchase = StockMovement::new(Reason::Purchase);
+        let resp = create_inventory_variant(&db, &album, Variant::new(), 1, purchase).await;
+        assert!(resp.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fail_on_missing_inventory() -> TestResult {
+        let db = common().await?;
+        let mut album = Album::new();
+        album.name("variant without stock");
+        let (album_id, _) = db.insert(album).await?;
+
+        let mut variant = Variant::new();
+        variant._from = album_id;
+        variant._to = DocKey::<Inventory>::new("missing")?.id();
+        assert!(db.insert(variant).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_graded_variants() -> TestResult {
+        let db = common().await?;
+        let mut album = Album::new();
+        album.name("graded album");
+        db.insert(album.clone()).await?;
+
+        let grades = [
+            (Grade::VeryGood, SleeveGrade::Graded(Grade::Mint)),
+            (Grade::NearMint, SleeveGrade::Generic),
+            (Grade::Mint, SleeveGrade::Graded(Grade::VeryGoodPlus)),
+        ];
+        for (media, sleeve) in grades {
+            let mut variant = Variant::new();
+            variant.media_grade(media).sleeve_grade(sleeve);
+            let purchase = StockMovement::new(Reason::Purchase);
+            create_inventory_variant(&db, &album, variant, 1, purchase).await?;
+        }
+
+        let mut filter = GradeFilter::new();
+        filter.media_at_least(Grade::VeryGoodPlus);
+        let found = graded_variants_of(&db, &album.id(), &filter).await?;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].variant.get_media_grade(), Grade::Mint);
+
+        filter.sleeve_at_least(Grade::VeryGood);
+        let found = graded_variants_of(&db, &album.id(), &filter).await?;
+        assert_eq!(found.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_sales() -> TestResult {
+        let db = common().await?;
+        let mut album = Album::new();
+        album.name("last copy");
+        db.insert(album.clone()).await?;
+
+        let purchase = StockMovement::new(Reason::Purchase);
+        let stock = create_inventory_variant(&db, &album, Variant::new(), 1, purchase).await?;
+        let id = stock.inventory.id();
+
+        let (a, b) = futures::join!(
+            adjust_stock(&db, &id, -1, StockMovement::new(Reason::Sale)),
+            adjust_stock(&db, &id, -1, StockMovement::new(Reason::Sale))
+        );
+        assert!(a.is_ok() != b.is_ok());
+        let err = a.err().or_else(|| b.err()).unwrap();
+        assert!(matches!(
+            err.downcast_ref::<DbError>(),
+            Some(DbError::InsufficientStock { available: 0, requested: 1 })
+        ));
+
+        let inventory = set_stock(&db, &id, 4, StockMovement::new(Reason::Adjustment)).await?;
+        assert_eq!(inventory.get_count(), 4);
+
+        let delta = i64::from(u32::MAX);
+        let err = adjust_stock(&db, &id, delta, StockMovement::new(Reason::Purchase))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DbError>(),
+            Some(DbError::StockOverflow { available: 4, .. })
+        ));
+        Ok(())
+    }
+}
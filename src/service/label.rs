@@ -0,0 +1,177 @@
+//! Labels, the releases issued on them and their sub label hierarchy.
+//!
+//! Releases are `released_on` edges from an `Album` to a `Label` carrying the
+//! catalogue number, sub labels are `sub_label` edges from the parent label.
+
+use arangors::AqlQuery;
+
+use crate::engine::db::arangodb::aql_snippet::{FIND_RELEASE, LINK_RELEASE, RELEASES_ON};
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::models::album::Album;
+use crate::models::edge::Edge;
+use crate::models::id::DocId;
+use crate::models::label::Label;
+use crate::service::tree::{self, MAX_DEPTH};
+
+/// Edge collection linking an album to the label it was released on
+pub const RELEASED_ON: &str = "released_on";
+/// Edge collection linking a label to its sub labels
+pub const SUB_LABEL: &str = "sub_label";
+
+/// An album as released on a label.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Release {
+    pub album: Album,
+    pub label: Label,
+    pub cat_no: String,
+}
+
+/// Catalogue numbers are compared ignoring case and spaces i.e. `warp 12` matches `WARP12`
+pub fn normalise_cat_no(cat_no: &str) -> String {
+    cat_no
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Stores a new label, linking it under its parent when one is set.
+/// Fails without writing anything if the parent doesn't exist.
+pub async fn create_label(engine: &ArangoDb, label: Label) -> Result<Label, EngineError> {
+    let parent = label.get_parent().map(DocId::to_string);
+    tree::create(engine, SUB_LABEL, label, parent.as_deref()).await
+}
+
+/// Moves a stored label under `parent_id`, or to the top of the hierarchy when `None`.
+/// Fails if either label is missing or the move would create a cycle.
+pub async fn set_parent(
+    engine: &ArangoDb,
+    label_id: &str,
+    parent_id: Option<&str>,
+) -> Result<Label, EngineError> {
+    tree::set_parent(engine, SUB_LABEL, label_id, parent_id).await
+}
+
+/// Every sub label below a label, nearest first.
+pub async fn sub_labels(engine: &ArangoDb, label_id: &str) -> Result<Vec<Label>, EngineError> {
+    tree::descendants(engine, SUB_LABEL, label_id).await
+}
+
+/// The parent of a label, its parent and so on up to the top of the hierarchy.
+pub async fn parent_labels(engine: &ArangoDb, label_id: &str) -> Result<Vec<Label>, EngineError> {
+    tree::ancestors(engine, SUB_LABEL, label_id).await
+}
+
+/// Records `album_id` as released on `label_id` under `cat_no`,
+/// replacing the catalogue number if it is already linked.
+/// Fails if either the album or label doesn't exist.
+pub async fn link_release(
+    engine: &ArangoDb,
+    album_id: &str,
+    label_id: &str,
+    cat_no: &str,
+) -> Result<Edge, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(LINK_RELEASE)
+        .bind_var("@collection", RELEASED_ON)
+        .bind_var("album", album_id)
+        .bind_var("label", label_id)
+        .bind_var("cat_no", cat_no.trim())
+        .build();
+
+    let resp: Option<Edge> = engine.db().aql_query(aql).await?.pop();
+    if let Some(edge) = resp {
+        Ok(edge)
+    } else {
+        DbError::FailedToCreate.into()
+    }
+}
+
+/// Every release on a label ordered by catalogue number,
+/// including those on its sub labels when `sub_labels` is set.
+pub async fn releases_on(
+    engine: &ArangoDb,
+    label_id: &str,
+    sub_labels: bool,
+) -> Result<Vec<Release>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(RELEASES_ON)
+        .bind_var("@sub_label", SUB_LABEL)
+        .bind_var("@released_on", RELEASED_ON)
+        .bind_var("label", label_id)
+        .bind_var("depth", if sub_labels { MAX_DEPTH } else { 0 })
+        .build();
+
+    let resp: Vec<Release> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+/// Finds the release on a label with the given catalogue number.
+pub async fn find_release(
+    engine: &ArangoDb,
+    label_id: &str,
+    cat_no: &str,
+) -> Result<Release, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(FIND_RELEASE)
+        .bind_var("@released_on", RELEASED_ON)
+        .bind_var("label", label_id)
+        .bind_var("cat_no", normalise_cat_no(cat_no))
+        .build();
+
+    let resp: Option<Release> = engine.db().aql_query(aql).await?.pop();
+    if let Some(release) = resp {
+        Ok(release)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::db::test::common;
+    use crate::engine::EngineError;
+    use crate::io::EngineWrite;
+    use crate::models::album::Album;
+    use crate::models::label::Label;
+    use crate::models::DocDetails;
+    use crate::service::label::*;
+
+    type TestResult = Result<(), EngineError>;
+
+    #[test]
+    fn test_normalise_cat_no() {
+        assert_eq!(normalise_cat_no(" warp 12 "), "WARP12");
+        assert_eq!(normalise_cat_no("LC-0001"), "LC-0001");
+    }
+
+    #[tokio::test]
+    async fn test_label_releases() -> TestResult {
+        let db = common().await?;
+
+        let mut parent = Label::new();
+        parent.name("Parent Records");
+        let parent = create_label(&db, parent).await?;
+        let mut imprint = Label::new();
+        imprint.name("Imprint").parent(parent.id());
+        let imprint = create_label(&db, imprint).await?;
+
+        let mut album = Album::new();
+        album.name("label album");
+        db.insert(album.clone()).await?;
+        link_release(&db, &album.id(), &imprint.id(), "IMP 001").await?;
+
+        let found = find_release(&db, &imprint.id(), "imp001").await?;
+        assert_eq!(found.album.id(), album.id());
+        assert!(releases_on(&db, &parent.id(), false).await?.is_empty());
+        assert_eq!(releases_on(&db, &parent.id(), true).await?.len(), 1);
+
+        assert_eq!(sub_labels(&db, &parent.id()).await?.len(), 1);
+        assert!(set_parent(&db, &parent.id(), Some(&imprint.id())).await.is_err());
+        let moved = set_parent(&db, &imprint.id(), None).await?;
+        assert_eq!(moved.get_parent(), None);
+        assert!(parent_labels(&db, &imprint.id()).await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Reading and replaying the `StockMovement` ledger.
+
+use arangors::AqlQuery;
+use serde_json::Value;
+
+use crate::engine::db::arangodb::aql_snippet::{LEDGER_CHECK, MOVEMENTS, RECOMPUTE_STOCK};
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::models::inventory::Inventory;
+use crate::models::stock_movement::StockMovement;
+use crate::models::DocDetails;
+
+/// Stored count of an inventory compared to the total of its ledger.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LedgerCheck {
+    pub inventory: String,
+    #[serde(default)]
+    pub count: u32,
+    pub ledger: i64,
+}
+
+impl LedgerCheck {
+    pub fn is_consistent(&self) -> bool {
+        i64::from(self.count) == self.ledger
+    }
+}
+
+/// Replays the ledger of a single inventory and compares it to the stored count.
+pub async fn verify_stock(engine: &ArangoDb, inventory_id: &str) -> Result<LedgerCheck, EngineError> {
+    let mut resp = ledger_check(engine, Value::from(inventory_id)).await?;
+    if let Some(check) = resp.pop() {
+        Ok(check)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Replays the ledger of every inventory, use `LedgerCheck::is_consistent`
+/// to find the items whose count has drifted.
+pub async fn verify_all(engine: &ArangoDb) -> Result<Vec<LedgerCheck>, EngineError> {
+    ledger_check(engine, Value::Null).await
+}
+
+async fn ledger_check(engine: &ArangoDb, id: Value) -> Result<Vec<LedgerCheck>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(LEDGER_CHECK)
+        .bind_var("@collection", Inventory::collection_name())
+        .bind_var("@ledger", StockMovement::collection_name())
+        .bind_var("id", id)
+        .build();
+
+    let resp: Vec<LedgerCheck> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+/// Resets the count of an inventory to the total of its ledger.
+pub async fn recompute_stock(engine: &ArangoDb, inventory_id: &str) -> Result<Inventory, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(RECOMPUTE_STOCK)
+        .bind_var("@collection", Inventory::collection_name())
+        .bind_var("@ledger", StockMovement::collection_name())
+        .bind_var("id", inventory_id)
+        .build();
+
+    let resp: Option<Inventory> = engine.db().aql_query(aql).await?.pop();
+    if let Some(inventory) = resp {
+        Ok(inventory)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Lists the movements of an inventory made between `from` (inclusive) and
+/// `to` (exclusive), both given as UTC timestamps in milliseconds, oldest first.
+/// Movements are timed by the database as they are applied, not by the client.
+pub async fn movements(
+    engine: &ArangoDb,
+    inventory_id: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<StockMovement>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(MOVEMENTS)
+        .bind_var("@ledger", StockMovement::collection_name())
+        .bind_var("id", inventory_id)
+        .bind_var("from", from)
+        .bind_var("to", to)
+        .build();
+
+    let resp: Vec<StockMovement> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use crate::engine::db::test::common;
+    use crate::engine::EngineError;
+    use crate::io::EngineWrite;
+    use crate::models::album::Album;
+    use crate::models::stock_movement::{Reason, StockMovement};
+    use crate::models::variant::Variant;
+    use crate::models::DocDetails;
+    use crate::service::inventory::{adjust_stock, create_inventory_variant};
+    use crate::service::ledger::*;
+
+    type TestResult = Result<(), EngineError>;
+
+    #[tokio::test]
+    async fn test_replay_ledger() -> TestResult {
+        let db = common().await?;
+        let start = Utc::now().timestamp_millis();
+
+        let mut album = Album::new();
+        album.name("ledger album");
+        db.insert(album.clone()).await?;
+
+        let purchase = StockMovement::new(Reason::Purchase);
+        let stock = create_inventory_variant(&db, &album, Variant::new(), 5, purchase).await?;
+        let id = stock.inventory.id();
+
+        let mut sale = StockMovement::new(Reason::Sale);
+        sale.reference("order-1").actor("till");
+        adjust_stock(&db, &id, -2, sale).await?;
+
+        let check = verify_stock(&db, &id).await?;
+        assert!(check.is_consistent());
+        assert_eq!(check.ledger, 3);
+
+        let history = movements(&db, &id, start, Utc::now().timestamp_millis() + 1).await?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].get_reason(), Reason::Sale);
+        assert_eq!(history[1].get_delta(), -2);
+
+        let inventory = recompute_stock(&db, &id).await?;
+        assert_eq!(inventory.get_count(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keep_count_on_update() -> TestResult {
+        let db = common().await?;
+        let mut album = Album::new();
+        album.name("counted album");
+        db.insert(album.clone()).await?;
+
+        let purchase = StockMovement::new(Reason::Purchase);
+        let stock = create_inventory_variant(&db, &album, Variant::new(), 2, purchase).await?;
+        let mut inventory = stock.inventory.clone();
+        inventory.amount(7);
+
+        db.update(inventory).await?;
+        let check = verify_stock(&db, &stock.inventory.id()).await?;
+        assert_eq!(check.count, 2);
+        Ok(())
+    }
+}
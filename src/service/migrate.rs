@@ -0,0 +1,381 @@
+//! One off upgrades of documents stored by earlier versions.
+
+use std::collections::HashMap;
+
+use arangors::AqlQuery;
+use serde_json::{json, Value};
+
+use crate::engine::db::arangodb::aql_snippet::{
+    BARCODES, CAT_NOS, FOREIGN_KEYS, MIGRATE_CAT_NOS, NAMES, OUTDATED, REPLACE_UNCHANGED,
+    SET_BARCODES, SET_EXTERNAL_IDS, SET_SEARCH_KEYS, UNLINKED_CAT_NOS,
+};
+use crate::engine::db::arangodb::REVISION_CONFLICT;
+use crate::engine::db::ArangoDb;
+use crate::engine::EngineError;
+use crate::models::album::Album;
+use crate::models::artist::Artist;
+use crate::models::barcode::Barcode;
+use crate::models::external_id::upgrade_foreign_key;
+use crate::models::label::Label;
+use crate::models::name::search_key;
+use crate::models::version::{upgrade, Versioned};
+use crate::models::{DocDetails, ReqModelTraits};
+use crate::service::label::RELEASED_ON;
+
+/// Outcome of moving album catalogue numbers to label links.
+#[derive(Debug, Clone, Default)]
+pub struct CatNoMigration {
+    /// `_id`s of the albums linked to a label
+    pub linked: Vec<String>,
+    /// `_id`s of the albums with a catalogue number but no label link
+    pub unlinked: Vec<String>,
+}
+
+/// Outcome of normalising stored album barcodes.
+#[derive(Debug, Clone, Default)]
+pub struct BarcodeMigration {
+    /// Number of albums whose barcode was rewritten or, when blank, removed
+    pub updated: u64,
+    /// `_id` and barcode of the albums whose barcode is not valid, left as they were
+    pub invalid: Vec<(String, String)>,
+}
+
+/// Outcome of rewriting stored documents in the current version of their model.
+#[derive(Debug, Clone, Default)]
+pub struct UpgradeReport {
+    /// Number of documents rewritten
+    pub upgraded: u64,
+    /// `_id` of each document that could not be upgraded and why
+    pub failed: Vec<(String, String)>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct StoredName {
+    id: String,
+    #[serde(default)]
+    name: String,
+    search_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StoredCatNo {
+    id: String,
+    cat_no: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct StoredBarcode {
+    id: String,
+    barcode: Option<String>,
+}
+
+/// Links albums to labels using their `cat_no`, where it is written as
+/// `Label Name - CAT123` and a label with that name exists. Names are compared
+/// by their search key, so case and accents don't have to match.
+/// Albums that could not be linked are listed so they can be fixed by hand,
+/// `Album.cat_no` itself is left as it was.
+pub async fn cat_nos(engine: &ArangoDb) -> Result<CatNoMigration, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(NAMES)
+        .bind_var("@collection", Label::collection_name())
+        .build();
+    let labels: Vec<StoredName> = engine.db().aql_query(aql).await?;
+    let mut by_key = HashMap::new();
+    for label in labels {
+        by_key.entry(search_key(&label.name)).or_insert(label.id);
+    }
+
+    let aql = AqlQuery::builder()
+        .query(CAT_NOS)
+        .bind_var("@album", Album::collection_name())
+        .build();
+    let stored: Vec<StoredCatNo> = engine.db().aql_query(aql).await?;
+    let items: Vec<Value> = stored
+        .into_iter()
+        .filter_map(|album| {
+            let (prefix, cat_no) = album.cat_no.split_once(" - ")?;
+            let label = by_key.get(&search_key(prefix))?;
+            let cat_no = cat_no.trim();
+            if cat_no.is_empty() {
+                return None;
+            }
+            Some(json!({"album": album.id, "label": label, "cat_no": cat_no}))
+        })
+        .collect();
+
+    let aql = AqlQuery::builder()
+        .query(MIGRATE_CAT_NOS)
+        .bind_var("@released_on", RELEASED_ON)
+        .bind_var("items", items)
+        .build();
+    let linked: Vec<String> = engine.db().aql_query(aql).await?;
+
+    let aql = AqlQuery::builder()
+        .query(UNLINKED_CAT_NOS)
+        .bind_var("@album", Album::collection_name())
+        .bind_var("@released_on", RELEASED_ON)
+        .build();
+    let unlinked: Vec<String> = engine.db().aql_query(aql).await?;
+
+    Ok(CatNoMigration { linked, unlinked })
+}
+
+/// Normalises the barcode of every stored album, removes blank ones and then
+/// creates the unique barcode index. Invalid barcodes are listed for fixing by
+/// hand, creating the index fails while two albums share a barcode.
+pub async fn barcodes(engine: &ArangoDb) -> Result<BarcodeMigration, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(BARCODES)
+        .bind_var("@collection", Album::collection_name())
+        .build();
+    let stored: Vec<StoredBarcode> = engine.db().aql_query(aql).await?;
+
+    let mut items = Vec::new();
+    let mut invalid = Vec::new();
+    for item in stored {
+        let raw = item.barcode.unwrap_or_default();
+        if raw.trim().is_empty() {
+            items.push(StoredBarcode {
+                id: item.id,
+                barcode: None,
+            });
+            continue;
+        }
+        match Barcode::new(&raw) {
+            Ok(barcode) if barcode.as_str() != raw => items.push(StoredBarcode {
+                id: item.id,
+                barcode: Some(barcode.into()),
+            }),
+            Ok(_) => {}
+            Err(_) => invalid.push((item.id, raw)),
+        }
+    }
+
+    let aql = AqlQuery::builder()
+        .query(SET_BARCODES)
+        .bind_var("@collection", Album::collection_name())
+        .bind_var("items", serde_json::to_value(&items)?)
+        .build();
+    let updated: Option<u64> = engine.db().aql_query(aql).await?.pop();
+
+    engine
+        .ensure_unique_index(Album::collection_name(), "barcode")
+        .await?;
+
+    Ok(BarcodeMigration {
+        updated: updated.unwrap_or_default(),
+        invalid,
+    })
+}
+
+/// Moves the `foreign_key` of stored albums, artists and labels into their list
+/// of `external_ids`, returns the number of documents upgraded. Keys that aren't
+/// an external id are moved to `legacy_foreign_key` instead, as they are on read.
+/// Documents that have not been migrated still deserialize and are found by it.
+pub async fn external_ids(engine: &ArangoDb) -> Result<u64, EngineError> {
+    let mut upgraded = 0;
+    for collection in [
+        Album::collection_name(),
+        Artist::collection_name(),
+        Label::collection_name(),
+    ] {
+        let aql = AqlQuery::builder()
+            .query(FOREIGN_KEYS)
+            .bind_var("@collection", collection)
+            .build();
+        let stored: Vec<serde_json::Map<String, Value>> = engine.db().aql_query(aql).await?;
+        if stored.is_empty() {
+            continue;
+        }
+
+        let mut items = Vec::new();
+        for mut item in stored {
+            upgrade_foreign_key(&mut item)?;
+            items.push(Value::Object(item));
+        }
+        let aql = AqlQuery::builder()
+            .query(SET_EXTERNAL_IDS)
+            .bind_var("@collection", collection)
+            .bind_var("items", items)
+            .build();
+        let resp: Option<u64> = engine.db().aql_query(aql).await?.pop();
+        upgraded += resp.unwrap_or_default();
+    }
+    Ok(upgraded)
+}
+
+/// Writes the search key of stored albums and artists without one, or with one
+/// derived by an earlier version, returns the number of documents upgraded.
+/// Names stored lowercased by earlier versions are left lowercased.
+pub async fn search_keys(engine: &ArangoDb) -> Result<u64, EngineError> {
+    let mut upgraded = 0;
+    for collection in [Album::collection_name(), Artist::collection_name()] {
+        let aql = AqlQuery::builder()
+            .query(NAMES)
+            .bind_var("@collection", collection)
+            .build();
+        let stored: Vec<StoredName> = engine.db().aql_query(aql).await?;
+
+        let items: Vec<StoredName> = stored
+            .into_iter()
+            .filter_map(|item| {
+                let key = search_key(&item.name);
+                match item.search_key {
+                    Some(stored) if stored == key => None,
+                    _ => Some(StoredName {
+                        search_key: Some(key),
+                        ..item
+                    }),
+                }
+            })
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+
+        let aql = AqlQuery::builder()
+            .query(SET_SEARCH_KEYS)
+            .bind_var("@collection", collection)
+            .bind_var("items", serde_json::to_value(&items)?)
+            .build();
+        let resp: Option<u64> = engine.db().aql_query(aql).await?.pop();
+        upgraded += resp.unwrap_or_default();
+    }
+    Ok(upgraded)
+}
+
+/// Rewrites the stored documents of `T` written in an earlier version, a batch at a time.
+/// Documents are upgraded as they are read either way, rewriting them saves doing so on
+/// every read. Meant to be run in the background, documents changed or removed while it
+/// runs are left for the next run. Each document is replaced on its own so one the
+/// database refuses, i.e. breaking a unique index, is reported without stopping the rest.
+pub async fn upgrade_all<T: ReqModelTraits + Versioned>(
+    engine: &ArangoDb,
+) -> Result<UpgradeReport, EngineError> {
+    const BATCH: u32 = 500;
+
+    let mut report = UpgradeReport::default();
+    let mut after = String::new();
+    loop {
+        let aql = AqlQuery::builder()
+            .query(OUTDATED)
+            .bind_var("@collection", T::collection_name())
+            .bind_var("after", after.as_str())
+            .bind_var("version", T::VERSION)
+            .bind_var("batch", BATCH)
+            .build();
+        let stored: Vec<Value> = engine.db().aql_query(aql).await?;
+        after = match stored.last().and_then(|doc| doc["_key"].as_str()) {
+            Some(key) => key.to_string(),
+            None => break,
+        };
+
+        // Upgraded as stored, keeping its `_rev` so it is replaced only if unchanged since it
+        // was read and any attribute `T` doesn't know of
+        let mut docs = Vec::new();
+        for mut doc in stored {
+            let id = doc["_id"].as_str().unwrap_or_default().to_string();
+            if let Err(e) = upgrade::<T>(&mut doc) {
+                report.failed.push((id, e.to_string()));
+                continue;
+            }
+            match serde_json::from_value::<T>(doc.clone()) {
+                Ok(_) => docs.push((id, doc)),
+                Err(e) => report.failed.push((id, e.to_string())),
+            }
+        }
+
+        for (id, doc) in docs {
+            let aql = AqlQuery::builder()
+                .query(REPLACE_UNCHANGED)
+                .bind_var("@collection", T::collection_name())
+                .bind_var("doc", doc)
+                .build();
+            let replaced: Result<Vec<String>, EngineError> =
+                engine.db().aql_query(aql).await.map_err(EngineError::from);
+            match replaced {
+                Ok(_) => report.upgraded += 1,
+                Err(e) if e.is_not_found() || e.error_num() == Some(REVISION_CONFLICT) => {}
+                Err(e) => report.failed.push((id, e.to_string())),
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use arangors::AqlQuery;
+    use serde_json::{json, Value};
+
+    use crate::engine::db::arangodb::aql_snippet::{GET_DOCUMENT, INSERT};
+    use crate::engine::db::test::common;
+    use crate::engine::EngineError;
+    use crate::models::album::Album;
+    use crate::models::artist::Artist;
+    use crate::models::label::Label;
+    use crate::models::version::Versioned;
+    use crate::models::DocDetails;
+    use crate::service::label::create_label;
+    use crate::service::migrate::*;
+
+    type TestResult = Result<(), EngineError>;
+
+    #[tokio::test]
+    async fn keep_unknown_attributes() -> TestResult {
+        let db = common().await?;
+        let doc = json!({
+            "name": "Stored Artist",
+            "aliases": [],
+            "profile": "",
+            "foreign_key": "",
+            "notes": "written by hand",
+        });
+        let aql = AqlQuery::builder()
+            .query(INSERT)
+            .bind_var("@collection", Artist::collection_name())
+            .bind_var("doc", doc)
+            .build();
+        let stored: Option<Value> = db.db().aql_query(aql).await?.pop();
+        let id = stored.unwrap()["_id"].clone();
+
+        let report = upgrade_all::<Artist>(&db).await?;
+        assert!(report.failed.is_empty());
+
+        let aql = AqlQuery::builder()
+            .query(GET_DOCUMENT)
+            .bind_var("id", id)
+            .build();
+        let upgraded: Option<Value> = db.db().aql_query(aql).await?.pop();
+        let upgraded = upgraded.unwrap();
+        assert_eq!(upgraded["notes"], "written by hand");
+        assert_eq!(upgraded["schema_version"], Artist::VERSION);
+        assert!(upgraded.get("foreign_key").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn link_cat_no_by_search_key() -> TestResult {
+        let db = common().await?;
+        let mut label = Label::new();
+        label.name("Éditions Mego");
+        create_label(&db, label).await?;
+
+        let doc = json!({"name": "cat no album", "cat_no": "EDITIONS MÉGO - eMEGO 001"});
+        let aql = AqlQuery::builder()
+            .query(INSERT)
+            .bind_var("@collection", Album::collection_name())
+            .bind_var("doc", doc)
+            .build();
+        let stored: Option<Value> = db.db().aql_query(aql).await?.pop();
+        let id = stored.unwrap()["_id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        let report = cat_nos(&db).await?;
+        assert!(report.linked.contains(&id));
+        assert!(!report.unlinked.contains(&id));
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+//! Operations that read or write several models at once,
+//! writes are made in a single query or transaction so they either all apply or none do.
+pub mod backup;
+pub mod dedup;
+pub mod fuzzy;
+pub mod genre;
+pub mod inventory;
+pub mod label;
+pub mod ledger;
+pub mod migrate;
+pub mod pricing;
+pub mod tracklist;
+pub(crate) mod tree;
@@ -0,0 +1,188 @@
+//! Variant prices, their history and the value of the stock held.
+
+use std::collections::BTreeMap;
+
+use arangors::AqlQuery;
+
+use crate::engine::db::arangodb::aql_snippet::{ALL_STOCK, PRICE_HISTORY, SET_PRICES};
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::models::price_change::PriceChange;
+use crate::models::variant::Variant;
+use crate::models::DocDetails;
+use crate::money::{Currency, Money, MoneyError};
+use crate::service::inventory::{variants_of, VariantStock};
+
+/// Total value of the stock held in a single currency.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Valuation {
+    pub currency: Currency,
+    /// Copies whose selling price, or purchase price when unlisted, is in this currency
+    pub copies: u64,
+    /// Purchase price of every copy
+    pub at_cost: Money,
+    /// Selling price of every copy
+    pub at_retail: Money,
+}
+
+impl Valuation {
+    fn new(currency: Currency) -> Self {
+        Self {
+            currency,
+            copies: 0,
+            at_cost: Money::zero(currency),
+            at_retail: Money::zero(currency),
+        }
+    }
+}
+
+/// Updates the prices of a stored variant and records `change` in its price history.
+pub async fn set_prices(
+    engine: &ArangoDb,
+    variant_id: &str,
+    change: PriceChange,
+) -> Result<Variant, EngineError> {
+    let aql = set_prices_query(variant_id, &change)?;
+
+    let resp: Option<Variant> = engine.db().aql_query(aql).await?.pop();
+    if let Some(variant) = resp {
+        Ok(variant)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Query of `set_prices`, to be run along with other writes
+pub(crate) fn set_prices_query(
+    variant_id: &str,
+    change: &PriceChange,
+) -> Result<AqlQuery<'static>, EngineError> {
+    Ok(AqlQuery::builder()
+        .query(SET_PRICES)
+        .bind_var("@variant", Variant::collection_name())
+        .bind_var("@history", PriceChange::collection_name())
+        .bind_var("id", variant_id)
+        .bind_var("prices", serde_json::to_value(change.get_prices())?)
+        .bind_var("change", serde_json::to_value(change)?)
+        .build())
+}
+
+/// Every price a variant has been set to, oldest first.
+pub async fn price_history(
+    engine: &ArangoDb,
+    variant_id: &str,
+) -> Result<Vec<PriceChange>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(PRICE_HISTORY)
+        .bind_var("@history", PriceChange::collection_name())
+        .bind_var("id", variant_id)
+        .build();
+
+    let resp: Vec<PriceChange> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+/// Value of all stock held, one `Valuation` per currency.
+pub async fn inventory_valuation(engine: &ArangoDb) -> Result<Vec<Valuation>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(ALL_STOCK)
+        .bind_var("@variant", Variant::collection_name())
+        .build();
+
+    let stock: Vec<VariantStock> = engine.db().aql_query(aql).await?;
+    Ok(value_stock(&stock)?)
+}
+
+/// Value of the stock held of a single album, one `Valuation` per currency.
+pub async fn album_valuation(
+    engine: &ArangoDb,
+    album_id: &str,
+) -> Result<Vec<Valuation>, EngineError> {
+    let stock = variants_of(engine, album_id).await?;
+    Ok(value_stock(&stock)?)
+}
+
+/// Sums the value of the given stock using exact integer arithmetic,
+/// variants without prices are left out.
+pub fn value_stock(stock: &[VariantStock]) -> Result<Vec<Valuation>, MoneyError> {
+    let mut totals: BTreeMap<Currency, Valuation> = BTreeMap::new();
+
+    for item in stock {
+        let count = i64::from(item.count());
+        let prices = item.variant.get_prices();
+
+        if let Some(cost) = prices.purchase_price {
+            let total = totals
+                .entry(cost.currency())
+                .or_insert_with(|| Valuation::new(cost.currency()));
+            total.at_cost = total.at_cost.checked_add(cost.checked_mul(count)?)?;
+        }
+        if let Some(price) = prices.selling_price() {
+            let total = totals
+                .entry(price.currency())
+                .or_insert_with(|| Valuation::new(price.currency()));
+            total.at_retail = total.at_retail.checked_add(price.checked_mul(count)?)?;
+        }
+        if let Some(price) = prices.selling_price().or(prices.purchase_price) {
+            if let Some(total) = totals.get_mut(&price.currency()) {
+                total.copies += u64::from(item.count());
+            }
+        }
+    }
+
+    Ok(totals.into_values().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::inventory::Inventory;
+    use crate::models::variant::{Prices, Variant};
+    use crate::money::{Currency, Money};
+    use crate::service::inventory::VariantStock;
+    use crate::service::pricing::value_stock;
+
+    fn stock(count: u32, prices: Prices) -> VariantStock {
+        let mut variant = Variant::new();
+        variant.prices(prices);
+        let mut inventory = Inventory::new();
+        inventory.amount(count);
+        VariantStock { variant, inventory }
+    }
+
+    #[test]
+    fn test_value_stock() {
+        let gbp = |m| Some(Money::new(m, Currency::GBP));
+        let stock = vec![
+            stock(
+                3,
+                Prices {
+                    purchase_price: gbp(500),
+                    list_price: gbp(1299),
+                    sale_price: gbp(999),
+                },
+            ),
+            stock(
+                2,
+                Prices {
+                    purchase_price: gbp(250),
+                    list_price: Some(Money::new(1500, Currency::EUR)),
+                    sale_price: None,
+                },
+            ),
+            stock(7, Prices::default()),
+        ];
+
+        let totals = value_stock(&stock).unwrap();
+        assert_eq!(totals.len(), 2);
+
+        let eur = &totals[0];
+        assert_eq!(eur.currency, Currency::EUR);
+        assert_eq!(eur.copies, 2);
+        assert_eq!(eur.at_retail.amount(), 3000);
+
+        let pounds = &totals[1];
+        assert_eq!(pounds.copies, 3);
+        assert_eq!(pounds.at_cost.amount(), 2000);
+        assert_eq!(pounds.at_retail.amount(), 2997);
+    }
+}
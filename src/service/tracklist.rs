@@ -0,0 +1,135 @@
+//! Setting, reordering and searching album tracklists.
+
+use arangors::AqlQuery;
+use serde_json::Value;
+
+use crate::engine::db::arangodb::aql_snippet::{
+    ALBUMS_WITH_TRACK, GET_DOCUMENT, SET_TRACKLIST, SET_TRACKLIST_AT,
+};
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::models::album::Album;
+use crate::models::track::{self, Track};
+use crate::models::DocDetails;
+
+/// Replaces the tracklist of a stored album.
+pub async fn set_tracklist(
+    engine: &ArangoDb,
+    album_id: &str,
+    tracks: Vec<Track>,
+) -> Result<Album, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(SET_TRACKLIST)
+        .bind_var("@collection", Album::collection_name())
+        .bind_var("id", album_id)
+        .bind_var("tracklist", serde_json::to_value(&tracks)?)
+        .build();
+
+    let resp: Option<Album> = engine.db().aql_query(aql).await?.pop();
+    if let Some(album) = resp {
+        Ok(album)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Tracklist of a stored album in release order.
+pub async fn tracklist(engine: &ArangoDb, album_id: &str) -> Result<Vec<Track>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(GET_DOCUMENT)
+        .bind_var("id", album_id)
+        .build();
+
+    let resp: Vec<Option<Album>> = engine.db().aql_query(aql).await?;
+    match resp.into_iter().flatten().next() {
+        Some(album) => Ok(album.get_tracklist().to_vec()),
+        None => DbError::ItemNotFound.into(),
+    }
+}
+
+/// Reorders the tracklist of a stored album to follow `positions`,
+/// which must list every positioned track once. See `track::reorder`.
+/// Fails with `EngineError::Conflict` if the album changed while it was reordered.
+pub async fn reorder_tracklist(
+    engine: &ArangoDb,
+    album_id: &str,
+    positions: &[&str],
+) -> Result<Album, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(GET_DOCUMENT)
+        .bind_var("id", album_id)
+        .build();
+    let resp: Vec<Option<Value>> = engine.db().aql_query(aql).await?;
+    let stored = match resp.into_iter().flatten().next() {
+        Some(stored) => stored,
+        None => return DbError::ItemNotFound.into(),
+    };
+    let rev = stored["_rev"].clone();
+    let album: Album = serde_json::from_value(stored)?;
+    let tracks = track::reorder(album.get_tracklist().to_vec(), positions)?;
+
+    let aql = AqlQuery::builder()
+        .query(SET_TRACKLIST_AT)
+        .bind_var("@collection", Album::collection_name())
+        .bind_var("id", album_id)
+        .bind_var("rev", rev)
+        .bind_var("tracklist", serde_json::to_value(&tracks)?)
+        .build();
+    let resp: Option<Album> = engine.db().aql_query(aql).await?.pop();
+    if let Some(album) = resp {
+        Ok(album)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Albums with a track or sub track whose title contains `title`, ignoring case.
+pub async fn albums_with_track(engine: &ArangoDb, title: &str) -> Result<Vec<Album>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(ALBUMS_WITH_TRACK)
+        .bind_var("@collection", Album::collection_name())
+        .bind_var("title", title.trim().to_lowercase())
+        .build();
+
+    let resp: Vec<Album> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::db::test::common;
+    use crate::engine::EngineError;
+    use crate::io::EngineWrite;
+    use crate::models::album::Album;
+    use crate::models::track::Track;
+    use crate::models::DocDetails;
+    use crate::service::tracklist::*;
+
+    type TestResult = Result<(), EngineError>;
+
+    #[tokio::test]
+    async fn test_tracklist() -> TestResult {
+        let db = common().await?;
+        let mut album = Album::new();
+        album.name("tracklist album");
+        db.insert(album.clone()).await?;
+
+        let tracks = vec![
+            Track::new("A1", "Quiet Harbour").duration(180).clone(),
+            Track::new("B1", "Loud Shore").duration(240).clone(),
+        ];
+        let stored = set_tracklist(&db, &album.id(), tracks).await?;
+        assert_eq!(stored.running_time(), 420);
+
+        let stored = reorder_tracklist(&db, &album.id(), &["B1", "A1"]).await?;
+        assert_eq!(stored.get_tracklist()[0].get_position(), "B1");
+        let err = reorder_tracklist(&db, &album.id(), &["B1"])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, EngineError::Validation(_)));
+
+        let found = albums_with_track(&db, "quiet harbour").await?;
+        assert!(found.iter().any(|a| a.id() == album.id()));
+        Ok(())
+    }
+}
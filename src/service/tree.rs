@@ -0,0 +1,255 @@
+//! Documents arranged in a tree, such as labels and genres.
+//!
+//! Each document keeps the `_id` of its parent in a `parent` attribute, mirrored
+//! by an edge from the parent to the child in the `tree` edge collection so the
+//! hierarchy can be traversed.
+
+use arangors::AqlQuery;
+use serde_json::Value;
+
+use crate::engine::db::arangodb::aql_snippet::{
+    ANCESTORS, CLEAR_PARENT, CREATE_NODE, DESCENDANTS, DROP_DUPLICATE_LINKS, GET_DOCUMENT,
+    MERGE_CHECK, MOVE_LINKS, REMOVE_DOCUMENT, REMOVE_LINKS, REPARENT, SET_PARENT,
+};
+use crate::engine::db::ArangoDb;
+use crate::engine::{DbError, EngineError};
+use crate::models::edge::Direction;
+use crate::models::ReqModelTraits;
+
+/// Deepest hierarchy followed
+pub(crate) const MAX_DEPTH: u32 = 32;
+
+/// Stores a new document as `EngineWrite::insert` does, linking it under `parent` when given.
+/// Fails without writing anything if the parent doesn't exist.
+pub(crate) async fn create<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    tree: &str,
+    doc: T,
+    parent: Option<&str>,
+) -> Result<T, EngineError> {
+    let resp: Option<T> = engine
+        .insert_keyed(doc, |doc| {
+            AqlQuery::builder()
+                .query(CREATE_NODE)
+                .bind_var("@collection", T::collection_name())
+                .bind_var("@tree", tree)
+                .bind_var("parent", parent)
+                .bind_var("doc", doc)
+                .build()
+        })
+        .await?
+        .pop();
+    if let Some(doc) = resp {
+        Ok(doc)
+    } else {
+        DbError::FailedToCreate.into()
+    }
+}
+
+/// Moves a stored document under `parent`, or to the top of the tree when `None`.
+/// Fails if either document is missing or the move would create a cycle.
+pub(crate) async fn set_parent<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    tree: &str,
+    id: &str,
+    parent: Option<&str>,
+) -> Result<T, EngineError> {
+    let aql = match parent {
+        Some(parent) => AqlQuery::builder()
+            .query(SET_PARENT)
+            .bind_var("@collection", T::collection_name())
+            .bind_var("@tree", tree)
+            .bind_var("id", id)
+            .bind_var("parent", parent)
+            .bind_var("depth", MAX_DEPTH)
+            .build(),
+        None => AqlQuery::builder()
+            .query(CLEAR_PARENT)
+            .bind_var("@collection", T::collection_name())
+            .bind_var("@tree", tree)
+            .bind_var("id", id)
+            .build(),
+    };
+
+    let resp: Option<T> = engine.db().aql_query(aql).await?.pop();
+    if let Some(doc) = resp {
+        Ok(doc)
+    } else {
+        DbError::ItemNotFound.into()
+    }
+}
+
+/// Every document below `id`, nearest first.
+pub(crate) async fn descendants<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    tree: &str,
+    id: &str,
+) -> Result<Vec<T>, EngineError> {
+    traverse(engine, DESCENDANTS, tree, id).await
+}
+
+/// The parent of `id`, its parent and so on up to the top of the tree.
+pub(crate) async fn ancestors<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    tree: &str,
+    id: &str,
+) -> Result<Vec<T>, EngineError> {
+    traverse(engine, ANCESTORS, tree, id).await
+}
+
+async fn traverse<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    query: &str,
+    tree: &str,
+    id: &str,
+) -> Result<Vec<T>, EngineError> {
+    let aql = AqlQuery::builder()
+        .query(query)
+        .bind_var("@tree", tree)
+        .bind_var("id", id)
+        .bind_var("depth", MAX_DEPTH)
+        .build();
+
+    let resp: Vec<T> = engine.db().aql_query(aql).await?;
+    Ok(resp)
+}
+
+/// Queries moving the links of `remove` in the `collection` edge collection to `keep`,
+/// dropping any `keep` already has. `Direction` is that of the edges as seen from the
+/// merged documents.
+pub(crate) fn move_links(
+    collection: &str,
+    direction: Direction,
+    keep: &str,
+    remove: &str,
+) -> Vec<AqlQuery<'static>> {
+    [DROP_DUPLICATE_LINKS, MOVE_LINKS]
+        .iter()
+        .map(|query| edge_query(query, collection, direction, keep, remove))
+        .collect()
+}
+
+fn edge_query(
+    query: &'static str,
+    collection: &str,
+    direction: Direction,
+    keep: &str,
+    remove: &str,
+) -> AqlQuery<'static> {
+    let (vertex, other) = match direction {
+        Direction::Outbound => ("_from", "_to"),
+        Direction::Inbound => ("_to", "_from"),
+    };
+    AqlQuery::builder()
+        .query(query)
+        .bind_var("@collection", collection.to_string())
+        .bind_var("vertex", vertex)
+        .bind_var("other", other)
+        .bind_var("keep", keep)
+        .bind_var("remove", remove)
+        .build()
+}
+
+/// Merges `remove` into `keep` in a single transaction: links to `remove` in each of
+/// the `links` edge collections are moved to `keep`, dropping any `keep` already has,
+/// the children of `remove` are moved under `keep` and `remove` is deleted.
+/// `Direction` is that of the edges as seen from the merged documents.
+pub(crate) async fn merge<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    tree: &str,
+    links: &[(&str, Direction)],
+    keep: &str,
+    remove: &str,
+) -> Result<T, EngineError> {
+    let mut queries = vec![AqlQuery::builder()
+        .query(MERGE_CHECK)
+        .bind_var("@tree", tree)
+        .bind_var("keep", keep)
+        .bind_var("remove", remove)
+        .bind_var("depth", MAX_DEPTH)
+        .build()];
+    for (collection, direction) in links {
+        queries.extend(move_links(collection, *direction, keep, remove));
+    }
+    queries.push(edge_query(
+        REMOVE_LINKS,
+        tree,
+        Direction::Inbound,
+        keep,
+        remove,
+    ));
+    queries.push(edge_query(
+        MOVE_LINKS,
+        tree,
+        Direction::Outbound,
+        keep,
+        remove,
+    ));
+    queries.push(
+        AqlQuery::builder()
+            .query(REPARENT)
+            .bind_var("@collection", T::collection_name())
+            .bind_var("keep", keep)
+            .bind_var("remove", remove)
+            .build(),
+    );
+    queries.push(
+        AqlQuery::builder()
+            .query(REMOVE_DOCUMENT)
+            .bind_var("@collection", T::collection_name())
+            .bind_var("id", remove)
+            .build(),
+    );
+
+    let mut write: Vec<String> = links.iter().map(|(c, _)| c.to_string()).collect();
+    write.push(tree.to_string());
+    write.push(T::collection_name().to_string());
+
+    in_transaction(engine, write, queries, keep).await
+}
+
+/// Runs the `queries` in order in one transaction writing to the `write` collections,
+/// returning the document `id` as it is once they have all run.
+/// Nothing is written if any query fails, the error of the query is returned
+/// even if aborting the transaction fails too.
+pub(crate) async fn in_transaction<T: ReqModelTraits>(
+    engine: &ArangoDb,
+    write: Vec<String>,
+    queries: Vec<AqlQuery<'_>>,
+    id: &str,
+) -> Result<T, EngineError> {
+    let tx = engine.begin_transaction(write).await?;
+
+    for aql in queries {
+        if let Err(e) = tx.aql_query::<Value>(aql).await {
+            if let Err(abort) = tx.abort().await {
+                log::error!("failed to abort the transaction writing {}: {}", id, abort);
+            }
+            return Err(e.into());
+        }
+    }
+    let result = tx
+        .aql_query::<Option<T>>(
+            AqlQuery::builder()
+                .query(GET_DOCUMENT)
+                .bind_var("id", id)
+                .build(),
+        )
+        .await;
+
+    match result {
+        Ok(resp) => {
+            tx.commit().await?;
+            match resp.into_iter().flatten().next() {
+                Some(doc) => Ok(doc),
+                None => DbError::ItemNotFound.into(),
+            }
+        }
+        Err(e) => {
+            if let Err(abort) = tx.abort().await {
+                log::error!("failed to abort the transaction writing {}: {}", id, abort);
+            }
+            Err(e.into())
+        }
+    }
+}
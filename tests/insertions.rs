@@ -5,6 +5,7 @@ mod test_generics {
     use discuits_api::engine::db::DbBasics;
     use discuits_api::io::{delete::EngineDelete, read::EngineGet, Write};
     use discuits_api::models::BoxedDoc;
+    use discuits_api::models::id::DocKey;
     use discuits_api::models::{album::*, artist::*, edge::*, DocDetails};
     use discuits_api::{insert_many, one_to_many};
 
@@ -53,6 +54,37 @@ mod test_generics {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn link_reports_missing_child() -> SimpleResult {
+        let session = with_arangodb().await?;
+        let db = session.get_ref().db().read().await;
+
+        let mut album = Album::new();
+        album.name("linked album");
+        let album = db.insert(album).await?;
+
+        let mut artist = Artist::new();
+        artist.name("linked artist");
+        let artist = db.insert(artist).await?;
+
+        let mut linker = Edge::linker(&db, "artist_to");
+        linker.direction(Direction::Inbound).role(Role::PrimaryArtist);
+
+        let children = vec![
+            artist.0.clone(),
+            artist.0.clone(),
+            DocKey::<Artist>::new("missing")?.id(),
+        ];
+        let resp = linker.link(&album.0, &children).await?;
+        assert_eq!(resp.len(), 2);
+        assert!(resp[0].is_ok());
+        assert!(matches!(resp[1].outcome, Err(LinkError::ChildNotFound)));
+
+        let removed = linker.unlink(&album.0, &[artist.0]).await?;
+        assert_eq!(removed.len(), 1);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn remove_an_element() -> SimpleResult {
         let seesion = with_arangodb().await?;